@@ -2,50 +2,226 @@
 // Records to WAV format (16-bit PCM, 48 kHz mono)
 // Python backend will transcode to OGG/Opus for transmission
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, SampleFormat};
-use std::io::{self, Write, BufWriter};
-use std::path::PathBuf;
+mod convert;
+mod crypto;
+mod devices;
+mod dsp;
+mod error;
+mod events;
+mod exclusive;
+mod fanout;
+mod fingerprint;
+mod input_test;
+mod latency;
+mod live_pcm;
+mod manifest;
+mod mapped;
+mod monitor;
+mod naming;
+mod os_volume;
+mod peaks;
+mod playback;
+mod pool;
+mod presets;
+mod queue;
+mod raw_capture;
+mod resample;
+mod self_test;
+mod sent;
+mod spectral;
+mod spectrogram;
+mod stream;
+mod tools;
+mod wav;
+
+use cpal::traits::DeviceTrait;
+use cpal::Device;
+use convert::ToI16;
+use dsp::{Compressor, CompressorConfig, SilenceThreshold, SpeakingDetector};
+use crypto::EncryptionKey;
+use devices::AudioDeviceInfo;
+use error::RecorderError;
+use fanout::{ExtraOutputFile, FanOut};
+use input_test::{ComparisonResult, InputTestResult};
+use os_volume::InputVolume;
+use latency::LatencyMeasurement;
+use manifest::{DeviceManifest, RecordingManifest};
+use peaks::{Peaks, PeaksBuilder};
+use playback::PlaybackInfo;
+use presets::PresetInfo;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::sync::mpsc::{self, Receiver, Sender, RecvTimeoutError};
-use std::thread;
-use byteorder::{LittleEndian, WriteBytesExt};
+use std::time::{Duration, Instant};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use queue::{FrameReceiver, FrameSender};
+use resample::{ResampleInfo, ResamplerQuality, ResamplerState};
+use self_test::SelfTestReport;
+use sent::SendFilter;
+use spectral::{NoiseProfile, NoiseProfileInfo, SpectralSubtractor};
+use spectrogram::SpectrogramInfo;
+use stream::InputStreamHandle;
+use std::thread::{self, JoinHandle};
+use tools::{
+    BatchNormalizeEntry, ConcatResult, DiffReport, DownmixResult, EnsureFormatResult, FitResult, NormalizeResult,
+    OutputSpec, PrependResult, RecordingEntry, RequantizeResult, SampleRateFix, SegmentResult, TranscodeResult,
+    WavValidation,
+};
+use wav::{AudioContainer, WavMetadata, WavReader, WavWriter};
 
 // Recording configuration matching Telegram voice messages
 const TELEGRAM_SAMPLE_RATE: u32 = 48000;  // 48 kHz (Telegram standard)
 const TELEGRAM_CHANNELS: u8 = 1;          // Mono (voice doesn't need stereo)
-const FRAME_SIZE_MS: u32 = 20;            // 20ms frames
-const FRAME_SIZE_SAMPLES: usize = (TELEGRAM_SAMPLE_RATE as usize * FRAME_SIZE_MS as usize) / 1000; // 960 samples at 48kHz
+const FRAME_SIZE_MS: u32 = 20;            // 20ms frames (960 samples at 48kHz)
+const MIN_SAMPLE_RATE: u32 = 8000;
+const MAX_SAMPLE_RATE: u32 = 192000;
+const MAX_CHANNELS: u16 = 2;
+const DEFAULT_MIN_DURATION_MS: u64 = 300;  // Shorter taps are treated as accidental
+const DEFAULT_QUEUE_CAPACITY_FRAMES: usize = 250;  // 5 seconds of 20ms frames (~480 KB)
+const WAV_HEADER_BYTES: u64 = 44;
+const NO_STOP_POINT: u64 = u64::MAX;
+
+// Bumped by every start; an encoder from an earlier start that is still
+// finishing must not emit events or touch state for the current session
+static RECORDING_EPOCH: AtomicU64 = AtomicU64::new(0);
+const LEVEL_HISTORY_FRAMES: usize = 500;  // 10 seconds of 20ms frames
+const LEVEL_HISTORY_BYTES: u64 = LEVEL_HISTORY_FRAMES as u64 * 4;
+const MIN_BUDGET_QUEUE_FRAMES: u64 = 25;  // 500 ms; a smaller `max_buffer_bytes` refuses to start
+const DEFAULT_EVENT_RATE_HZ: u32 = 20;
+const MAX_EVENT_RATE_HZ: u32 = 1000 / FRAME_SIZE_MS; // One event per frame
+const SPEAKING_HOLD_MS: u32 = 300;  // Quiet time before `recording-speaking` turns false
+const LIKELY_MUTED_DBFS: f32 = -70.0;  // Peak below this for the whole file: a muted mic, not a quiet room
+const MUTE_RAMP_MS: u32 = 10;       // Fade for mute/unmute, short enough to feel instant
+// `clip_protection`: a frame clips with this many full-scale samples, and
+// 5 such frames within 500 ms lower the gain 3 dB, down to at most -12 dB
+const CLIP_SAMPLES_PER_FRAME: usize = 4;
+const CLIP_WINDOW_FRAMES: u32 = 25;
+const CLIP_SUSTAIN_FRAMES: u32 = 5;
+const CLIP_GAIN_STEP_DB: f32 = 3.0;
+const MIN_CLIP_GAIN_DB: f32 = -12.0;
+const GAIN_RAMP_MS: u32 = 50;       // Glide for each gain step, so it doesn't click
+const BEEP_DBFS: f32 = -12.0;       // Clearly audible without startling
+const MAX_BEEP_MS: u64 = 5000;
+const MAX_IGNORE_MS: u64 = 1000;    // Longest `ignore_start_ms`/`ignore_stop_ms`
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+const MMAP_MAX_PREALLOC_SECONDS: u64 = 3600;  // Longer `mmap_output` recordings grow the file as they go
+const RESUME_POLL_INTERVAL: Duration = Duration::from_millis(500); // Reopen attempts after an interruption
+// Upper bound for voice Opus (the backend's send format); typical is ~32 kbps
+const OPUS_MAX_BITRATE_PER_CHANNEL: u64 = 64_000;
 
 // Audio samples sent from cpal callback to encoder thread
 #[derive(Debug)]
 enum AudioSample {
     Data(Vec<i16>),
+    Flush { fsync: bool, reply: mpsc::Sender<Result<u64, String>> },
     Stop,
 }
 
+// Live per-session values written by the audio threads and read by commands.
+// Kept as atomics so the UI can poll them without contending with capture.
+struct SessionMeters {
+    level: AtomicU32,           // f32 bits: RMS of the last frame, 0.0-1.0
+    samples_written: AtomicU64, // Samples (per channel) handed to the WAV writer
+    xruns: AtomicU64,           // Frames lost because the encoder fell behind
+    paused: AtomicBool,
+    muted: AtomicBool,
+    stop_at: AtomicU64,         // Samples (per channel) to keep, from `request_stop_at`
+    level_history: Mutex<VecDeque<f32>>, // Recent per-frame levels, oldest first
+    callback_us: AtomicU64,     // Audio in the latest device callback
+    device_latency_us: AtomicU64, // Capture-to-callback delay from cpal; 0 if unknown
+    interrupted: AtomicBool,    // The device went away; waiting to reopen it
+    stream_generation: AtomicU64, // Bumped each time capture resumes on a reopened stream
+    clipped_samples: AtomicU64, // Full-scale samples written so far
+    gain_db: AtomicU32,         // f32 bits: software gain, lowered by `clip_protection`
+    queued_frames: AtomicU64,   // Captured, not yet taken by the processing thread
+    resampler_lookahead: AtomicU64, // Output frames the resampler holds back; 0 once downgraded
+    raw_overruns: Arc<AtomicU64>, // Callbacks missing from the raw capture; shared with its pools
+}
+
+impl SessionMeters {
+    fn new() -> Self {
+        Self {
+            level: AtomicU32::new(0),
+            samples_written: AtomicU64::new(0),
+            xruns: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            muted: AtomicBool::new(false),
+            stop_at: AtomicU64::new(NO_STOP_POINT),
+            level_history: Mutex::new(VecDeque::with_capacity(LEVEL_HISTORY_FRAMES)),
+            callback_us: AtomicU64::new(0),
+            device_latency_us: AtomicU64::new(0),
+            interrupted: AtomicBool::new(false),
+            stream_generation: AtomicU64::new(0),
+            clipped_samples: AtomicU64::new(0),
+            gain_db: AtomicU32::new(0.0f32.to_bits()),
+            queued_frames: AtomicU64::new(0),
+            resampler_lookahead: AtomicU64::new(0),
+            raw_overruns: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    // Called once per frame, so the history stays at frame resolution
+    fn set_level(&self, level: f32) {
+        self.level.store(level.to_bits(), Ordering::Relaxed);
+        let mut history = self.level_history.lock().unwrap();
+        if history.len() == LEVEL_HISTORY_FRAMES {
+            history.pop_front();
+        }
+        history.push_back(level);
+    }
+}
+
 // Recording state shared across commands
 struct RecordingState {
     is_recording: bool,
+    is_starting: bool, // A `start_recording` is setting up its device (see `StartingGuard`)
     output_path: Option<PathBuf>,
     sample_rate: Option<u32>,
     channels: Option<u16>,
-    sample_tx: Option<Sender<AudioSample>>,
+    sample_tx: Option<FrameSender<AudioSample>>,
+    encoder_handle: Option<JoinHandle<Result<RecordingFinalized, RecorderError>>>,
+    input_stream: Option<InputStreamHandle>,
+    reopen: Option<ReopenStream>, // Replaces `input_stream` after an interruption
+    session_id: Option<String>,
+    device_name: Option<String>,
+    meters: Option<Arc<SessionMeters>>,
+    config: RecordingConfig,
+    sample_limit: Option<u64>, // Samples (per channel) at which the encoder stops; `None` runs until stopped
+    noise_profile: Option<Arc<NoiseProfile>>, // Kept across recordings until recaptured
 }
 
 impl RecordingState {
     fn new() -> Self {
         Self {
             is_recording: false,
+            is_starting: false,
             output_path: None,
             sample_rate: None,
             channels: None,
             sample_tx: None,
+            encoder_handle: None,
+            input_stream: None,
+            reopen: None,
+            session_id: None,
+            device_name: None,
+            meters: None,
+            config: RecordingConfig::default(),
+            sample_limit: None,
+            noise_profile: None,
         }
     }
 }
 
+// Opens the recording's device again with the same settings, feeding the
+// same processing thread, so the encoder never sees the gap. Shared so the
+// resume thread can call it without holding the state lock.
+type ReopenStream = Arc<dyn Fn() -> Result<InputStreamHandle, String> + Send + Sync>;
+
 // Global recording state (using Arc<Mutex<>> for thread safety)
 type GlobalState = Arc<Mutex<RecordingState>>;
 
@@ -56,11 +232,453 @@ fn get_global_state() -> GlobalState {
         .clone()
 }
 
+/// Container/codec of a recording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    #[default]
+    Wav,
+    Opus,
+    Flac,
+}
+
+impl RecordingFormat {
+    /// `PartialFrame` used when the config leaves it unset: Opus packets are
+    /// whole frames, the rest can end on any sample
+    pub fn default_partial_frame(self) -> PartialFrame {
+        match self {
+            RecordingFormat::Opus => PartialFrame::Pad,
+            RecordingFormat::Wav | RecordingFormat::Flac => PartialFrame::WritePartial,
+        }
+    }
+}
+
+/// What stopping does with audio that doesn't fill a whole 20 ms frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialFrame {
+    Pad,          // Fill the frame with silence, keeping the length frame-aligned
+    Truncate,     // Drop it; the file ends on the last whole frame
+    WritePartial, // Write it as captured, sample-accurate
+}
+
+/// One more copy of a recording, in another format or container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ExtraOutput {
+    pub format: RecordingFormat,
+    pub container: AudioContainer,
+}
+
+impl Default for ExtraOutput {
+    fn default() -> Self {
+        Self { format: RecordingFormat::Wav, container: AudioContainer::Aiff }
+    }
+}
+
+/// Formats this build can record. Opus and FLAC are listed in
+/// `RecordingFormat` for sizing and the frontend's transcode step, but
+/// no encoder for them is compiled in.
+pub fn supported_formats() -> Vec<RecordingFormat> {
+    vec![RecordingFormat::Wav]
+}
+
+/// Containers available for `RecordingFormat::Wav` recordings
+pub fn supported_containers() -> Vec<AudioContainer> {
+    vec![AudioContainer::Wav, AudioContainer::Aiff]
+}
+
+/// Optional recording settings; every field defaults to the voice-message behavior
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub format: RecordingFormat,
+    /// File framing for WAV-format recordings: RIFF `.wav` or AIFF `.aif`
+    pub container: AudioContainer,
+    pub sample_rate: u32,
+    /// 1 (mono downmix) or 2 (the device's first two channels)
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    /// Produce a clip of exactly this length: shorter takes are padded with
+    /// trailing silence when recording stops
+    pub fixed_duration_seconds: Option<f64>,
+    /// With a fixed duration, auto-stop at the target and cut the overshoot.
+    /// Without it, a take longer than the target is rejected on stop.
+    pub allow_truncate: bool,
+    /// Stopping before this much audio was captured discards the recording
+    /// and returns `RecorderError::TooShort` (push-to-talk accidental taps)
+    pub min_duration_ms: u64,
+    /// Level below which audio counts as silence (dBFS or linear)
+    pub silence_threshold: SilenceThreshold,
+    /// Record the device's native rate and channel layout untouched (no
+    /// resampling or downmix); `sample_rate`/`channels` are ignored. Samples
+    /// are still stored as 16-bit PCM.
+    pub passthrough: bool,
+    /// Write a `.peaks.json` waveform sidecar next to the recording
+    pub write_peaks: bool,
+    /// Write a `.manifest.json` next to the recording: device, negotiated
+    /// format, this config, app version and session id (see `manifest`)
+    pub write_manifest: bool,
+    /// Per-device-channel gains for the mono downmix; `None` averages all
+    /// channels equally. Must have one entry per device input channel.
+    pub channel_weights: Option<Vec<f32>>,
+    /// Record mono from this device channel alone (0-based) instead of
+    /// mixing, e.g. the mic on input 1 of a two-input interface
+    pub source_channel: Option<usize>,
+    /// Frames the encoder may fall behind before the oldest are dropped
+    /// (each drop counts as an xrun). Bounds memory if the disk stalls.
+    pub queue_capacity_frames: usize,
+    /// Subtract the profile from `capture_noise_profile` (adds ~11 ms latency)
+    pub spectral_subtract: bool,
+    /// When more channels are requested than the device has, duplicate its
+    /// channels to fill them instead of failing to start
+    pub channel_upmix: bool,
+    /// Sync the file to disk this often while recording, so a crash loses at
+    /// most this much audio. Off by default (costs I/O); stop always syncs.
+    pub fsync_interval_ms: Option<u64>,
+    /// Mid-side width for stereo recordings: 0 = mono, 1 = unchanged (the
+    /// default), up to 2 = wider. Ignored for mono.
+    pub stereo_width: f32,
+    /// Replace an existing file at the output path instead of failing
+    pub overwrite: bool,
+    /// Save into a `YYYY-MM-DD` folder (local date) under the output dir
+    pub subdir_by_date: bool,
+    /// On stop, cut trailing audio below `silence_threshold`
+    pub trim_silence: bool,
+    /// On stop, fade out over this long at the end (after any trim).
+    /// Neither tail edit applies to fixed-duration clips.
+    pub fade_out_ms: u64,
+    /// Ask the OS to run the processing and encoder threads at high priority
+    /// to avoid xruns under load. Needs no special permissions to attempt;
+    /// where elevation is refused, recording continues at normal priority.
+    pub high_priority: bool,
+    /// Downward compression to even out loud and soft speech; off when `None`
+    pub compressor: Option<CompressorConfig>,
+    /// File name without extension; see `naming` for the placeholders
+    pub filename_template: String,
+    /// Title/artist/comment/date tags stored in the file (WAV container only)
+    pub metadata: Option<WavMetadata>,
+    /// Most `recording-level` events per second (1-50). Drops to 2/s while
+    /// the app window is hidden; the final event on stop always fires.
+    pub event_rate_hz: u32,
+    /// Write a `.wav.enc` sealed with this key (64 hex characters) instead
+    /// of plain WAV; see `crypto` for the format. Never written back out, so
+    /// saved presets don't keep it. Peaks, silence trimming and fade-out
+    /// need the plaintext on disk and can't be combined with it.
+    #[serde(skip_serializing)]
+    pub encryption_key: Option<EncryptionKey>,
+    /// Make the file a pure function of the captured samples and this
+    /// config. The encoder queue then waits instead of dropping frames when
+    /// the disk stalls (the backlog grows in memory instead of counting
+    /// xruns), and encryption, whose nonce is random, is refused. Nothing
+    /// else depends on timing: frames are cut at 20 ms whatever the device
+    /// callback size, resampling, noise subtraction and compression give the
+    /// same output however their input is chunked, and no stage dithers.
+    pub deterministic: bool,
+    /// `recording-speaking` turns true when a frame reaches this level...
+    pub speaking_open: SilenceThreshold,
+    /// ...and false once the level stays below this (lower) one for 300 ms
+    pub speaking_close: SilenceThreshold,
+    /// Write a tone before the captured audio, e.g. to mark a call
+    /// recording for consent. Not counted toward `min_duration_ms`.
+    pub prepend_beep: bool,
+    /// Write the same tone after the captured audio
+    pub append_beep: bool,
+    pub beep_hz: f32,
+    pub beep_ms: u64,
+    /// Input device id or name from `list_input_devices`; `None` records
+    /// from the system default
+    pub input_device: Option<String>,
+    /// If `input_device` exists but fails to open (e.g. held exclusively by
+    /// another app), record from the default device and emit
+    /// `device-fallback` instead of failing. A missing device still fails.
+    pub fallback_to_default: bool,
+    /// Handling of the last, incomplete frame on stop; `None` picks
+    /// `RecordingFormat::default_partial_frame`. A stop point from
+    /// `request_stop_at` and fixed-duration clips always end exactly.
+    pub partial_frame: Option<PartialFrame>,
+    /// Write through a memory map of a file preallocated for
+    /// `max_duration_seconds` (up to an hour), trading a larger file on
+    /// disk during recording for no write syscalls. Not for encrypted
+    /// recordings.
+    pub mmap_output: bool,
+    /// Drop this much captured audio from the start, e.g. the click of the
+    /// record button on a built-in mic. Unlike a fade nothing of it is kept.
+    pub ignore_start_ms: u64,
+    /// Drop this much from the end, counted back from the stop point when
+    /// `request_stop_at` sets one; trim and fade-out then apply to the new end
+    pub ignore_stop_ms: u64,
+    /// Debug: also write exactly what the device delivers, in its native
+    /// format and before any processing, to `<name>.raw.wav` (see
+    /// `raw_capture`). Not for encrypted recordings. Callbacks the writer
+    /// can't keep up with are dropped from the copy and counted in
+    /// `raw_capture_overruns`.
+    pub capture_raw: bool,
+    /// When the recording keeps clipping, lower the software gain a step at
+    /// a time (never raising it again) and emit `gain-reduced`. This stops
+    /// the compressor and stereo width from pushing a loud source further
+    /// into clipping; what the device itself clipped stays clipped, so
+    /// lower the input volume for that.
+    pub clip_protection: bool,
+    /// More copies written from the same frames as the main file, each on
+    /// its own thread, e.g. an AIFF next to the WAV. They take the main
+    /// file's name with their own extension, get the same trims and fades,
+    /// and are listed in `recording-finalized`. Limited to
+    /// `supported_formats`, in containers other than `container`.
+    pub extra_outputs: Vec<ExtraOutput>,
+    /// Ceiling on audio held in memory at once. The encoder queue shrinks
+    /// to fit (down to 500 ms, below which the recording won't start), and
+    /// trims, fades and peaks for a longer file work on it in place rather
+    /// than loading it. `extra_outputs`, which buffers without bound, is
+    /// refused. `None` sets no ceiling.
+    pub max_buffer_bytes: Option<u64>,
+    /// Ask for exclusive use of the device (WASAPI exclusive mode), so no
+    /// other app shares or resamples it. The device is opened in the first
+    /// format it takes as is, nearest `sample_rate` and `channels` (see
+    /// `exclusive`); the start fails if another app holds it or Windows
+    /// won't allow it, and with `RecorderError::Unsupported` on other
+    /// platforms.
+    pub exclusive_mode: bool,
+    /// How the device rate is converted to `sample_rate`, when they differ:
+    /// `fast` (linear) or `high` (windowed sinc; see `resample`)
+    pub resampler_quality: ResamplerQuality,
+    /// With the `high` resampler, switch to `fast` mid-recording once the
+    /// processing thread keeps falling further behind the device (see
+    /// `stream::BacklogMonitor`), and emit `resampler-downgraded`. The two
+    /// are crossfaded, so the switch is inaudible. Without it a machine
+    /// too slow for `high` lets the delay grow for as long as it records.
+    pub auto_downgrade_resampler: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            format: RecordingFormat::Wav,
+            container: AudioContainer::Wav,
+            sample_rate: TELEGRAM_SAMPLE_RATE,
+            channels: TELEGRAM_CHANNELS as u16,
+            bits_per_sample: 16,
+            fixed_duration_seconds: None,
+            allow_truncate: false,
+            min_duration_ms: DEFAULT_MIN_DURATION_MS,
+            silence_threshold: SilenceThreshold::default(),
+            passthrough: false,
+            write_peaks: false,
+            write_manifest: false,
+            channel_weights: None,
+            source_channel: None,
+            queue_capacity_frames: DEFAULT_QUEUE_CAPACITY_FRAMES,
+            spectral_subtract: false,
+            channel_upmix: false,
+            fsync_interval_ms: None,
+            stereo_width: 1.0,
+            overwrite: false,
+            subdir_by_date: false,
+            trim_silence: false,
+            fade_out_ms: 0,
+            high_priority: false,
+            compressor: None,
+            filename_template: naming::DEFAULT_TEMPLATE.to_string(),
+            metadata: None,
+            event_rate_hz: DEFAULT_EVENT_RATE_HZ,
+            encryption_key: None,
+            deterministic: false,
+            speaking_open: SilenceThreshold::Dbfs(-40.0),
+            speaking_close: SilenceThreshold::Dbfs(-48.0),
+            prepend_beep: false,
+            append_beep: false,
+            beep_hz: 1000.0,
+            beep_ms: 250,
+            input_device: None,
+            fallback_to_default: false,
+            partial_frame: None,
+            mmap_output: false,
+            ignore_start_ms: 0,
+            ignore_stop_ms: 0,
+            capture_raw: false,
+            clip_protection: false,
+            extra_outputs: Vec::new(),
+            max_buffer_bytes: None,
+            exclusive_mode: false,
+            resampler_quality: ResamplerQuality::Fast,
+            auto_downgrade_resampler: false,
+        }
+    }
+}
+
+impl RecordingConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !supported_formats().contains(&self.format) {
+            return Err(format!("{:?} recording is not supported; record WAV and transcode", self.format));
+        }
+        for (i, extra) in self.extra_outputs.iter().enumerate() {
+            if !supported_formats().contains(&extra.format) {
+                return Err(format!("{:?} output is not supported; record WAV and transcode", extra.format));
+            }
+            // Copies are named by extension, so each container appears once
+            let repeated = self.extra_outputs[..i].iter().any(|e| e.container == extra.container);
+            if extra.container == self.container || repeated {
+                return Err(format!("extra_outputs lists the {:?} container more than once", extra.container));
+            }
+        }
+        if !self.passthrough {
+            if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&self.sample_rate) {
+                return Err(format!("Unsupported sample rate: {} Hz", self.sample_rate));
+            }
+            if !(1..=MAX_CHANNELS).contains(&self.channels) {
+                return Err(format!("Unsupported channel count: {}", self.channels));
+            }
+            if self.channel_weights.is_some() && self.channels != 1 {
+                return Err("channel_weights only apply to mono recordings".to_string());
+            }
+            if self.source_channel.is_some() && self.channels != 1 {
+                return Err("source_channel only applies to mono recordings".to_string());
+            }
+            if self.source_channel.is_some() && self.channel_weights.is_some() {
+                return Err("source_channel and channel_weights can't be combined".to_string());
+            }
+        }
+        if self.bits_per_sample != 16 {
+            return Err(format!("Unsupported bit depth: {}", self.bits_per_sample));
+        }
+        if let Some(seconds) = self.fixed_duration_seconds {
+            if !seconds.is_finite() || seconds <= 0.0 {
+                return Err(format!("Invalid fixed duration: {} seconds", seconds));
+            }
+        }
+        if let Some(weights) = &self.channel_weights {
+            if weights.iter().any(|w| !w.is_finite()) {
+                return Err("channel_weights must be finite numbers".to_string());
+            }
+        }
+        if !(0.0..=dsp::MAX_STEREO_WIDTH).contains(&self.stereo_width) {
+            return Err(format!(
+                "stereo_width must be between 0 and {}, got {}",
+                dsp::MAX_STEREO_WIDTH, self.stereo_width
+            ));
+        }
+        if self.metadata.as_ref().is_some_and(|m| m.chunk_bytes() > 0) && self.container != AudioContainer::Wav {
+            return Err("Metadata tags are only supported in the WAV container".to_string());
+        }
+        if self.encryption_key.is_some() {
+            if self.deterministic {
+                return Err("Encrypted recordings can't be deterministic (each file gets a random nonce)".to_string());
+            }
+            if self.container != AudioContainer::Wav {
+                return Err("Encrypted recordings use the WAV container".to_string());
+            }
+            if self.write_peaks || self.trim_silence || self.fade_out_ms > 0 {
+                return Err("write_peaks, trim_silence and fade_out_ms are not available for encrypted recordings".to_string());
+            }
+            if self.write_manifest {
+                return Err("write_manifest would describe an encrypted recording in plaintext".to_string());
+            }
+            if self.mmap_output {
+                return Err("mmap_output is not available for encrypted recordings".to_string());
+            }
+            if self.capture_raw {
+                return Err("capture_raw would write an encrypted recording's audio in plaintext".to_string());
+            }
+            if !self.extra_outputs.is_empty() {
+                return Err("extra_outputs would write an encrypted recording's audio in plaintext".to_string());
+            }
+        }
+        if self.speaking_close.to_linear() > self.speaking_open.to_linear() {
+            return Err("speaking_close must not be above speaking_open".to_string());
+        }
+        if !(1..=MAX_EVENT_RATE_HZ).contains(&self.event_rate_hz) {
+            return Err(format!("event_rate_hz must be 1-{}, got {}", MAX_EVENT_RATE_HZ, self.event_rate_hz));
+        }
+        if self.prepend_beep || self.append_beep {
+            if !(1..=MAX_BEEP_MS).contains(&self.beep_ms) {
+                return Err(format!("beep_ms must be 1-{}, got {}", MAX_BEEP_MS, self.beep_ms));
+            }
+            // Passthrough rates are only known once the device is open
+            let nyquist = if self.passthrough { MIN_SAMPLE_RATE } else { self.sample_rate } as f32 / 2.0;
+            if !(self.beep_hz > 0.0 && self.beep_hz < nyquist) {
+                return Err(format!("beep_hz must be between 0 and {} Hz, got {}", nyquist, self.beep_hz));
+            }
+            if self.fixed_duration_seconds.is_some() {
+                return Err("Beeps can't be added to fixed-duration clips".to_string());
+            }
+        }
+        if self.append_beep && (self.trim_silence || self.fade_out_ms > 0) {
+            return Err("append_beep can't be combined with trim_silence or fade_out_ms (they would cut the beep)".to_string());
+        }
+        if let Some(compressor) = &self.compressor {
+            compressor.validate()?;
+        }
+        if self.format == RecordingFormat::Opus && self.partial_frame == Some(PartialFrame::WritePartial) {
+            return Err("Opus recordings must stay frame-aligned; use pad or truncate for partial_frame".to_string());
+        }
+        if self.ignore_start_ms > MAX_IGNORE_MS || self.ignore_stop_ms > MAX_IGNORE_MS {
+            return Err(format!("ignore_start_ms and ignore_stop_ms must be at most {} ms", MAX_IGNORE_MS));
+        }
+        if self.fsync_interval_ms == Some(0) {
+            return Err("fsync_interval_ms must be positive".to_string());
+        }
+        if self.queue_capacity_frames == 0 {
+            return Err("queue_capacity_frames must be at least 1".to_string());
+        }
+        if self.max_buffer_bytes.is_some() && !self.extra_outputs.is_empty() {
+            return Err("extra_outputs buffers without a bound; it can't be used with max_buffer_bytes".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Samples in one `FRAME_SIZE_MS` frame at `sample_rate`, across all channels
+fn frame_size_samples(sample_rate: u32, channels: u16) -> usize {
+    (sample_rate as usize * FRAME_SIZE_MS as usize / 1000).max(1) * channels as usize
+}
+
+/// Expected output size in bytes for a recording of `duration_seconds`.
+/// Exact for WAV; a conservative upper bound for compressed formats.
+/// A fixed-duration config always produces its fixed length.
+pub fn estimate_size(config: &RecordingConfig, duration_seconds: f64) -> u64 {
+    let beeps = [config.prepend_beep, config.append_beep].iter().filter(|&&b| b).count();
+    let seconds = config.fixed_duration_seconds.unwrap_or(duration_seconds).max(0.0)
+        + beeps as f64 * config.beep_ms as f64 / 1000.0;
+    let pcm_bytes = (seconds * config.sample_rate as f64).round() as u64
+        * config.channels as u64
+        * (config.bits_per_sample as u64 / 8);
+
+    match config.format {
+        RecordingFormat::Wav => {
+            let tags = config.metadata.as_ref().map_or(0, WavMetadata::chunk_bytes);
+            let wav_bytes = config.container.header_bytes() + tags + pcm_bytes;
+            match config.encryption_key {
+                Some(_) => crypto::sealed_len(wav_bytes),
+                None => wav_bytes,
+            }
+        }
+        // Ogg page overhead is well under 2%
+        RecordingFormat::Opus => {
+            let bits = OPUS_MAX_BITRATE_PER_CHANNEL * config.channels as u64;
+            (seconds * bits as f64 / 8.0 * 1.02).ceil() as u64
+        }
+        // FLAC falls back to verbatim frames on incompressible audio, so it
+        // never meaningfully exceeds PCM size
+        RecordingFormat::Flac => WAV_HEADER_BYTES + pcm_bytes + pcm_bytes / 100,
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct RecordingStartResult {
     pub output_path: String,
+    pub device_name: Option<String>, // The device actually recording
+    pub fell_back: bool,             // The requested device failed to open
     pub sample_rate: u32,
     pub channels: u16,
+    /// What the input device actually negotiated, before any conversion
+    pub device_sample_rate: u32,
+    pub device_channels: u16,
+    pub device_format: String,
+    pub resampling: bool,
+    pub resample: Option<ResampleInfo>,
+    pub raw_capture_path: Option<String>, // Set with `capture_raw`
+    pub extra_output_paths: Vec<String>,  // From `extra_outputs`, in order
+    pub exclusive: bool,                  // The device was opened in exclusive mode
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -69,201 +687,661 @@ pub struct RecordingStatus {
     pub output_path: Option<String>,
 }
 
-// WAV file writer (simple 16-bit PCM format)
-struct WavWriter {
-    file: BufWriter<std::fs::File>,
-    data_size: u32,
-}
-
-impl WavWriter {
-    fn new(path: &str, sample_rate: u32, channels: u16) -> io::Result<Self> {
-        let file = std::fs::File::create(path)?;
-        let mut file = BufWriter::with_capacity(64 * 1024, file);
-
-        // Write RIFF header
-        file.write_all(b"RIFF")?;
-        // File size - 8 (will be updated on finish)
-        file.write_u32::<LittleEndian>(0)?;
-        // WAVE format
-        file.write_all(b"WAVE")?;
-
-        // fmt chunk
-        file.write_all(b"fmt ")?;
-        // Chunk size (16 for PCM)
-        file.write_u32::<LittleEndian>(16)?;
-        // Audio format (1 = PCM)
-        file.write_u16::<LittleEndian>(1)?;
-        // Channels
-        file.write_u16::<LittleEndian>(channels)?;
-        // Sample rate
-        file.write_u32::<LittleEndian>(sample_rate)?;
-        // Byte rate (sample_rate * channels * bits_per_sample / 8)
-        let byte_rate = sample_rate * channels as u32 * 2;
-        file.write_u32::<LittleEndian>(byte_rate)?;
-        // Block align (channels * bits_per_sample / 8)
-        file.write_u16::<LittleEndian>(channels * 2)?;
-        // Bits per sample (16)
-        file.write_u16::<LittleEndian>(16)?;
-
-        // data chunk
-        file.write_all(b"data")?;
-        // Data size (will be updated on finish)
-        file.write_u32::<LittleEndian>(0)?;
-
-        Ok(Self {
-            file,
-            data_size: 0,
-        })
-    }
-
-    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
-        for &sample in samples {
-            self.file.write_i16::<LittleEndian>(sample)?;
-        }
-        self.data_size += samples.len() as u32 * 2; // 2 bytes per sample
-        Ok(())
-    }
-
-    fn finish(self) -> io::Result<()> {
-        // Update data chunk size
-        use std::io::Seek;
-        let mut file = self.file.into_inner()?;
+/// Payload of `device-fallback`: the requested input failed to open and
+/// the recording started on the default device instead
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceFallbackEvent {
+    pub session_id: String,
+    pub requested: String,
+    pub used: Option<String>,
+    pub error: String, // Why the requested device failed
+}
 
-        // Data chunk size is at position 40 (after "data" marker)
-        file.seek(std::io::SeekFrom::Start(40))?;
-        file.write_u32::<LittleEndian>(self.data_size)?;
+/// Payload of `recording-finalized`: the file is complete and safe to read.
+/// Also the result of `stop_recording`, so a caller needn't wait for the event.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecordingFinalized {
+    pub session_id: String,
+    pub output_path: String,
+    pub size_bytes: u64,
+    pub duration_ms: u64,
+    pub peaks_path: Option<String>,
+    pub manifest_path: Option<String>,
+    pub resample: Option<ResampleInfo>, // Device rate -> file rate, if converted
+    pub peak_dbfs: f32,     // Loudest captured sample, beeps excluded
+    pub likely_muted: bool, // Nothing above `LIKELY_MUTED_DBFS`; worth asking if the mic was muted
+    pub extra_outputs: Vec<ExtraOutputFile>, // Copies from `extra_outputs`
+    pub raw_capture_overruns: u64, // Device callbacks dropped from `capture_raw`'s copy
+}
 
-        // File size is at position 4
-        let file_size = self.data_size + 36; // 36 = header size
-        file.seek(std::io::SeekFrom::Start(4))?;
-        file.write_u32::<LittleEndian>(file_size)?;
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
 
-        file.flush()?;
-        Ok(())
-    }
+/// Everything the UI needs about the recorder, read under a single lock so
+/// the fields are consistent with each other. Cheap enough to poll per frame.
+#[derive(Debug, serde::Serialize)]
+pub struct RecorderSnapshot {
+    pub is_recording: bool,
+    pub is_paused: bool,
+    pub is_muted: bool,
+    pub is_interrupted: bool, // Between `recording-interrupted` and `recording-resumed`
+    pub elapsed_ms: u64,
+    pub level: f32,
+    pub level_dbfs: f32,
+    pub is_silent: bool,
+    pub xruns: u64,
+    pub clipped_samples: u64, // Full-scale samples written so far
+    pub gain_db: f32,         // Software gain; below 0 once `clip_protection` stepped in
+    pub queued_frames: usize, // Frames waiting for the encoder
+    pub buffer_bytes: u64,    // Audio held in memory: the queued frames and level history
+    pub session_id: Option<String>,
+    pub device_name: Option<String>,
+    pub format: Option<AudioFormat>,
 }
 
-/// Start audio recording to WAV format
+/// Start audio recording to WAV format. A `max_duration_seconds` of 0
+/// records until stopped.
 pub fn start_recording(
     output_dir: String,
     max_duration_seconds: u64,
-) -> Result<RecordingStartResult, String> {
+    config: RecordingConfig,
+) -> Result<RecordingStartResult, RecorderError> {
+    check_exclusive_mode(&config)?;
+    // Held until this returns, so a second start (e.g. a double-click) is
+    // turned away while this one opens the device without the state lock
+    let (_starting, noise_profile) = begin_start(&config)?;
+
+    // A device that is missing is an error; only one that exists but won't
+    // open (busy, exclusive mode) falls back to the default
+    let requested = config.input_device.clone();
+    let device = devices::find_input_device(requested.as_deref())?;
+    match start_on_device(device, &output_dir, max_duration_seconds, config.clone(), noise_profile.clone()) {
+        Err(StartFailure::Open(e)) if requested.is_some() && config.fallback_to_default => {
+            eprintln!("Could not open {}: {}; falling back to the default device", requested.as_deref().unwrap_or_default(), e);
+            let device = devices::find_input_device(None)?;
+            let (mut result, session_id) = start_on_device(device, &output_dir, max_duration_seconds, config, noise_profile)
+                .map_err(String::from)?;
+            result.fell_back = true;
+            events::emit(events::DEVICE_FALLBACK, DeviceFallbackEvent {
+                session_id,
+                requested: requested.unwrap_or_default(),
+                used: result.device_name.clone(),
+                error: e,
+            });
+            Ok(result)
+        }
+        result => result.map(|(result, _)| result).map_err(|e| String::from(e).into()),
+    }
+}
+
+// Exclusive capture needs a backend that can request it; cpal can't
+fn check_exclusive_mode(config: &RecordingConfig) -> Result<(), RecorderError> {
+    match config.exclusive_mode {
+        true => exclusive::check_available(),
+        false => Ok(()),
+    }
+}
+
+// Check that a start may go ahead and mark it in progress; the noise
+// profile is the one to subtract, if the config asks for it
+fn begin_start(config: &RecordingConfig) -> Result<(StartingGuard, Option<Arc<NoiseProfile>>), String> {
+    config.validate()?;
     let global_state = get_global_state();
     let mut state = global_state
         .lock()
         .map_err(|e| format!("Failed to acquire lock: {}", e))?;
-
     if state.is_recording {
         return Err("Already recording".to_string());
     }
+    if state.is_starting {
+        return Err("Already starting".to_string());
+    }
+    let noise_profile = match (config.spectral_subtract, &state.noise_profile) {
+        (false, _) => None,
+        (true, Some(profile)) => Some(profile.clone()),
+        (true, None) => {
+            return Err("Spectral subtraction needs a noise profile; capture one first".to_string())
+        }
+    };
+    state.is_starting = true;
+    Ok((StartingGuard, noise_profile))
+}
+
+// Clears `is_starting` when the start it belongs to returns, whether it
+// installed a session or failed. Takes the state lock, so it must not be
+// dropped while that lock is held.
+struct StartingGuard;
+
+impl Drop for StartingGuard {
+    fn drop(&mut self) {
+        if let Ok(mut state) = get_global_state().lock() {
+            state.is_starting = false;
+        }
+    }
+}
+
+// Why `start_on_device` failed: opening the device (worth retrying on
+// another) or anything else
+enum StartFailure {
+    Open(String),
+    Other(String),
+}
+
+impl From<String> for StartFailure {
+    fn from(e: String) -> Self {
+        StartFailure::Other(e)
+    }
+}
+
+impl From<StartFailure> for String {
+    fn from(failure: StartFailure) -> Self {
+        match failure {
+            StartFailure::Open(e) | StartFailure::Other(e) => e,
+        }
+    }
+}
+
+// The channel settings against what the device actually has
+fn check_device_channels(config: &RecordingConfig, device_channels: u16) -> Result<(), String> {
+    if !config.passthrough && config.channels > device_channels && !config.channel_upmix {
+        return Err(format!(
+            "{} channels requested but the device has {}; set channel_upmix to duplicate them",
+            config.channels, device_channels
+        ));
+    }
+    if let Some(channel) = config.source_channel.filter(|&ch| ch >= device_channels as usize) {
+        return Err(format!(
+            "source_channel {} is out of range; the device has {} channels (0-{})",
+            channel,
+            device_channels,
+            device_channels - 1
+        ));
+    }
+    if let Some(weights) = &config.channel_weights {
+        if weights.len() != device_channels as usize {
+            return Err(format!(
+                "channel_weights has {} entries but the device has {} channels",
+                weights.len(),
+                device_channels
+            ));
+        }
+    }
+    Ok(())
+}
+
+// What `start_on_device` opens: a cpal device, or in tests one whose
+// stream fails partway through opening
+trait InputSource {
+    fn source_name(&self) -> Option<String>;
+    fn input_config(&self) -> Result<cpal::SupportedStreamConfig, String>;
+    fn start_capture(
+        self,
+        device_config: cpal::SupportedStreamConfig,
+        options: CaptureOptions,
+        sample_tx: FrameSender<AudioSample>,
+        meters: Arc<SessionMeters>,
+    ) -> Result<(InputStreamHandle, ReopenStream, CaptureInfo), String>;
+}
+
+impl InputSource for Device {
+    fn source_name(&self) -> Option<String> {
+        self.name().ok()
+    }
 
-    // Get default audio input device
-    let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .ok_or("No audio input device found")?;
+    fn input_config(&self) -> Result<cpal::SupportedStreamConfig, String> {
+        self.default_input_config().map_err(|e| format!("Failed to get default input config: {}", e))
+    }
+
+    fn start_capture(
+        self,
+        device_config: cpal::SupportedStreamConfig,
+        options: CaptureOptions,
+        sample_tx: FrameSender<AudioSample>,
+        meters: Arc<SessionMeters>,
+    ) -> Result<(InputStreamHandle, ReopenStream, CaptureInfo), String> {
+        convert::with_sample_type!(
+            device_config.sample_format(),
+            start_audio_capture(self, device_config, options, sample_tx, meters)
+        )
+    }
+}
 
-    let device_config = device
-        .default_input_config()
-        .map_err(|e| format!("Failed to get default input config: {}", e))?;
+// The part of a start that depends on the device, run without the state
+// lock; the session is only installed once capture is running, so a
+// failure leaves the state untouched. Also returns the session id.
+fn start_on_device(
+    device: impl InputSource,
+    output_dir: &str,
+    max_duration_seconds: u64,
+    config: RecordingConfig,
+    noise_profile: Option<Arc<NoiseProfile>>,
+) -> Result<(RecordingStartResult, String), StartFailure> {
+    let device_name = device.source_name();
+    let device_config = match config.exclusive_mode {
+        true => exclusive::negotiate(device_name.as_deref(), config.sample_rate, config.channels)
+            .map_err(|e| StartFailure::Open(e.to_string()))?,
+        false => device.input_config().map_err(StartFailure::Open)?,
+    };
+    stream::check_input_config(&device_config)?;
+    check_device_channels(&config, device_config.channels())?;
 
     // Create temp file in output directory
-    let output_path = PathBuf::from(output_dir);
+    let mut output_path = PathBuf::from(output_dir);
+    if config.subdir_by_date {
+        output_path.push(chrono::Local::now().format("%Y-%m-%d").to_string());
+    }
     std::fs::create_dir_all(&output_path)
         .map_err(|e| format!("Failed to create output directory: {}", e))?;
 
-    // Generate filename with timestamp
-    let timestamp = format!("{}", std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs());
-    let filename = format!("voice_{}.wav", timestamp);
+    // Generate filename from the template (timestamp by default)
+    let stem = naming::expand_template(&config.filename_template)?;
+    let filename = match config.encryption_key {
+        Some(_) => format!("{}.{}.enc", stem, config.container.extension()),
+        None => format!("{}.{}", stem, config.container.extension()),
+    };
     let file_path = output_path.join(&filename);
     let file_path_str = file_path.to_string_lossy().to_string();
+    if file_path.exists() && !config.overwrite {
+        return Err(format!("File already exists: {}", file_path_str).into());
+    }
+    let extra_outputs: Vec<(String, AudioContainer)> = config
+        .extra_outputs
+        .iter()
+        .map(|extra| {
+            let path = file_path.with_extension(extra.container.extension());
+            (path.to_string_lossy().to_string(), extra.container)
+        })
+        .collect();
+    if let Some((path, _)) = extra_outputs.iter().find(|(path, _)| Path::new(path).exists() && !config.overwrite) {
+        return Err(format!("File already exists: {}", path).into());
+    }
+
+    // Store config for later use (passthrough keeps the device's own layout)
+    let (sample_rate, channels) = if config.passthrough {
+        (device_config.sample_rate().0, device_config.channels())
+    } else {
+        (config.sample_rate, config.channels)
+    };
+
+    // Under `max_buffer_bytes` the queue gets what the budget leaves
+    let frame_bytes = frame_size_samples(sample_rate, channels) as u64 * 2;
+    let queue_frames = match config.max_buffer_bytes {
+        Some(budget) => {
+            let fits = budget.saturating_sub(LEVEL_HISTORY_BYTES) / frame_bytes;
+            if fits < MIN_BUDGET_QUEUE_FRAMES {
+                return Err(format!(
+                    "max_buffer_bytes of {} is below the {} bytes this format needs",
+                    budget,
+                    LEVEL_HISTORY_BYTES + MIN_BUDGET_QUEUE_FRAMES * frame_bytes
+                ).into());
+            }
+            config.queue_capacity_frames.min(fits as usize)
+        }
+        None => config.queue_capacity_frames,
+    };
 
-    // Store config for later use
-    let sample_rate = TELEGRAM_SAMPLE_RATE;
-    let channels = TELEGRAM_CHANNELS as u16;
+    // Create bounded queue for sending samples to encoder thread
+    let (sample_tx, sample_rx) = if config.deterministic {
+        queue::lossless::<AudioSample>(queue_frames)
+    } else {
+        queue::bounded::<AudioSample>(queue_frames)
+    };
 
-    // Create channel for sending samples to encoder thread
-    let (sample_tx, sample_rx) = mpsc::channel::<AudioSample>();
+    let meters = Arc::new(SessionMeters::new());
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let epoch = RECORDING_EPOCH.fetch_add(1, Ordering::SeqCst) + 1;
 
     // Spawn encoder thread
     let encoder_file_path = file_path_str.clone();
-    let max_frames = (sample_rate as usize * max_duration_seconds as usize) / FRAME_SIZE_SAMPLES;
+    let frame_samples = frame_size_samples(sample_rate, channels);
+    let max_frames = match max_duration_seconds {
+        0 => usize::MAX,
+        seconds => (sample_rate as usize * channels as usize * seconds as usize) / frame_samples,
+    };
+    let encoder_meters = meters.clone();
+    let fixed_length = config.fixed_duration_seconds.map(|seconds| FixedLength {
+        total_samples: (seconds * sample_rate as f64).round() as usize * channels as usize,
+        allow_truncate: config.allow_truncate,
+    });
+    let fixed_samples = fixed_length.as_ref().map(|fixed| fixed.total_samples);
+
+    let manifest = config.write_manifest.then(|| {
+        let device = DeviceManifest {
+            id: device_name.as_deref().and_then(devices::device_id),
+            name: device_name.clone(),
+            sample_rate: device_config.sample_rate().0,
+            channels: device_config.channels(),
+            sample_format: device_config.sample_format().to_string(),
+            input_volume: os_volume::get_input_volume().ok(),
+        };
+        let resample = ResampleInfo::between(device_config.sample_rate().0, sample_rate);
+        RecordingManifest::new(session_id.clone(), device, config.clone(), resample)
+    });
 
-    thread::spawn(move || {
-        encoder_thread(sample_rx, encoder_file_path, sample_rate, channels, max_frames);
+    let settings = EncoderSettings {
+        session_id: session_id.clone(),
+        output_path: encoder_file_path,
+        container: config.container,
+        sample_rate,
+        channels,
+        frame_samples,
+        max_frames,
+        fixed_length,
+        min_duration_ms: config.min_duration_ms,
+        write_peaks: config.write_peaks,
+        fsync_interval: config.fsync_interval_ms.map(Duration::from_millis),
+        resample: ResampleInfo::between(device_config.sample_rate().0, sample_rate),
+        trim_threshold: config.trim_silence.then(|| config.silence_threshold.to_linear()),
+        fade_out_ms: config.fade_out_ms,
+        epoch,
+        metadata: config.metadata.clone(),
+        encryption_key: config.encryption_key.clone(),
+        beep: (config.prepend_beep || config.append_beep).then(|| {
+            dsp::tone(sample_rate, channels, config.beep_hz, config.beep_ms, BEEP_DBFS)
+        }),
+        prepend_beep: config.prepend_beep,
+        append_beep: config.append_beep,
+        partial_frame: config.partial_frame.unwrap_or(config.format.default_partial_frame()),
+        preallocate: config.mmap_output.then(|| {
+            let seconds = match max_duration_seconds {
+                0 => MMAP_MAX_PREALLOC_SECONDS,
+                seconds => seconds.min(MMAP_MAX_PREALLOC_SECONDS),
+            };
+            config.container.header_bytes() + seconds * sample_rate as u64 * channels as u64 * 2
+        }),
+        ignore_start_samples: (config.ignore_start_ms * sample_rate as u64 / 1000) as usize * channels as usize,
+        ignore_stop_samples: (config.ignore_stop_ms * sample_rate as u64 / 1000) as usize * channels as usize,
+        extra_outputs: extra_outputs.clone(),
+        max_buffer_bytes: config.max_buffer_bytes,
+        manifest,
+    };
+
+    let high_priority = config.high_priority;
+    let encoder_handle = thread::spawn(move || {
+        if high_priority {
+            raise_thread_priority("encoder");
+        }
+        encoder_thread(sample_rx, settings, encoder_meters)
     });
 
+    let raw_capture_path = config.capture_raw.then(|| raw_capture::raw_path(&file_path));
+    let raw_capture = raw_capture_path
+        .as_deref()
+        .map(|path| raw_capture::RawCapture::spawn(path, &device_config, meters.raw_overruns.clone()));
+
     // Start audio capture based on sample format
     let sample_tx_clone = sample_tx.clone();
-    match device_config.sample_format() {
-        SampleFormat::I16 => {
-            start_audio_capture::<i16>(device, device_config, sample_rate, sample_tx_clone)?;
-        }
-        SampleFormat::F32 => {
-            start_audio_capture::<f32>(device, device_config, sample_rate, sample_tx_clone)?;
-        }
-        _ => {
-            let _ = sample_tx.send(AudioSample::Stop);
-            return Err("Unsupported sample format".to_string());
+    let options = CaptureOptions {
+        target_sample_rate: sample_rate,
+        target_channels: channels,
+        passthrough: config.passthrough,
+        high_priority,
+        channel_weights: config.channel_weights.clone(),
+        source_channel: config.source_channel,
+        noise_profile,
+        stereo_width: config.stereo_width,
+        compressor: config.compressor,
+        session_id: session_id.clone(),
+        event_rate_hz: config.event_rate_hz,
+        speaking_thresholds: (config.speaking_open, config.speaking_close),
+        raw_capture,
+        clip_protection: config.clip_protection,
+        resampler_quality: config.resampler_quality,
+        auto_downgrade_resampler: config.auto_downgrade_resampler,
+        exclusive: config.exclusive_mode,
+    };
+    let capture = device.start_capture(device_config, options, sample_tx_clone, meters.clone());
+    let (input_stream, reopen, capture_info) = match capture {
+        Ok(capture) => capture,
+        Err(e) => {
+            abort_encoder(sample_tx, encoder_handle, &file_path);
+            return Err(StartFailure::Open(e));
         }
-    }
+    };
 
     // Set recording state AFTER starting the stream
+    let global_state = get_global_state();
+    let Ok(mut state) = global_state.lock() else {
+        drop(input_stream);
+        abort_encoder(sample_tx, encoder_handle, &file_path);
+        return Err("Failed to acquire lock".to_string().into());
+    };
     state.is_recording = true;
     state.output_path = Some(file_path.clone());
     state.sample_rate = Some(sample_rate);
     state.channels = Some(channels);
     state.sample_tx = Some(sample_tx);
-
-    Ok(RecordingStartResult {
+    state.encoder_handle = Some(encoder_handle);
+    state.input_stream = Some(input_stream);
+    state.reopen = Some(reopen);
+    state.session_id = Some(session_id.clone());
+    state.device_name = device_name.clone();
+    state.meters = Some(meters);
+    let duration_limit = (max_frames != usize::MAX).then(|| (max_frames * frame_samples / channels as usize) as u64);
+    let fixed_limit = fixed_samples.map(|samples| (samples / channels as usize) as u64);
+    state.sample_limit = duration_limit.into_iter().chain(fixed_limit).min();
+    state.config = config;
+
+    let result = RecordingStartResult {
         output_path: file_path_str,
+        device_name,
+        fell_back: false,
         sample_rate,
         channels,
-    })
+        device_sample_rate: capture_info.device_sample_rate,
+        device_channels: capture_info.device_channels,
+        device_format: capture_info.device_format,
+        resampling: capture_info.resample.is_some(),
+        resample: capture_info.resample,
+        raw_capture_path: raw_capture_path.map(|path| path.to_string_lossy().to_string()),
+        extra_output_paths: extra_outputs.into_iter().map(|(path, _)| path).collect(),
+        exclusive: capture_info.exclusive,
+    };
+    Ok((result, session_id))
 }
 
-/// Encoder thread - receives audio samples and writes WAV file
-fn encoder_thread(
-    sample_rx: Receiver<AudioSample>,
+// Undo a start that failed after the encoder was spawned: stop and join
+// it, then delete what it wrote. Bumping the epoch first keeps its
+// `recording-finalized` event from announcing a recording that never began.
+fn abort_encoder(
+    sample_tx: FrameSender<AudioSample>,
+    encoder_handle: JoinHandle<Result<RecordingFinalized, RecorderError>>,
+    output_path: &std::path::Path,
+) {
+    RECORDING_EPOCH.fetch_add(1, Ordering::SeqCst);
+    let _ = sample_tx.send(AudioSample::Stop);
+    drop(sample_tx);
+    let _ = encoder_handle.join();
+    let _ = std::fs::remove_file(output_path);
+    let _ = std::fs::remove_file(peaks::sidecar_path(output_path));
+}
+
+// Exact output length for fixed-duration clips, in interleaved samples
+struct FixedLength {
+    total_samples: usize,
+    allow_truncate: bool,
+}
+
+// Everything the encoder thread needs to know about its output
+struct EncoderSettings {
+    session_id: String,
     output_path: String,
+    container: AudioContainer,
     sample_rate: u32,
     channels: u16,
+    frame_samples: usize, // Interleaved samples per frame
     max_frames: usize,
-) {
+    fixed_length: Option<FixedLength>,
+    min_duration_ms: u64,
+    write_peaks: bool,
+    fsync_interval: Option<Duration>,
+    resample: Option<ResampleInfo>,
+    trim_threshold: Option<f32>, // Linear RMS, when trimming trailing silence
+    fade_out_ms: u64,
+    epoch: u64,
+    metadata: Option<WavMetadata>,
+    encryption_key: Option<EncryptionKey>,
+    beep: Option<Vec<i16>>, // Interleaved tone, when either beep is on
+    prepend_beep: bool,
+    append_beep: bool,
+    partial_frame: PartialFrame,
+    preallocate: Option<u64>, // Bytes to map up front, for `mmap_output`
+    ignore_start_samples: usize, // Interleaved
+    ignore_stop_samples: usize,
+    extra_outputs: Vec<(String, AudioContainer)>, // Paths for `extra_outputs`
+    max_buffer_bytes: Option<u64>,
+    manifest: Option<RecordingManifest>, // Completed and written on finalize
+}
+
+// Best effort: log and carry on where the OS refuses
+fn raise_thread_priority(name: &str) {
+    if let Err(e) = thread_priority::set_current_thread_priority(thread_priority::ThreadPriority::Max) {
+        eprintln!("Could not raise {} thread priority: {:?}", name, e);
+    }
+}
+
+/// Encoder thread - receives audio samples and writes WAV file
+fn encoder_thread(
+    sample_rx: FrameReceiver<AudioSample>,
+    settings: EncoderSettings,
+    meters: Arc<SessionMeters>,
+) -> Result<RecordingFinalized, RecorderError> {
+    let EncoderSettings {
+        session_id,
+        output_path,
+        container,
+        sample_rate,
+        channels,
+        frame_samples,
+        max_frames,
+        fixed_length,
+        min_duration_ms,
+        write_peaks,
+        fsync_interval,
+        resample,
+        trim_threshold,
+        fade_out_ms,
+        epoch,
+        metadata,
+        encryption_key,
+        beep,
+        prepend_beep,
+        append_beep,
+        partial_frame,
+        preallocate,
+        ignore_start_samples,
+        ignore_stop_samples,
+        extra_outputs,
+        max_buffer_bytes,
+        manifest,
+    } = settings;
+
     // Create WAV writer
-    let mut writer = WavWriter::new(&output_path, sample_rate, channels)
-        .expect("Failed to create output file");
+    let writer = match (&encryption_key, preallocate) {
+        (Some(key), _) => WavWriter::encrypted(&output_path, sample_rate, channels, metadata.as_ref(), key),
+        (None, Some(capacity)) => {
+            WavWriter::mapped(&output_path, sample_rate, channels, container, metadata.as_ref(), capacity)
+        }
+        (None, None) => WavWriter::with_metadata(&output_path, sample_rate, channels, container, metadata.as_ref()),
+    };
+    let writer = writer.map_err(|e| format!("Failed to create output file: {}", e))?;
+    let mut writer = FanOut::new(writer, &extra_outputs, sample_rate, channels, metadata.as_ref())
+        .map_err(|e| format!("Failed to create extra output {}", e))?;
+
+    // Peaks are built from exactly what gets written, so no second pass
+    let mut peaks = write_peaks.then(|| PeaksBuilder::new(sample_rate, channels));
+
+    // Captured audio starts after the leading beep, so positions within it
+    // (stop points, truncation) are offset by the beep's length
+    let mut lead_in = 0;
+    if let Some(beep) = beep.as_ref().filter(|_| prepend_beep) {
+        writer.write_samples(beep)
+            .map_err(|e| format!("Failed to write WAV data: {}", e))?;
+        if let Some(peaks) = peaks.as_mut() {
+            peaks.push(beep);
+        }
+        lead_in = beep.len();
+    }
 
     // Buffer for accumulating samples
     let mut sample_buffer = Vec::new();
     let mut frames_written = 0usize;
+    let mut samples_written = 0usize; // Captured samples only, excluding beeps
+    let mut peak = 0.0f32; // Likewise
+
+    // Samples still allowed before a truncating fixed-length clip auto-stops
+    let cut_at = fixed_length
+        .as_ref()
+        .filter(|f| f.allow_truncate)
+        .map(|f| f.total_samples);
+
+    let mut last_sync = Instant::now();
+    let mut skip_start = ignore_start_samples;
 
     // Process samples until we receive Stop signal
-    loop {
+    'capture: loop {
         match sample_rx.recv_timeout(Duration::from_millis(100)) {
             Ok(AudioSample::Data(mut samples)) => {
+                // The ignored start never reaches the buffer, so frames,
+                // stop points and limits all count from after it
+                if skip_start > 0 {
+                    let skipped = skip_start.min(samples.len());
+                    samples.drain(..skipped);
+                    skip_start -= skipped;
+                }
                 sample_buffer.append(&mut samples);
 
                 // Write complete frames
-                while sample_buffer.len() >= FRAME_SIZE_SAMPLES {
+                while sample_buffer.len() >= frame_samples {
                     if frames_written >= max_frames {
                         eprintln!("Max duration reached, stopping recording");
-                        writer.finish().ok();
-                        return;
+                        sample_buffer.clear();
+                        break 'capture;
                     }
 
-                    let frame: Vec<i16> = sample_buffer.drain(..FRAME_SIZE_SAMPLES).collect();
+                    let mut frame: Vec<i16> = sample_buffer.drain(..frame_samples).collect();
+                    let reached_cut = match cut_at {
+                        Some(cut) if samples_written + frame.len() >= cut => {
+                            frame.truncate(cut - samples_written);
+                            true
+                        }
+                        _ => false,
+                    };
 
                     // Write samples to WAV file
                     writer.write_samples(&frame)
-                        .expect("Failed to write WAV data");
+                        .map_err(|e| format!("Failed to write WAV data: {}", e))?;
+                    if let Some(peaks) = peaks.as_mut() {
+                        peaks.push(&frame);
+                    }
+                    peak = peak.max(dsp::frame_peak(&frame));
 
                     frames_written += 1;
+                    samples_written += frame.len();
+                    meters.samples_written.fetch_add(frame.len() as u64 / channels as u64, Ordering::Relaxed);
+
+                    if reached_cut {
+                        eprintln!("Fixed duration reached, stopping recording");
+                        sample_buffer.clear();
+                        break 'capture;
+                    }
+                }
+
+                if fsync_interval.is_some_and(|interval| last_sync.elapsed() >= interval) {
+                    writer.sync()
+                        .map_err(|e| format!("Failed to sync WAV data: {}", e))?;
+                    last_sync = Instant::now();
                 }
             }
+            Ok(AudioSample::Flush { fsync, reply }) => {
+                let flushed = writer.flush_partial(fsync)
+                    .map_err(|e| format!("Failed to flush WAV data: {}", e));
+                let _ = reply.send(flushed);
+            }
             Ok(AudioSample::Stop) | Err(RecvTimeoutError::Disconnected) => {
                 break;
             }
@@ -273,75 +1351,492 @@ fn encoder_thread(
         }
     }
 
-    // Flush remaining samples (pad if needed)
+    // A precise stop point from `request_stop_at`, clamped to what was
+    // captured, then `ignore_stop_ms` before it. The stop arrives over IPC
+    // after the audio it refers to, so the cut may land in frames already
+    // on disk.
+    let stop_at = meters.stop_at.load(Ordering::Relaxed);
+    let exact_end = stop_at != NO_STOP_POINT || ignore_stop_samples > 0;
+    let mut rewound = false;
+    if exact_end {
+        let captured = samples_written + sample_buffer.len();
+        let cut = (stop_at as usize)
+            .saturating_mul(channels as usize)
+            .min(captured)
+            .saturating_sub(ignore_stop_samples);
+        if cut < samples_written {
+            writer.truncate(lead_in + cut)
+                .map_err(|e| format!("Failed to trim WAV data: {}", e))?;
+            samples_written = cut;
+            sample_buffer.clear();
+            meters.samples_written.store((cut / channels as usize) as u64, Ordering::Relaxed);
+            rewound = true;
+        } else {
+            sample_buffer.truncate(cut - samples_written);
+        }
+    }
+
+    let mut captured_samples = ((samples_written + sample_buffer.len()) / channels as usize) as u64;
+    let fixed_duration = fixed_length.is_some();
+
+    if let Some(fixed) = fixed_length {
+        // Write the real tail, then pad with silence to the exact target
+        let total = samples_written + sample_buffer.len();
+        if total > fixed.total_samples {
+            writer.discard();
+            let _ = std::fs::remove_file(&output_path);
+            return Err(format!(
+                "Recording is {:.3}s, longer than the fixed duration of {:.3}s",
+                total as f64 / (sample_rate as f64 * channels as f64),
+                fixed.total_samples as f64 / (sample_rate as f64 * channels as f64),
+            ).into());
+        }
+        sample_buffer.resize(fixed.total_samples - samples_written, 0);
+    } else if !sample_buffer.is_empty() && !exact_end {
+        match partial_frame {
+            PartialFrame::Pad => sample_buffer.resize(frame_samples, 0),
+            PartialFrame::Truncate => {
+                captured_samples -= (sample_buffer.len() / channels as usize) as u64;
+                sample_buffer.clear();
+            }
+            PartialFrame::WritePartial => {}
+        }
+    }
+
     if !sample_buffer.is_empty() {
-        // Pad to complete frame
-        while sample_buffer.len() < FRAME_SIZE_SAMPLES {
-            sample_buffer.push(0);
+        writer.write_samples(&sample_buffer)
+            .map_err(|e| format!("Failed to write WAV data: {}", e))?;
+        if let Some(peaks) = peaks.as_mut() {
+            peaks.push(&sample_buffer);
         }
-        writer.write_samples(&sample_buffer).ok();
+        peak = peak.max(dsp::frame_peak(&sample_buffer));
+        meters.samples_written.fetch_add(sample_buffer.len() as u64 / channels as u64, Ordering::Relaxed);
     }
 
+    if let Some(beep) = beep.as_ref().filter(|_| append_beep) {
+        writer.write_samples(beep)
+            .map_err(|e| format!("Failed to write WAV data: {}", e))?;
+        if let Some(peaks) = peaks.as_mut() {
+            peaks.push(beep);
+        }
+    }
+    // The meters now count the whole file, beeps included
+    let beep_frames = beep.map_or(0, |b| b.len() / channels as usize) as u64;
+    let beeps = [prepend_beep, append_beep].iter().filter(|&&b| b).count() as u64;
+    meters.samples_written.fetch_add(beeps * beep_frames, Ordering::Relaxed);
+
     // Finalize WAV file
-    writer.finish()
-        .expect("Failed to finalize WAV file");
+    let mut extra_files = writer.finish()
+        .map_err(|e| format!("Failed to finalize WAV file: {}", e))?;
 
     println!("Encoder thread finalized: {} frames written", frames_written);
-}
 
-/// Start audio capture using cpal
-fn start_audio_capture<T>(
-    device: Device,
-    device_config: cpal::SupportedStreamConfig,
-    target_sample_rate: u32,
-    sample_tx: Sender<AudioSample>,
-) -> Result<(), String>
-where
-    T: cpal::Sample + cpal::SizedSample,
-{
-    let input_channels = device_config.channels() as usize;
-    let device_sample_rate = device_config.sample_rate().0;
-    let resample_ratio = target_sample_rate as f64 / device_sample_rate as f64;
+    if !fixed_duration && (trim_threshold.is_some() || fade_out_ms > 0) {
+        let kept = tools::trim_and_fade(&output_path, trim_threshold, fade_out_ms, max_buffer_bytes)?;
+        for extra in extra_files.iter_mut().filter(|extra| extra.error.is_none()) {
+            match tools::trim_and_fade(&extra.path, trim_threshold, fade_out_ms, max_buffer_bytes) {
+                Ok(_) => extra.size_bytes = std::fs::metadata(&extra.path).map(|m| m.len()).unwrap_or(0),
+                Err(e) => extra.error = Some(e),
+            }
+        }
+        let lead_in_frames = (lead_in / channels as usize) as u64;
+        captured_samples = captured_samples.min(kept.saturating_sub(lead_in_frames));
+        meters.samples_written.store(kept, Ordering::Relaxed);
+        rewound = true;
+    }
 
-    // Channel for sending samples from audio callback
-    let (tx, rx) = mpsc::channel::<Vec<i16>>();
+    // Nothing but a header (or fixed-length padding): not worth keeping
+    if captured_samples == 0 {
+        let _ = std::fs::remove_file(&output_path);
+        fanout::remove_extras(&extra_files);
+        return Err(RecorderError::EmptyRecording);
+    }
 
-    // Spawn thread to process samples and send to encoder
-    thread::spawn(move || {
-        let mut input_buffer = Vec::new();
-        let mut output_buffer = Vec::new();
-        let mut src_idx = 0.0f64;
+    // Discard accidental taps rather than sending a fraction of a second
+    let captured_ms = captured_samples * 1000 / sample_rate as u64;
+    if captured_ms < min_duration_ms {
+        let _ = std::fs::remove_file(&output_path);
+        fanout::remove_extras(&extra_files);
+        return Err(RecorderError::TooShort { duration_ms: captured_ms, min_duration_ms });
+    }
 
-        loop {
-            match rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(mut samples) => {
-                    input_buffer.append(&mut samples);
-
-                    // Resample to 48 kHz if needed
-                    while input_buffer.len() >= 2 && (output_buffer.len() < FRAME_SIZE_SAMPLES * 2) {
-                        src_idx += resample_ratio.recip();
-                        let idx0 = src_idx.floor() as usize;
-                        let idx1 = (idx0 + 1).min(input_buffer.len() - 1);
-                        let frac = (src_idx.fract() * 1024.0) as i64;
-
-                        if idx0 < input_buffer.len() {
-                            let sample = ((input_buffer[idx0] as i64) * (1024 - frac)
-                                         + (input_buffer[idx1] as i64) * frac) / 1024;
-                            output_buffer.push(sample as i16);
-                        }
+    // Peaks pushed for trimmed or faded audio are stale; rebuild from the file
+    if rewound && peaks.is_some() {
+        let read_back = |e: std::io::Error| format!("Failed to read back trimmed recording: {}", e);
+        let mut builder = PeaksBuilder::new(sample_rate, channels);
+        let mut reader = WavReader::open(&output_path).map_err(read_back)?;
+        let frames = reader.frames();
+        let piece = max_buffer_bytes.map_or(frames, |budget| budget / (channels as u64 * 2)).max(1);
+        for first in (0..frames).step_by(piece as usize) {
+            builder.push(&reader.read_frames_at(first, piece).map_err(read_back)?);
+        }
+        peaks = Some(builder);
+    }
 
-                        if idx0 >= 1 {
-                            input_buffer.drain(..1);
-                            src_idx -= 1.0;
-                        }
-                    }
+    let peaks_path = match peaks {
+        Some(peaks) => Some(
+            peaks::write_sidecar(std::path::Path::new(&output_path), &peaks.finish())?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        None => None,
+    };
 
-                    // Send complete frames to encoder
-                    while output_buffer.len() >= FRAME_SIZE_SAMPLES {
-                        let frame: Vec<i16> = output_buffer.drain(..FRAME_SIZE_SAMPLES).collect();
-                        if sample_tx.send(AudioSample::Data(frame)).is_err() {
-                            return;
-                        }
+    let size_bytes = std::fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
+    let duration_ms = meters.samples_written.load(Ordering::Relaxed) * 1000 / sample_rate as u64;
+    let manifest_path = match manifest {
+        Some(mut manifest) => {
+            manifest.duration_ms = duration_ms;
+            manifest.size_bytes = size_bytes;
+            Some(
+                manifest::write_manifest(std::path::Path::new(&output_path), &manifest)?
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        }
+        None => None,
+    };
+
+    // Audio cut by a stop point or trim still counts, so this can only
+    // under-report a muted mic, never flag one that picked something up
+    let peak_dbfs = dsp::linear_to_dbfs(peak);
+    let finalized = RecordingFinalized {
+        session_id,
+        peaks_path,
+        manifest_path,
+        resample,
+        size_bytes,
+        output_path,
+        duration_ms,
+        peak_dbfs,
+        likely_muted: peak_dbfs < LIKELY_MUTED_DBFS,
+        extra_outputs: extra_files,
+        // The stream is closed before Stop is sent, so this is final
+        raw_capture_overruns: meters.raw_overruns.load(Ordering::Relaxed),
+    };
+    if RECORDING_EPOCH.load(Ordering::SeqCst) == epoch {
+        events::emit(events::RECORDING_FINALIZED, finalized.clone());
+    } else {
+        eprintln!("Encoder for session {} finished after a new recording started; not emitting", finalized.session_id);
+    }
+    Ok(finalized)
+}
+
+// How captured audio is shaped before it reaches the encoder
+struct CaptureOptions {
+    target_sample_rate: u32,
+    target_channels: u16,
+    passthrough: bool, // Keep native channels and rate
+    high_priority: bool,
+    channel_weights: Option<Vec<f32>>,
+    source_channel: Option<usize>, // Validated against the device channel count
+    noise_profile: Option<Arc<NoiseProfile>>, // Set when spectral subtraction is on
+    stereo_width: f32,
+    compressor: Option<CompressorConfig>,
+    session_id: String, // Tags the level events
+    event_rate_hz: u32,
+    speaking_thresholds: (SilenceThreshold, SilenceThreshold), // Open, close
+    raw_capture: Option<raw_capture::RawCapture>, // Device bytes for `capture_raw`
+    clip_protection: bool,
+    resampler_quality: ResamplerQuality,
+    auto_downgrade_resampler: bool,
+    exclusive: bool, // Open with `exclusive` in the negotiated format rather than through cpal
+}
+
+/// Payload of `gain-reduced`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GainReducedEvent {
+    pub session_id: String,
+    pub gain_db: f32,    // The new software gain, 0 dB or below
+    pub elapsed_ms: u64, // Where in the recording it stepped down
+}
+
+/// Payload of `resampler-downgraded`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ResamplerDowngradedEvent {
+    pub session_id: String,
+    pub backlog_ms: u64, // Capture waiting for processing when it switched
+    pub elapsed_ms: u64, // Where in the recording the crossfade starts
+}
+
+/// Payload of `recording-level`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LevelEvent {
+    pub session_id: String,
+    pub level: f32,      // RMS of the latest frame, 0.0-1.0
+    /// RMS per recorded channel, for stereo and passthrough recordings;
+    /// `None` for mono, where `level` is the only channel
+    pub channel_levels: Option<Vec<f32>>,
+    pub elapsed_ms: u64, // Audio captured so far, excluding paused time
+    pub is_final: bool,  // Sent once when capture ends, with level 0
+}
+
+/// Payload of `recording-config-changed`: the device stopped delivering
+/// audio at the rate it negotiated, so the recording was finalized at that
+/// point rather than continuing at the wrong pitch
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConfigChangedEvent {
+    pub session_id: String,
+    pub expected_sample_rate: u32,
+    pub measured_sample_rate: u32,
+}
+
+/// Payload of `recording-interrupted`: the device went away mid-recording
+/// (e.g. a call took the microphone). Nothing is finalized; capture picks up
+/// where it left off once the device can be opened again.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InterruptedEvent {
+    pub session_id: String,
+    pub error: String,
+}
+
+/// Payload of `recording-resumed`: capture continues in the same file, with
+/// the time spent interrupted left out like a pause
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ResumedEvent {
+    pub session_id: String,
+    pub interrupted_ms: u64,
+}
+
+/// Payload of `recording-speaking`, sent only when the state changes
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SpeakingEvent {
+    pub session_id: String,
+    pub speaking: bool,
+}
+
+fn emit_speaking(session_id: &str, speaking: bool) {
+    events::emit(events::RECORDING_SPEAKING, SpeakingEvent {
+        session_id: session_id.to_string(),
+        speaking,
+    });
+}
+
+// What the device negotiated, reported back from `start_audio_capture`
+struct CaptureInfo {
+    device_sample_rate: u32,
+    device_channels: u16,
+    device_format: String,
+    resample: Option<ResampleInfo>,
+    exclusive: bool,
+}
+
+/// Start audio capture using cpal
+fn start_audio_capture<T>(
+    device: Device,
+    device_config: cpal::SupportedStreamConfig,
+    options: CaptureOptions,
+    sample_tx: FrameSender<AudioSample>,
+    meters: Arc<SessionMeters>,
+) -> Result<(InputStreamHandle, ReopenStream, CaptureInfo), String>
+where
+    T: ToI16,
+{
+    let CaptureOptions {
+        target_sample_rate,
+        target_channels,
+        passthrough,
+        high_priority,
+        channel_weights,
+        source_channel,
+        noise_profile,
+        stereo_width,
+        compressor,
+        session_id,
+        event_rate_hz,
+        speaking_thresholds: (speaking_open, speaking_close),
+        raw_capture,
+        clip_protection,
+        resampler_quality,
+        auto_downgrade_resampler,
+        exclusive,
+    } = options;
+    let input_channels = device_config.channels() as usize;
+    let device_sample_rate = device_config.sample_rate().0;
+    let output_channels = if passthrough { input_channels as u16 } else { target_channels };
+    let frame_samples = frame_size_samples(target_sample_rate, output_channels);
+    let info = CaptureInfo {
+        device_sample_rate,
+        device_channels: device_config.channels(),
+        device_format: device_config.sample_format().to_string(),
+        resample: ResampleInfo::between(device_sample_rate, target_sample_rate),
+        exclusive,
+    };
+
+    // Channel for sending samples from audio callback
+    let (tx, rx) = mpsc::channel::<Vec<i16>>();
+
+    let capture_stream = CaptureStream {
+        device_name: device.name().ok(),
+        device_config,
+        output_channels,
+        passthrough,
+        channel_weights,
+        source_channel,
+        session_id: session_id.clone(),
+        meters: meters.clone(),
+        tx,
+        raw_capture,
+        exclusive,
+    };
+
+    // Spawn thread to process samples and send to encoder
+    thread::spawn(move || {
+        if high_priority {
+            raise_thread_priority("processing");
+        }
+        let mut output_buffer = Vec::new();
+        let mut resamplers = vec![ResamplerState::new(resampler_quality); output_channels as usize];
+        let mut compressor = compressor.map(|c| Compressor::new(c, target_sample_rate));
+        let mut throttle = events::Throttle::new(event_rate_hz);
+        let mut mute_ramp = dsp::GainRamp::new(target_sample_rate, MUTE_RAMP_MS);
+        let mut gain_ramp = dsp::GainRamp::new(target_sample_rate, GAIN_RAMP_MS);
+        let mut clip_guard = clip_protection.then(|| {
+            dsp::ClipGuard::new(CLIP_GAIN_STEP_DB, MIN_CLIP_GAIN_DB, CLIP_WINDOW_FRAMES, CLIP_SUSTAIN_FRAMES)
+        });
+        let mut rate_monitor = stream::RateMonitor::new(device_sample_rate);
+        // Only a sinc resampler in use has anything to fall back from
+        let downgradable = auto_downgrade_resampler
+            && resampler_quality == ResamplerQuality::High
+            && !passthrough
+            && device_sample_rate != target_sample_rate;
+        let mut backlog_monitor = downgradable.then(|| stream::BacklogMonitor::new(device_sample_rate));
+        if !passthrough {
+            let lookahead = resample::lookahead_frames(resampler_quality, device_sample_rate, target_sample_rate);
+            meters.resampler_lookahead.store(lookahead, Ordering::Relaxed);
+        }
+        let mut speaking = SpeakingDetector::new(speaking_open, speaking_close, SPEAKING_HOLD_MS / FRAME_SIZE_MS);
+        let mut monitor_feed = monitor::MonitorFeed::default();
+        let mut frames_sent = 0u64;
+        let mut stream_generation = 0;
+        let elapsed_ms = |frames: u64| frames * FRAME_SIZE_MS as u64;
+        let channel_levels = |frame: &[i16]| {
+            (output_channels > 1).then(|| dsp::channel_rms(frame, output_channels as usize))
+        };
+        let mut subtractors: Option<Vec<SpectralSubtractor>> = noise_profile.map(|profile| {
+            (0..output_channels)
+                .map(|_| SpectralSubtractor::new(&profile, target_sample_rate))
+                .collect()
+        });
+
+        'capture: loop {
+            match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(samples) => {
+                    // The interruption stalled delivery; don't mistake it for a
+                    // slow device, or bridge the gap from pre-interruption audio
+                    let generation = meters.stream_generation.load(Ordering::Relaxed);
+                    if generation != stream_generation {
+                        stream_generation = generation;
+                        rate_monitor.reset();
+                        resamplers.iter_mut().for_each(ResamplerState::reset);
+                        if let Some(monitor) = backlog_monitor.as_mut() {
+                            monitor.reset();
+                        }
+                    }
+                    let frames = (samples.len() / output_channels as usize) as u64;
+                    let queued = meters.queued_frames.fetch_sub(frames, Ordering::Relaxed).saturating_sub(frames);
+                    if let Some(backlog) = backlog_monitor.as_mut().and_then(|m| m.push(queued)) {
+                        eprintln!("Resampling can't keep up ({} frames behind), switching to the fast resampler", backlog);
+                        for resampler in &mut resamplers {
+                            resampler.downgrade();
+                        }
+                        backlog_monitor = None;
+                        meters.resampler_lookahead.store(0, Ordering::Relaxed);
+                        events::emit(events::RESAMPLER_DOWNGRADED, ResamplerDowngradedEvent {
+                            session_id: session_id.clone(),
+                            backlog_ms: backlog * 1000 / device_sample_rate as u64,
+                            elapsed_ms: elapsed_ms(frames_sent),
+                        });
+                    }
+                    if let Some(measured) = rate_monitor.push(samples.len() / output_channels as usize) {
+                        eprintln!(
+                            "Device rate changed mid-stream ({} Hz -> ~{} Hz), finalizing recording",
+                            device_sample_rate, measured
+                        );
+                        events::emit(events::RECORDING_CONFIG_CHANGED, ConfigChangedEvent {
+                            session_id: session_id.clone(),
+                            expected_sample_rate: device_sample_rate,
+                            measured_sample_rate: measured,
+                        });
+                        let _ = sample_tx.send(AudioSample::Stop);
+                        break 'capture;
+                    }
+
+                    // Resample to the output rate if needed (passthrough keeps native audio)
+                    let samples = if passthrough {
+                        samples
+                    } else {
+                        resample::resample_interleaved(
+                            &samples,
+                            output_channels as usize,
+                            device_sample_rate,
+                            target_sample_rate,
+                            &mut resamplers,
+                        )
+                    };
+                    match subtractors.as_mut() {
+                        Some(subtractors) => output_buffer.extend(spectral::process_interleaved(subtractors, &samples)),
+                        None => output_buffer.extend(samples),
+                    }
+
+                    // Send complete frames to encoder
+                    while output_buffer.len() >= frame_samples {
+                        let mut frame: Vec<i16> = output_buffer.drain(..frame_samples).collect();
+
+                        // Paused audio is dropped; muted audio fades to silence and keeps the timeline running
+                        if meters.paused.load(Ordering::Relaxed) {
+                            meters.set_level(0.0);
+                            if let Some(state) = speaking.update(0.0) {
+                                emit_speaking(&session_id, state);
+                            }
+                            continue;
+                        }
+                        let mute_target = if meters.muted.load(Ordering::Relaxed) { 0.0 } else { 1.0 };
+                        mute_ramp.apply(&mut frame, output_channels as usize, mute_target);
+                        let gain = dsp::db_to_gain(f32::from_bits(meters.gain_db.load(Ordering::Relaxed)));
+                        gain_ramp.apply(&mut frame, output_channels as usize, gain);
+                        if let Some(compressor) = compressor.as_mut() {
+                            compressor.process(&mut frame, output_channels as usize);
+                        }
+                        if output_channels == 2 {
+                            dsp::apply_stereo_width(&mut frame, stereo_width);
+                        }
+                        let clipped = dsp::count_clipped(&frame);
+                        meters.clipped_samples.fetch_add(clipped as u64, Ordering::Relaxed);
+                        let stepped = clip_guard.as_mut().and_then(|g| g.update(clipped >= CLIP_SAMPLES_PER_FRAME));
+                        if let Some(gain_db) = stepped {
+                            meters.gain_db.store(gain_db.to_bits(), Ordering::Relaxed);
+                            events::emit(events::GAIN_REDUCED, GainReducedEvent {
+                                session_id: session_id.clone(),
+                                gain_db,
+                                elapsed_ms: elapsed_ms(frames_sent),
+                            });
+                        }
+                        let level = dsp::frame_rms(&frame);
+                        let levels = channel_levels(&frame);
+                        meters.set_level(level);
+                        if let Some(state) = speaking.update(level) {
+                            emit_speaking(&session_id, state);
+                        }
+                        monitor_feed.feed(&frame, target_sample_rate, output_channels);
+                        live_pcm::feed(&frame, target_sample_rate, output_channels);
+
+                        match sample_tx.send(AudioSample::Data(frame)) {
+                            Ok(dropped) => {
+                                meters.xruns.fetch_add(dropped as u64, Ordering::Relaxed);
+                            }
+                            Err(_) => break 'capture,
+                        }
+                        frames_sent += 1;
+                        if throttle.ready() {
+                            events::emit(events::RECORDING_LEVEL, LevelEvent {
+                                session_id: session_id.clone(),
+                                level,
+                                channel_levels: levels,
+                                elapsed_ms: elapsed_ms(frames_sent),
+                                is_final: false,
+                            });
+                        }
                     }
                 }
                 Err(mpsc::RecvTimeoutError::Disconnected) => {
@@ -352,103 +1847,258 @@ where
                 }
             }
         }
+
+        emit_speaking(&session_id, false);
+        events::emit(events::RECORDING_LEVEL, LevelEvent {
+            session_id,
+            level: 0.0,
+            channel_levels: channel_levels(&[]),
+            elapsed_ms: elapsed_ms(frames_sent),
+            is_final: true,
+        });
     });
 
-    // Setup cpal audio stream
-    let err_callback = |err| {
-        eprintln!("Audio input error: {}", err);
-    };
+    let stream = capture_stream.open::<T>(device)?;
+    let reopen: ReopenStream = Arc::new(move || {
+        let device = devices::find_input_device(capture_stream.device_name.as_deref())?;
+        capture_stream.open::<T>(device)
+    });
+    Ok((stream, reopen, info))
+}
 
-    let tx_clone = tx.clone();
-    let data_callback = move |data: &[T], _: &cpal::InputCallbackInfo| {
-        let mut samples: Vec<i16> = Vec::with_capacity(data.len() / input_channels);
-
-        for chunk in data.chunks(input_channels) {
-            let mut sum: f32 = 0.0;
-            for sample in chunk.iter() {
-                let s_i16: i16;
-                if std::mem::size_of::<T>() == std::mem::size_of::<i16>() {
-                    s_i16 = i16::from_ne_bytes(unsafe {
-                        std::mem::transmute_copy::<T, [u8; 2]>(sample)
-                    });
-                } else {
-                    let s_f32: f32 = f32::from_ne_bytes(unsafe {
-                        std::mem::transmute_copy::<T, [u8; 4]>(sample)
-                    });
-                    s_i16 = (s_f32.clamp(-1.0, 1.0) * 32767.0) as i16;
-                }
-                sum += s_i16 as f32;
-            }
-            let mono_sample = (sum / input_channels as f32) as i16;
-            samples.push(mono_sample);
-        }
+// Everything the device callback needs, kept so an interrupted recording
+// can open the device again and carry on with the same processing thread
+struct CaptureStream {
+    device_name: Option<String>, // `None` reopens the default device
+    device_config: cpal::SupportedStreamConfig,
+    output_channels: u16,
+    passthrough: bool,
+    channel_weights: Option<Vec<f32>>,
+    source_channel: Option<usize>,
+    session_id: String,
+    meters: Arc<SessionMeters>,
+    tx: mpsc::Sender<Vec<i16>>,
+    raw_capture: Option<raw_capture::RawCapture>,
+    exclusive: bool, // Opened by name in `device_config` through `exclusive`
+}
 
-        let _ = tx_clone.send(samples);
-    };
+impl CaptureStream {
+    // Open `device` and feed its audio to the processing thread. It must
+    // come back in the format capture started with, or the processing
+    // (and the file) would change partway through; an exclusive stream asks
+    // for that format outright.
+    fn open<T: ToI16>(&self, device: Device) -> Result<InputStreamHandle, String> {
+        let device_config = match self.exclusive {
+            true => self.device_config.clone(),
+            false => device
+                .default_input_config()
+                .map_err(|e| format!("Failed to get default input config: {}", e))?,
+        };
+        if device_config.channels() != self.device_config.channels()
+            || device_config.sample_rate() != self.device_config.sample_rate()
+            || device_config.sample_format() != self.device_config.sample_format()
+        {
+            return Err(format!(
+                "Device format changed ({} ch, {} Hz, {} -> {} ch, {} Hz, {})",
+                self.device_config.channels(),
+                self.device_config.sample_rate().0,
+                self.device_config.sample_format(),
+                device_config.channels(),
+                device_config.sample_rate().0,
+                device_config.sample_format(),
+            ));
+        }
 
-    let stream_config = cpal::StreamConfig {
-        channels: device_config.channels(),
-        sample_rate: device_config.sample_rate(),
-        buffer_size: cpal::BufferSize::Default,
-    };
+        let input_channels = device_config.channels() as usize;
+        let device_sample_rate = device_config.sample_rate().0;
+        let output_channels = self.output_channels;
+        let passthrough = self.passthrough;
+        let channel_weights = self.channel_weights.clone();
+        let source_channel = self.source_channel;
+        let callback_meters = self.meters.clone();
+        let tx_clone = self.tx.clone();
+        let raw_tx = self.raw_capture.as_ref().map(raw_capture::RawCapture::pool);
+        let mut converted: Vec<i16> = Vec::with_capacity(input_channels);
+        // Shared by cpal and exclusive streams; `delay` is capture to callback
+        let mut process = move |data: &[T], delay: Option<Duration>| {
+            let frames = (data.len() / input_channels) as u64;
+            callback_meters.callback_us.store(frames * 1_000_000 / device_sample_rate as u64, Ordering::Relaxed);
+            if let Some(delay) = delay {
+                callback_meters.device_latency_us.store(delay.as_micros() as u64, Ordering::Relaxed);
+            }
+            if let Some(raw_tx) = &raw_tx {
+                raw_tx.send_with(|bytes| data.iter().for_each(|&sample| sample.extend_le_bytes(bytes)));
+            }
 
-    let _stream = device
-        .build_input_stream(&stream_config, data_callback, err_callback, None)
-        .map_err(|e| format!("Failed to build input stream: {}", e))?;
+            let mut samples: Vec<i16> = Vec::with_capacity(data.len() / input_channels * output_channels as usize);
 
-    _stream.play()
-        .map_err(|e| format!("Failed to play stream: {}", e))?;
+            for chunk in data.chunks(input_channels) {
+                converted.clear();
+                converted.extend(chunk.iter().map(|&sample| sample.to_i16()));
+                if passthrough {
+                    samples.extend_from_slice(&converted);
+                    continue;
+                }
+                if output_channels > 1 {
+                    // Leading device channels; the last one repeats when upmixing
+                    let last = converted.len() - 1;
+                    samples.extend((0..output_channels as usize).map(|ch| converted[ch.min(last)]));
+                    continue;
+                }
+                samples.push(match (&channel_weights, source_channel) {
+                    (_, Some(channel)) => converted.get(channel).copied().unwrap_or(0),
+                    (Some(weights), None) => dsp::downmix_weighted(&converted, weights),
+                    (None, None) => dsp::downmix(&converted),
+                });
+            }
 
-    // Keep the stream alive
-    std::mem::forget(_stream);
+            callback_meters.queued_frames.fetch_add(frames, Ordering::Relaxed);
+            let _ = tx_clone.send(samples);
+        };
+
+        // Only a device that is gone interrupts; other errors are logged and
+        // the stream carries on
+        let meters = self.meters.clone();
+        let session_id = self.session_id.clone();
+        let on_error = move |err: cpal::StreamError| {
+            if !matches!(err, cpal::StreamError::DeviceNotAvailable) || meters.interrupted.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            events::emit(events::RECORDING_INTERRUPTED, InterruptedEvent {
+                session_id: session_id.clone(),
+                error: err.to_string(),
+            });
+            let session_id = session_id.clone();
+            thread::spawn(move || resume_after_interruption(session_id));
+        };
+
+        if self.exclusive {
+            return exclusive::open_input_stream(self.device_name.clone(), device_config, process, on_error);
+        }
+        let data_callback = move |data: &[T], info: &cpal::InputCallbackInfo| {
+            let timestamp = info.timestamp();
+            process(data, timestamp.callback.duration_since(&timestamp.capture))
+        };
+        let stream_config = cpal::StreamConfig {
+            channels: device_config.channels(),
+            sample_rate: device_config.sample_rate(),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        stream::open_input_stream_with_errors(device, stream_config, data_callback, on_error)
+    }
+}
 
-    Ok(())
+// Try to reopen an interrupted recording's device until it comes back or
+// the recording ends. The dead stream is only replaced, not torn down
+// first, so the processing thread keeps running across the gap.
+fn resume_after_interruption(session_id: String) {
+    let interrupted_at = Instant::now();
+    let epoch = RECORDING_EPOCH.load(Ordering::SeqCst);
+    // Still the session this thread was started for
+    let current = |state: &RecordingState| {
+        state.is_recording
+            && state.session_id.as_deref() == Some(session_id.as_str())
+            && RECORDING_EPOCH.load(Ordering::SeqCst) == epoch
+    };
+    let global_state = get_global_state();
+    loop {
+        thread::sleep(RESUME_POLL_INTERVAL);
+        // Opening the device can take a while; commands (stop, status)
+        // must not wait on it, so it runs without the lock
+        let reopen = {
+            let state = global_state.lock().unwrap();
+            match state.reopen.clone() {
+                Some(reopen) if current(&state) => reopen,
+                _ => return,
+            }
+        };
+        let Ok(stream) = reopen() else {
+            continue;
+        };
+        let mut state = global_state.lock().unwrap();
+        if !current(&state) {
+            // Stopped meanwhile; the new stream closes as it drops
+            return;
+        }
+        state.input_stream = Some(stream);
+        if let Some(meters) = &state.meters {
+            meters.stream_generation.fetch_add(1, Ordering::Relaxed);
+            meters.interrupted.store(false, Ordering::SeqCst);
+        }
+        eprintln!("Input device is back; resuming recording {}", session_id);
+        events::emit(events::RECORDING_RESUMED, ResumedEvent {
+            session_id,
+            interrupted_ms: interrupted_at.elapsed().as_millis() as u64,
+        });
+        return;
+    }
 }
 
 /// Stop audio recording
-pub fn stop_recording() -> Result<String, String> {
+pub fn stop_recording() -> Result<RecordingFinalized, RecorderError> {
     let global_state = get_global_state();
     let mut state = global_state
         .lock()
         .map_err(|e| format!("Failed to acquire lock: {}", e))?;
 
     if !state.is_recording {
-        return Err("Not recording".to_string());
+        return Err("Not recording".into());
     }
 
     state.is_recording = false;
 
+    // Close the device first so no more audio arrives after Stop
+    drop(state.input_stream.take());
+    state.reopen = None;
+
     if let Some(tx) = &state.sample_tx {
         let _ = tx.send(AudioSample::Stop);
     }
 
-    let output_path = state
-        .output_path
-        .as_ref()
-        .ok_or("No recording in progress")?
-        .to_string_lossy()
-        .to_string();
-
     state.sample_tx = None;
+    state.meters = None;
+    state.session_id = None;
+    state.device_name = None;
+    let encoder_handle = state.encoder_handle.take();
 
-    // Give the encoder thread time to finalize (WAV files finalize quickly)
+    // Wait for the encoder thread to finalize (WAV files finalize quickly)
     drop(state);
-    std::thread::sleep(Duration::from_millis(500));
+    let finalized = encoder_handle
+        .ok_or("No recording in progress")?
+        .join()
+        .map_err(|_| "Encoder thread panicked".to_string())??;
 
-    // Verify the file exists
-    use std::path::Path;
-    let path = Path::new(&output_path);
-    if !path.exists() {
-        return Err(format!("Output file not found: {}", output_path));
+    // The encoder has already refused a take with no audio (`EmptyRecording`),
+    // so a file that is only a few samples long is still a real recording
+    if !Path::new(&finalized.output_path).exists() {
+        return Err(format!("Output file not found: {}", finalized.output_path).into());
     }
 
-    let metadata = std::fs::metadata(&path)
-        .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-    if metadata.len() < 100 {
-        return Err(format!("Output file is too small ({} bytes): {}", metadata.len(), output_path));
-    }
+    Ok(finalized)
+}
 
-    Ok(output_path)
+/// Make the file being recorded readable up to now without stopping:
+/// write out what the encoder has, fix up the header sizes and, with
+/// `fsync`, sync to disk. Audio still in the last partial 20 ms frame is not
+/// included. Returns the file's size in bytes.
+pub fn flush_recording(fsync: bool) -> Result<u64, String> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    {
+        let state = get_global_state();
+        let state = state.lock().unwrap();
+        let Some(sample_tx) = state.sample_tx.as_ref().filter(|_| state.is_recording) else {
+            return Err("Not recording".to_string());
+        };
+        sample_tx
+            .send(AudioSample::Flush { fsync, reply: reply_tx })
+            .map_err(|_| "Encoder has stopped".to_string())?;
+    }
+    // A full queue can evict the request, which drops `reply_tx`
+    match reply_rx.recv_timeout(FLUSH_TIMEOUT) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err("Encoder did not respond to the flush in time".to_string()),
+        Err(RecvTimeoutError::Disconnected) => Err("Flush request was dropped; the encoder is falling behind".to_string()),
+    }
 }
 
 /// Get current recording status
@@ -464,22 +2114,798 @@ pub fn get_recording_status() -> RecordingStatus {
     }
 }
 
+/// Give the recorder the app handle it emits events through
+pub fn init(app: tauri::AppHandle) {
+    events::init(app);
+}
+
+/// Register with `tauri::Builder::on_window_event`: the recorder slows its
+/// events while no window is on screen
+pub fn on_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    events::on_window_event(window, event);
+}
+
+/// Where captured audio spends its time before reaching the encoder (and
+/// the monitor, which is fed just before the queue). The encoder writes
+/// each frame as it arrives, so it adds nothing beyond `queue_ms`.
+#[derive(Debug, serde::Serialize)]
+pub struct LatencyEstimate {
+    pub device_ms: f64,   // Driver/hardware buffering, as cpal reports it (0 if it doesn't)
+    pub callback_ms: f64, // One device callback's worth of audio
+    pub framing_ms: f64,  // Filling a 20 ms frame
+    pub resampler_ms: f64,
+    pub noise_subtraction_ms: f64,
+    pub queue_ms: f64,    // Frames waiting for the encoder right now
+    pub total_ms: f64,
+    pub largest: String,  // The stage contributing most
+}
+
+/// Latency budget of the active recording
+pub fn get_latency_estimate() -> Result<LatencyEstimate, String> {
+    let global_state = get_global_state();
+    let state = global_state.lock().unwrap();
+    let (Some(meters), Some(sample_rate)) = (state.meters.as_deref(), state.sample_rate) else {
+        return Err("Not recording".to_string());
+    };
+    let us_to_ms = |us: u64| us as f64 / 1000.0;
+    let samples_to_ms = |samples: usize| samples as f64 * 1000.0 / sample_rate as f64;
+
+    let stages = [
+        ("device", us_to_ms(meters.device_latency_us.load(Ordering::Relaxed))),
+        ("callback", us_to_ms(meters.callback_us.load(Ordering::Relaxed))),
+        ("framing", FRAME_SIZE_MS as f64),
+        ("resampler", samples_to_ms(resample::LINEAR_DELAY_FRAMES as usize + meters.resampler_lookahead.load(Ordering::Relaxed) as usize)),
+        ("noise_subtraction", if state.config.spectral_subtract { samples_to_ms(spectral::FFT_SIZE) } else { 0.0 }),
+        ("queue", state.sample_tx.as_ref().map_or(0, |tx| tx.queued()) as f64 * FRAME_SIZE_MS as f64),
+    ];
+    let largest = stages.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|s| s.0).unwrap_or_default();
+
+    Ok(LatencyEstimate {
+        device_ms: stages[0].1,
+        callback_ms: stages[1].1,
+        framing_ms: stages[2].1,
+        resampler_ms: stages[3].1,
+        noise_subtraction_ms: stages[4].1,
+        queue_ms: stages[5].1,
+        total_ms: stages.iter().map(|s| s.1).sum(),
+        largest: largest.to_string(),
+    })
+}
+
+/// Get a consistent view of the whole recorder in one call
+pub fn get_recorder_snapshot() -> RecorderSnapshot {
+    let global_state = get_global_state();
+    let state = global_state.lock().unwrap();
+    let meters = state.meters.as_deref();
+    let sample_rate = state.sample_rate.unwrap_or(TELEGRAM_SAMPLE_RATE);
+    let level = meters.map(|m| m.level()).unwrap_or(0.0);
+    let queued_frames = state.sample_tx.as_ref().map(|tx| tx.queued()).unwrap_or(0);
+    let frame_bytes = frame_size_samples(sample_rate, state.channels.unwrap_or(1)) as u64 * 2;
+
+    RecorderSnapshot {
+        is_recording: state.is_recording,
+        is_paused: meters.is_some_and(|m| m.paused.load(Ordering::Relaxed)),
+        is_muted: meters.is_some_and(|m| m.muted.load(Ordering::Relaxed)),
+        is_interrupted: meters.is_some_and(|m| m.interrupted.load(Ordering::Relaxed)),
+        elapsed_ms: meters
+            .map(|m| m.samples_written.load(Ordering::Relaxed) * 1000 / sample_rate as u64)
+            .unwrap_or(0),
+        level,
+        level_dbfs: dsp::linear_to_dbfs(level),
+        is_silent: meters.is_some() && state.config.silence_threshold.is_silent(level),
+        xruns: meters.map(|m| m.xruns.load(Ordering::Relaxed)).unwrap_or(0),
+        clipped_samples: meters.map(|m| m.clipped_samples.load(Ordering::Relaxed)).unwrap_or(0),
+        gain_db: meters.map(|m| f32::from_bits(m.gain_db.load(Ordering::Relaxed))).unwrap_or(0.0),
+        queued_frames,
+        buffer_bytes: match meters {
+            Some(_) => queued_frames as u64 * frame_bytes + LEVEL_HISTORY_BYTES,
+            None => 0,
+        },
+        session_id: state.session_id.clone(),
+        device_name: state.device_name.clone(),
+        format: match (state.is_recording, state.sample_rate, state.channels) {
+            (true, Some(sample_rate), Some(channels)) => Some(AudioFormat {
+                sample_rate,
+                channels,
+                bits_per_sample: 16,
+            }),
+            _ => None,
+        },
+    }
+}
+
+/// Stop at a precise point in the recording (milliseconds of captured audio,
+/// the same timeline as the snapshot's `elapsed_ms`), trimming anything
+/// captured after it. A point beyond the captured audio keeps everything.
+pub fn request_stop_at(timestamp_ms: u64) -> Result<RecordingFinalized, RecorderError> {
+    {
+        let global_state = get_global_state();
+        let state = global_state
+            .lock()
+            .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        let meters = state.meters.as_ref().ok_or("Not recording")?;
+        let sample_rate = state.sample_rate.unwrap_or(TELEGRAM_SAMPLE_RATE) as u64;
+        meters.stop_at.store(timestamp_ms.saturating_mul(sample_rate) / 1000, Ordering::Relaxed);
+    }
+    stop_recording()
+}
+
+/// Audio left before the recording stops on its own, at
+/// `max_duration_seconds` or the end of a fixed-duration clip
+#[derive(Debug, serde::Serialize)]
+pub struct RemainingInfo {
+    pub seconds: Option<f64>, // `None`: no limit, records until stopped
+    pub bytes: Option<u64>,   // Sample data still to be written
+}
+
+/// How much of the duration limit is left, counted from what the encoder
+/// has written (so paused time doesn't use it up)
+pub fn get_remaining_time() -> Result<RemainingInfo, String> {
+    let global_state = get_global_state();
+    let state = global_state.lock().unwrap();
+    let meters = state.meters.as_ref().filter(|_| state.is_recording).ok_or("Not recording")?;
+    let Some(limit) = state.sample_limit else {
+        return Ok(RemainingInfo { seconds: None, bytes: None });
+    };
+    let sample_rate = state.sample_rate.unwrap_or(TELEGRAM_SAMPLE_RATE) as f64;
+    let channels = state.channels.unwrap_or(TELEGRAM_CHANNELS as u16) as u64;
+    let remaining = limit.saturating_sub(meters.samples_written.load(Ordering::Relaxed));
+    Ok(RemainingInfo {
+        seconds: Some(remaining as f64 / sample_rate),
+        bytes: Some(remaining * channels * 2),
+    })
+}
+
+/// Frame levels (0.0-1.0, one per 20ms) for the last 10 seconds, oldest
+/// first; empty when not recording
+pub fn get_level_history() -> Vec<f32> {
+    let global_state = get_global_state();
+    let state = global_state.lock().unwrap();
+    state
+        .meters
+        .as_ref()
+        .map(|m| m.level_history.lock().unwrap().iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Pause or resume writing audio (the stream stays open while paused)
+pub fn set_recording_paused(paused: bool) -> Result<(), String> {
+    let global_state = get_global_state();
+    let state = global_state
+        .lock()
+        .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let meters = state.meters.as_ref().ok_or("Not recording")?;
+    meters.paused.store(paused, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Mute or unmute the input (muted audio is recorded as silence)
+pub fn set_recording_muted(muted: bool) -> Result<(), String> {
+    let global_state = get_global_state();
+    let state = global_state
+        .lock()
+        .map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let meters = state.meters.as_ref().ok_or("Not recording")?;
+    meters.muted.store(muted, Ordering::Relaxed);
+    Ok(())
+}
+
 // Tauri command wrappers
 
 #[tauri::command]
 pub fn tauri_start_recording(
     output_dir: String,
     max_duration_seconds: u64,
-) -> Result<RecordingStartResult, String> {
-    start_recording(output_dir, max_duration_seconds)
+    config: Option<RecordingConfig>,
+    preset: Option<String>,
+) -> Result<RecordingStartResult, RecorderError> {
+    let config = presets::resolve_config(config, preset)?;
+    let result = start_recording(output_dir, max_duration_seconds, config.clone())?;
+    if let Err(e) = presets::remember_config(&config) {
+        eprintln!("Failed to remember recording config: {}", e);
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn tauri_supported_formats() -> Vec<RecordingFormat> {
+    supported_formats()
+}
+
+#[tauri::command]
+pub fn tauri_supported_containers() -> Vec<AudioContainer> {
+    supported_containers()
+}
+
+#[tauri::command]
+pub fn tauri_list_presets() -> Result<Vec<PresetInfo>, String> {
+    presets::list_presets()
+}
+
+#[tauri::command]
+pub fn tauri_save_preset(name: String, config: RecordingConfig, overwrite: Option<bool>) -> Result<(), String> {
+    presets::save_preset(name, config, overwrite.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn tauri_apply_preset(name: String) -> Result<RecordingConfig, String> {
+    presets::apply_preset(name)
 }
 
 #[tauri::command]
-pub fn tauri_stop_recording() -> Result<String, String> {
+pub fn tauri_stop_recording() -> Result<RecordingFinalized, RecorderError> {
     stop_recording()
 }
 
+#[tauri::command]
+pub fn tauri_request_stop_at(timestamp_ms: u64) -> Result<RecordingFinalized, RecorderError> {
+    request_stop_at(timestamp_ms)
+}
+
+#[tauri::command]
+pub fn tauri_capture_noise_profile(duration_ms: u64) -> Result<NoiseProfileInfo, String> {
+    spectral::capture_noise_profile(duration_ms)
+}
+
 #[tauri::command]
 pub fn tauri_get_recording_status() -> RecordingStatus {
     get_recording_status()
 }
+
+#[tauri::command]
+pub fn tauri_get_recorder_snapshot() -> RecorderSnapshot {
+    get_recorder_snapshot()
+}
+
+#[tauri::command]
+pub fn tauri_get_latency_estimate() -> Result<LatencyEstimate, String> {
+    get_latency_estimate()
+}
+
+#[tauri::command]
+pub fn tauri_get_level_history() -> Vec<f32> {
+    get_level_history()
+}
+
+#[tauri::command]
+pub fn tauri_get_remaining_time() -> Result<RemainingInfo, String> {
+    get_remaining_time()
+}
+
+#[tauri::command]
+pub fn tauri_flush_recording(fsync: Option<bool>) -> Result<u64, String> {
+    flush_recording(fsync.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn tauri_set_recording_paused(paused: bool) -> Result<(), String> {
+    set_recording_paused(paused)
+}
+
+#[tauri::command]
+pub fn tauri_set_recording_muted(muted: bool) -> Result<(), String> {
+    set_recording_muted(muted)
+}
+
+#[tauri::command]
+pub fn tauri_list_recordings(
+    dir: String,
+    recursive: Option<bool>,
+    filter: Option<SendFilter>,
+) -> Result<Vec<RecordingEntry>, String> {
+    tools::list_recordings(dir, recursive.unwrap_or(false), filter.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn tauri_mark_sent(path: String) -> Result<(), String> {
+    sent::mark_sent(path)
+}
+
+#[tauri::command]
+pub fn tauri_mark_unsent(path: String) -> Result<(), String> {
+    sent::mark_unsent(path)
+}
+
+#[tauri::command]
+pub fn tauri_downmix_to_mono(input: String, output: String) -> Result<DownmixResult, String> {
+    tools::downmix_to_mono(input, output)
+}
+
+#[tauri::command]
+pub fn tauri_apply_gain_envelope(input: String, output: String, points: Vec<(f64, f32)>) -> Result<(), String> {
+    tools::apply_gain_envelope(input, output, points)
+}
+
+#[tauri::command]
+pub fn tauri_concat_wavs(paths: Vec<String>, output: String) -> Result<ConcatResult, String> {
+    tools::concat_wavs(paths, output)
+}
+
+#[tauri::command]
+pub fn tauri_prepend_audio(intro: String, recording: String, output: String) -> Result<PrependResult, String> {
+    tools::prepend_audio(intro, recording, output)
+}
+
+#[tauri::command]
+pub fn tauri_extract_segment(
+    input: String,
+    output: String,
+    start_seconds: f64,
+    end_seconds: f64,
+) -> Result<SegmentResult, String> {
+    tools::extract_segment(input, output, start_seconds, end_seconds)
+}
+
+#[tauri::command]
+pub fn tauri_auto_split_on_silence(
+    input: String,
+    output_dir: String,
+    min_silence_ms: u64,
+    min_segment_ms: u64,
+    threshold: Option<SilenceThreshold>,
+) -> Result<Vec<String>, String> {
+    tools::auto_split_on_silence(input, output_dir, min_silence_ms, min_segment_ms, threshold)
+}
+
+#[tauri::command]
+pub fn tauri_ensure_format(
+    input: String,
+    output: String,
+    target_rate: u32,
+    target_channels: u16,
+    target_bits: u16,
+) -> Result<EnsureFormatResult, String> {
+    tools::ensure_format(input, output, target_rate, target_channels, target_bits)
+}
+
+#[tauri::command]
+pub fn tauri_get_recording_data_url(path: String) -> Result<String, String> {
+    tools::recording_data_url(path)
+}
+
+#[tauri::command]
+pub fn tauri_fix_sample_rate(path: String, correct_rate: u32) -> Result<SampleRateFix, String> {
+    tools::fix_sample_rate(path, correct_rate)
+}
+
+#[tauri::command]
+pub fn tauri_normalize_wav(input: String, output: String, target_dbfs: f32) -> Result<NormalizeResult, String> {
+    tools::normalize_wav(input, output, target_dbfs)
+}
+
+#[tauri::command]
+pub fn tauri_vorbis_comments(path: String) -> Result<Vec<String>, String> {
+    tools::vorbis_comments(path)
+}
+
+#[tauri::command]
+pub fn tauri_compare_recordings(a: String, b: String) -> Result<DiffReport, String> {
+    tools::compare_recordings(a, b)
+}
+
+#[tauri::command]
+pub fn tauri_requantize_wav(input: String, output: String, target_bits: u16) -> Result<RequantizeResult, String> {
+    tools::requantize_wav(input, output, target_bits)
+}
+
+#[tauri::command]
+pub fn tauri_transcode(input: String, output: String, target: OutputSpec) -> Result<TranscodeResult, String> {
+    tools::transcode(input, output, target)
+}
+
+#[tauri::command]
+pub fn tauri_normalize_directory(
+    dir: String,
+    target_dbfs: f32,
+    output_dir: Option<String>,
+) -> Result<Vec<BatchNormalizeEntry>, String> {
+    tools::normalize_directory(dir, target_dbfs, output_dir)
+}
+
+#[tauri::command]
+pub fn tauri_fit_to_size(input: String, output: String, max_bytes: u64) -> Result<FitResult, String> {
+    tools::fit_to_size(input, output, max_bytes)
+}
+
+#[tauri::command]
+pub fn tauri_validate_wav(path: String) -> Result<WavValidation, RecorderError> {
+    tools::validate_wav(path)
+}
+
+#[tauri::command]
+pub fn tauri_fingerprint_wav(path: String) -> Result<String, String> {
+    fingerprint::fingerprint_wav(path)
+}
+
+#[tauri::command]
+pub fn tauri_generate_waveform(path: String, points: Option<usize>) -> Result<Peaks, String> {
+    peaks::generate_waveform(path, points)
+}
+
+#[tauri::command]
+pub fn tauri_estimate_size(config: Option<RecordingConfig>, duration_seconds: f64) -> u64 {
+    estimate_size(&config.unwrap_or_default(), duration_seconds)
+}
+
+#[tauri::command]
+pub fn tauri_list_input_devices() -> Result<Vec<AudioDeviceInfo>, String> {
+    devices::list_input_devices()
+}
+
+#[tauri::command]
+pub fn tauri_get_input_volume() -> Result<InputVolume, RecorderError> {
+    os_volume::get_input_volume()
+}
+
+#[tauri::command]
+pub fn tauri_set_input_volume(volume: f32) -> Result<InputVolume, RecorderError> {
+    os_volume::set_input_volume(volume)
+}
+
+#[tauri::command]
+pub fn tauri_run_audio_self_test(output_dir: String) -> Result<SelfTestReport, String> {
+    self_test::run_audio_self_test(output_dir)
+}
+
+#[tauri::command]
+pub fn tauri_test_input_device(device_name: Option<String>, duration_ms: u64) -> Result<InputTestResult, String> {
+    input_test::test_input_device(device_name, duration_ms)
+}
+
+#[tauri::command]
+pub fn tauri_compare_devices(device_a: String, device_b: String, duration_ms: u64) -> Result<ComparisonResult, String> {
+    input_test::compare_devices(device_a, device_b, duration_ms)
+}
+
+#[tauri::command]
+pub fn tauri_measure_noise_floor(duration_ms: u64) -> Result<f32, String> {
+    input_test::measure_noise_floor(duration_ms)
+}
+
+#[tauri::command]
+pub fn tauri_measure_loopback_latency() -> Result<LatencyMeasurement, String> {
+    latency::measure_loopback_latency()
+}
+
+#[tauri::command]
+pub fn tauri_play_audio(path: String) -> Result<PlaybackInfo, String> {
+    playback::play_audio(path)
+}
+
+#[tauri::command]
+pub fn tauri_play_encrypted(path: String, key: String) -> Result<PlaybackInfo, String> {
+    playback::play_encrypted(path, &EncryptionKey::from_hex(&key)?)
+}
+
+#[tauri::command]
+pub fn tauri_decrypt_recording(input: String, output: String, key: String) -> Result<(), String> {
+    crypto::decrypt_to_file(&input, &output, &EncryptionKey::from_hex(&key)?)
+}
+
+#[tauri::command]
+pub fn tauri_stop_playback() {
+    playback::stop_playback()
+}
+
+#[tauri::command]
+pub fn tauri_get_playback_level() -> f32 {
+    playback::get_playback_level()
+}
+
+#[tauri::command]
+pub fn tauri_set_event_target(window_label: Option<String>) -> Result<(), String> {
+    events::set_event_target(window_label)
+}
+
+#[tauri::command]
+pub fn tauri_set_monitoring(enabled: bool) -> Result<(), String> {
+    monitor::set_monitoring(enabled)
+}
+
+#[tauri::command]
+pub fn tauri_duck_monitor(duration_ms: u64, attenuation_db: f32) -> Result<(), String> {
+    monitor::duck_monitor(duration_ms, attenuation_db)
+}
+
+#[tauri::command]
+pub fn tauri_set_monitor_pan(pan: f32) {
+    monitor::set_monitor_pan(pan)
+}
+
+#[tauri::command]
+pub fn tauri_start_live_pcm(channel: tauri::ipc::Channel) {
+    live_pcm::start_live_pcm(channel)
+}
+
+#[tauri::command]
+pub fn tauri_stop_live_pcm() {
+    live_pcm::stop_live_pcm()
+}
+
+#[tauri::command]
+pub fn tauri_render_spectrogram(
+    input: String,
+    output_png: String,
+    width: u32,
+    height: u32,
+) -> Result<SpectrogramInfo, String> {
+    spectrogram::render_spectrogram(input, output_png, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_on_a_mono_device() {
+        let stereo = RecordingConfig { channels: 2, ..RecordingConfig::default() };
+        let err = check_device_channels(&stereo, 1).unwrap_err();
+        assert!(err.contains("2 channels requested but the device has 1"), "{}", err);
+
+        let upmixed = RecordingConfig { channel_upmix: true, ..stereo.clone() };
+        assert!(check_device_channels(&upmixed, 1).is_ok());
+        // Passthrough records the device's own layout, so there is nothing to upmix
+        let passthrough = RecordingConfig { passthrough: true, ..stereo.clone() };
+        assert!(check_device_channels(&passthrough, 1).is_ok());
+        assert!(check_device_channels(&stereo, 2).is_ok());
+    }
+
+    fn encoder_settings(output_path: &Path) -> EncoderSettings {
+        EncoderSettings {
+            session_id: "test".to_string(),
+            output_path: output_path.to_string_lossy().to_string(),
+            container: AudioContainer::Wav,
+            sample_rate: 48000,
+            channels: 1,
+            frame_samples: 960,
+            max_frames: usize::MAX,
+            fixed_length: None,
+            min_duration_ms: 0,
+            write_peaks: true,
+            fsync_interval: None,
+            resample: None,
+            trim_threshold: None,
+            fade_out_ms: 0,
+            epoch: RECORDING_EPOCH.load(Ordering::SeqCst),
+            metadata: None,
+            encryption_key: None,
+            beep: None,
+            prepend_beep: false,
+            append_beep: false,
+            partial_frame: PartialFrame::Pad,
+            preallocate: None,
+            ignore_start_samples: 0,
+            ignore_stop_samples: 0,
+            extra_outputs: Vec::new(),
+            max_buffer_bytes: None,
+            manifest: None,
+        }
+    }
+
+    // A device whose stream delivers some audio and then fails to start
+    // playing; it keeps the sender it was given, to show the encoder is gone
+    struct FailingSource {
+        output_dir: PathBuf,
+        kept_tx: Arc<Mutex<Option<FrameSender<AudioSample>>>>,
+    }
+
+    impl InputSource for FailingSource {
+        fn source_name(&self) -> Option<String> {
+            Some("Failing Mic".to_string())
+        }
+
+        fn input_config(&self) -> Result<cpal::SupportedStreamConfig, String> {
+            Ok(cpal::SupportedStreamConfig::new(
+                1,
+                cpal::SampleRate(48000),
+                cpal::SupportedBufferSize::Unknown,
+                cpal::SampleFormat::I16,
+            ))
+        }
+
+        fn start_capture(
+            self,
+            _device_config: cpal::SupportedStreamConfig,
+            _options: CaptureOptions,
+            sample_tx: FrameSender<AudioSample>,
+            _meters: Arc<SessionMeters>,
+        ) -> Result<(InputStreamHandle, ReopenStream, CaptureInfo), String> {
+            sample_tx.send(AudioSample::Data(vec![1000; 960 * 3])).unwrap();
+            while std::fs::read_dir(&self.output_dir).unwrap().next().is_none() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            *self.kept_tx.lock().unwrap() = Some(sample_tx);
+            Err("Failed to play stream: the device went away".to_string())
+        }
+    }
+
+    #[test]
+    fn failed_start_joins_the_encoder_and_removes_its_file() {
+        let output_dir = std::env::temp_dir().join(format!("dpc-recorder-test-{}-aborted", std::process::id()));
+        let _ = std::fs::remove_dir_all(&output_dir);
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let kept_tx = Arc::new(Mutex::new(None));
+        let source = FailingSource { output_dir: output_dir.clone(), kept_tx: kept_tx.clone() };
+
+        let dir = output_dir.to_string_lossy().to_string();
+        let failure = start_on_device(source, &dir, 0, RecordingConfig::default(), None).err();
+        assert!(
+            matches!(&failure, Some(StartFailure::Open(e)) if e.contains("the device went away")),
+            "{:?}",
+            failure.map(String::from)
+        );
+
+        // The encoder thread has exited, taking its end of the queue with it
+        let kept_tx = kept_tx.lock().unwrap().take().expect("the stream was opened");
+        assert!(kept_tx.send(AudioSample::Stop).is_err(), "encoder thread still running");
+        let left: Vec<_> = std::fs::read_dir(&output_dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert!(left.is_empty(), "left behind: {:?}", left);
+        assert!(get_global_state().lock().unwrap().session_id.is_none(), "session installed");
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    // What `stop_recording` returns, from a short take with `settings`
+    fn finalize(settings: EncoderSettings, samples: Vec<i16>) -> RecordingFinalized {
+        let (sample_tx, sample_rx) = queue::bounded::<AudioSample>(8);
+        sample_tx.send(AudioSample::Data(samples)).unwrap();
+        sample_tx.send(AudioSample::Stop).unwrap();
+        encoder_thread(sample_rx, settings, Arc::new(SessionMeters::new())).unwrap()
+    }
+
+    #[test]
+    fn stop_result_reports_the_rate_conversion() {
+        let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-resampled.wav", std::process::id()));
+        let resample = ResampleInfo::between(44100, 48000);
+        let settings = EncoderSettings { resample, write_peaks: false, ..encoder_settings(&path) };
+        let finalized = finalize(settings, vec![1000; 960 * 5]);
+        let reported = finalized.resample.expect("resample info");
+        assert_eq!((reported.from_rate, reported.to_rate), (44100, 48000));
+        assert!(!reported.integer_ratio);
+        assert!((reported.ratio - 48000.0 / 44100.0).abs() < 1e-12);
+        assert_eq!(finalized.output_path, path.to_string_lossy());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn stop_result_flags_a_silent_take_as_likely_muted() {
+        let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-muted.wav", std::process::id()));
+        let settings = || EncoderSettings { write_peaks: false, ..encoder_settings(&path) };
+
+        let silent = finalize(settings(), vec![0; 960 * 5]);
+        assert!(silent.likely_muted, "peak {} dBFS", silent.peak_dbfs);
+
+        // A quiet room (about -60 dBFS) is still someone recording
+        let quiet = finalize(settings(), (0..960 * 5).map(|i| if i % 2 == 0 { 33 } else { -33 }).collect());
+        assert!(!quiet.likely_muted, "peak {} dBFS", quiet.peak_dbfs);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_tiny_take_is_kept_and_an_empty_one_removed() {
+        let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-tiny.wav", std::process::id()));
+        let settings = || EncoderSettings {
+            partial_frame: PartialFrame::WritePartial,
+            write_peaks: false,
+            ..encoder_settings(&path)
+        };
+
+        // Four samples: a valid WAV smaller than the old 100-byte cut-off
+        let tiny = finalize(settings(), vec![1000; 4]);
+        assert!(tiny.size_bytes < 100, "{} bytes", tiny.size_bytes);
+        assert_eq!(WavReader::open(path.to_str().unwrap()).unwrap().read_samples().unwrap(), vec![1000; 4]);
+
+        let (sample_tx, sample_rx) = queue::bounded::<AudioSample>(8);
+        sample_tx.send(AudioSample::Stop).unwrap();
+        let empty = encoder_thread(sample_rx, settings(), Arc::new(SessionMeters::new())).unwrap_err();
+        assert_eq!(empty.kind(), "empty_recording", "{}", empty);
+        assert!(!path.exists(), "header-only stub left behind");
+    }
+
+    #[test]
+    fn stop_result_lists_each_extra_output() {
+        let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-fanout.wav", std::process::id()));
+        let aiff = path.with_extension("aif").to_string_lossy().to_string();
+        let settings = EncoderSettings {
+            extra_outputs: vec![(aiff.clone(), AudioContainer::Aiff)],
+            write_peaks: false,
+            ..encoder_settings(&path)
+        };
+        let finalized = finalize(settings, vec![1000; 960 * 5]);
+
+        assert_eq!(finalized.extra_outputs.len(), 1);
+        let extra = &finalized.extra_outputs[0];
+        assert_eq!((extra.path.as_str(), extra.container), (aiff.as_str(), AudioContainer::Aiff));
+        assert_eq!(extra.error, None);
+        assert_eq!(extra.size_bytes, std::fs::metadata(&aiff).unwrap().len());
+        assert!(extra.size_bytes > 960 * 5 * 2);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&aiff);
+    }
+
+    #[test]
+    fn stop_result_points_at_the_manifest() {
+        let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-manifested.wav", std::process::id()));
+        let device = DeviceManifest {
+            name: Some("Test Mic".to_string()),
+            id: None,
+            sample_rate: 48000,
+            channels: 1,
+            sample_format: "i16".to_string(),
+            input_volume: None,
+        };
+        let manifest = RecordingManifest::new("test".to_string(), device, RecordingConfig::default(), None);
+        let settings = EncoderSettings { manifest: Some(manifest), write_peaks: false, ..encoder_settings(&path) };
+        let finalized = finalize(settings, vec![1000; 960 * 5]);
+
+        let expected = manifest::manifest_path(&path);
+        assert_eq!(finalized.manifest_path.as_deref(), Some(&*expected.to_string_lossy()));
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&expected).unwrap()).unwrap();
+        assert_eq!(written["size_bytes"].as_u64(), Some(finalized.size_bytes));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&expected);
+    }
+
+    #[test]
+    fn partial_frame_modes_write_the_expected_samples() {
+        // Two whole 20 ms frames and 500 samples of a third
+        let captured: Vec<i16> = (0..960 * 2 + 500).map(|i| (i % 2000) as i16 + 1).collect();
+        for (mode, expected) in [
+            (PartialFrame::Pad, 960 * 3),
+            (PartialFrame::Truncate, 960 * 2),
+            (PartialFrame::WritePartial, 960 * 2 + 500),
+        ] {
+            let path = std::env::temp_dir().join(format!("dpc-recorder-test-{}-partial-{:?}.wav", std::process::id(), mode));
+            let settings = EncoderSettings { partial_frame: mode, write_peaks: false, ..encoder_settings(&path) };
+            let (sample_tx, sample_rx) = queue::bounded::<AudioSample>(8);
+            sample_tx.send(AudioSample::Data(captured.clone())).unwrap();
+            sample_tx.send(AudioSample::Stop).unwrap();
+            encoder_thread(sample_rx, settings, Arc::new(SessionMeters::new())).unwrap();
+
+            let samples = WavReader::open(path.to_str().unwrap()).unwrap().read_samples().unwrap();
+            assert_eq!(samples.len(), expected, "{:?}", mode);
+            let real = expected.min(captured.len());
+            assert_eq!(samples[..real], captured[..real], "{:?}", mode);
+            assert!(samples[real..].iter().all(|&s| s == 0), "{:?} pads with silence", mode);
+            let _ = std::fs::remove_file(&path);
+        }
+
+        // WAV is sample-accurate; Opus packets must stay whole frames
+        assert_eq!(RecordingFormat::Wav.default_partial_frame(), PartialFrame::WritePartial);
+        assert_eq!(RecordingFormat::Opus.default_partial_frame(), PartialFrame::Pad);
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn exclusive_mode_is_refused_before_any_device_opens() {
+        let config = RecordingConfig { exclusive_mode: true, ..RecordingConfig::default() };
+        let dir = std::env::temp_dir().to_string_lossy().to_string();
+        let err = start_recording(dir, 0, config).unwrap_err();
+        assert_eq!(err.kind(), "unsupported", "{}", err);
+        assert!(check_exclusive_mode(&RecordingConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn concurrent_starts_let_exactly_one_through() {
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let starts: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let started = begin_start(&RecordingConfig::default());
+                    // The winner keeps its start open, as it would while
+                    // opening the device, until the other has been answered
+                    thread::sleep(Duration::from_millis(100));
+                    started.map(|_| ())
+                })
+            })
+            .collect();
+        let results: Vec<Result<(), String>> = starts.into_iter().map(|t| t.join().unwrap()).collect();
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1, "{:?}", results);
+        assert!(results.contains(&Err("Already starting".to_string())), "{:?}", results);
+        // Finishing (here, abandoning) the start lets the next one in
+        assert!(!get_global_state().lock().unwrap().is_starting);
+        assert!(begin_start(&RecordingConfig::default()).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+// Sample format conversions to and from the 16-bit samples we store
+//
+// Conventions, shared by every function here:
+// - Integer formats keep their top 16 bits (arithmetic shift, so negative
+//   values round toward -inf). Narrower ones are shifted up, exactly.
+// - Unsigned formats are offset binary: the midpoint (e.g. 32768 for u16) is
+//   silence. They are converted by flipping the sign bit, which is exact.
+// - Floats use the symmetric 32768 scale, so i16 -> f32 -> i16 is the
+//   identity. They round to nearest and clamp to [-1.0, 1.0); +1.0 and
+//   anything above becomes i16::MAX, NaN becomes 0.
+
+/// -1.0..1.0 from a sample, exactly (i16::MIN maps to -1.0)
+pub fn i16_to_f32(sample: i16) -> f32 {
+    sample as f32 / 32768.0
+}
+
+pub fn f32_to_i16(sample: f32) -> i16 {
+    f64_to_i16(sample as f64)
+}
+
+pub fn f64_to_i16(sample: f64) -> i16 {
+    if sample.is_nan() {
+        return 0;
+    }
+    // `as` saturates, so values past full scale clamp rather than wrap
+    (sample * 32768.0).round() as i16
+}
+
+pub fn i8_to_i16(sample: i8) -> i16 {
+    (sample as i16) << 8
+}
+
+pub fn u8_to_i16(sample: u8) -> i16 {
+    i8_to_i16((sample ^ 0x80) as i8)
+}
+
+pub fn u16_to_i16(sample: u16) -> i16 {
+    (sample ^ 0x8000) as i16
+}
+
+/// From 24-bit audio held in the low bits of an i32 (sign-extended)
+pub fn i24_to_i16(sample: i32) -> i16 {
+    (sample >> 8) as i16
+}
+
+pub fn i32_to_i16(sample: i32) -> i16 {
+    (sample >> 16) as i16
+}
+
+pub fn u32_to_i16(sample: u32) -> i16 {
+    i32_to_i16((sample ^ 0x8000_0000) as i32)
+}
+
+pub fn i64_to_i16(sample: i64) -> i16 {
+    (sample >> 48) as i16
+}
+
+pub fn u64_to_i16(sample: u64) -> i16 {
+    i64_to_i16((sample ^ 0x8000_0000_0000_0000) as i64)
+}
+
+/// Every cpal input format the recorder accepts, mapped onto the functions above
+pub trait ToI16: cpal::SizedSample + Send + 'static {
+    fn to_i16(self) -> i16;
+    /// The sample unconverted, as little-endian bytes (for `capture_raw`)
+    fn extend_le_bytes(self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_to_i16 {
+    ($($t:ty => $convert:expr),* $(,)?) => {
+        $(impl ToI16 for $t {
+            fn to_i16(self) -> i16 {
+                $convert(self)
+            }
+
+            fn extend_le_bytes(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        })*
+    };
+}
+impl_to_i16!(
+    i8 => i8_to_i16,
+    i16 => std::convert::identity,
+    i32 => i32_to_i16,
+    i64 => i64_to_i16,
+    u8 => u8_to_i16,
+    u16 => u16_to_i16,
+    u32 => u32_to_i16,
+    u64 => u64_to_i16,
+    f32 => f32_to_i16,
+    f64 => f64_to_i16,
+);
+
+/// `$open::<T>(args)` with `T` the `ToI16` type for a `cpal::SampleFormat`,
+/// so every capture path accepts the same formats. Any other format is an
+/// `Err(String)`.
+macro_rules! with_sample_type {
+    ($format:expr, $open:ident($($arg:expr),* $(,)?)) => {
+        match $format {
+            cpal::SampleFormat::I8 => $open::<i8>($($arg),*),
+            cpal::SampleFormat::I16 => $open::<i16>($($arg),*),
+            cpal::SampleFormat::I32 => $open::<i32>($($arg),*),
+            cpal::SampleFormat::I64 => $open::<i64>($($arg),*),
+            cpal::SampleFormat::U8 => $open::<u8>($($arg),*),
+            cpal::SampleFormat::U16 => $open::<u16>($($arg),*),
+            cpal::SampleFormat::U32 => $open::<u32>($($arg),*),
+            cpal::SampleFormat::U64 => $open::<u64>($($arg),*),
+            cpal::SampleFormat::F32 => $open::<f32>($($arg),*),
+            cpal::SampleFormat::F64 => $open::<f64>($($arg),*),
+            other => Err(format!("Unsupported sample format: {}", other)),
+        }
+    };
+}
+pub(crate) use with_sample_type;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn i16_through_f32_is_the_identity() {
+        for sample in i16::MIN..=i16::MAX {
+            assert_eq!(f32_to_i16(i16_to_f32(sample)), sample);
+        }
+    }
+
+    #[test]
+    fn float_extremes() {
+        assert_eq!(i16_to_f32(i16::MIN), -1.0);
+        assert!(i16_to_f32(i16::MAX) < 1.0);
+        assert_eq!(f32_to_i16(-1.0), i16::MIN);
+        assert_eq!(f32_to_i16(1.0), i16::MAX);
+        assert_eq!(f32_to_i16(1.5), i16::MAX);
+        assert_eq!(f32_to_i16(-1.5), i16::MIN);
+        assert_eq!(f32_to_i16(f32::INFINITY), i16::MAX);
+        assert_eq!(f32_to_i16(f32::NAN), 0);
+        assert_eq!(f64_to_i16(f64::NAN), 0);
+        assert_eq!(f32_to_i16(0.0), 0);
+    }
+
+    #[test]
+    fn integer_extremes() {
+        assert_eq!(i32_to_i16(i32::MIN), i16::MIN);
+        assert_eq!(i32_to_i16(i32::MAX), i16::MAX);
+        assert_eq!(i64_to_i16(i64::MIN), i16::MIN);
+        assert_eq!(i64_to_i16(i64::MAX), i16::MAX);
+        assert_eq!(i8_to_i16(i8::MIN), i16::MIN);
+        assert_eq!(i8_to_i16(i8::MAX), 127 << 8);
+        // 24-bit full scale
+        assert_eq!(i24_to_i16(-0x80_0000), i16::MIN);
+        assert_eq!(i24_to_i16(0x7F_FFFF), i16::MAX);
+        assert_eq!(i24_to_i16(0), 0);
+    }
+
+    #[test]
+    fn unsigned_midpoint_is_silence() {
+        assert_eq!(u16_to_i16(0), i16::MIN);
+        assert_eq!(u16_to_i16(32768), 0);
+        assert_eq!(u16_to_i16(65535), i16::MAX);
+        assert_eq!(u8_to_i16(0), i16::MIN);
+        assert_eq!(u8_to_i16(128), 0);
+        assert_eq!(u8_to_i16(255), 127 << 8);
+        assert_eq!(u32_to_i16(0), i16::MIN);
+        assert_eq!(u32_to_i16(0x8000_0000), 0);
+        assert_eq!(u32_to_i16(u32::MAX), i16::MAX);
+        assert_eq!(u64_to_i16(1 << 63), 0);
+    }
+}
@@ -0,0 +1,217 @@
+// Encrypted-at-rest recordings (`.wav.enc`)
+//
+// On-disk framing, version 1:
+//
+//   header  "DPCE" | version: u8 = 1 | chunk_size: u32 LE | nonce prefix: 19 bytes
+//   chunks  each sealed with XChaCha20-Poly1305: ciphertext + 16-byte tag.
+//           Every chunk holds `chunk_size` plaintext bytes except the last,
+//           which holds the remainder (possibly none).
+//
+// Chunk nonces follow the STREAM construction (`aead::stream`, big-endian
+// 32-bit counter): prefix | counter | last flag, with the flag set only on
+// the final chunk. Reordered or dropped chunks fail authentication, and a
+// file cut short at a chunk boundary fails because it has no final chunk.
+//
+// The plaintext is an ordinary WAV, except that its RIFF and data sizes are
+// left zero: sealed chunks can't be patched in place (that would reuse a
+// nonce). `decrypt_file` fills them in from the decrypted length.
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use std::fs::File;
+use std::io::{self, Write};
+
+use super::wav;
+
+const MAGIC: &[u8; 4] = b"DPCE";
+const VERSION: u8 = 1;
+const CHUNK_SIZE: usize = 64 * 1024;
+const NONCE_PREFIX_BYTES: usize = 19;
+const TAG_BYTES: usize = 16;
+const HEADER_BYTES: usize = 4 + 1 + 4 + NONCE_PREFIX_BYTES;
+
+/// 256-bit key, given as 64 hex characters. Never serialized, and redacted
+/// from `Debug`, so configs and presets don't leak it.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err("Encryption key must be 64 hex characters".to_string());
+        }
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| "Encryption key must be 64 hex characters".to_string())?;
+        }
+        Ok(Self(key))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new((&self.0).into())
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EncryptionKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Self::from_hex(&hex).map_err(serde::de::Error::custom)
+    }
+}
+
+/// File size for `plaintext` bytes of WAV
+pub fn sealed_len(plaintext: u64) -> u64 {
+    let chunks = plaintext / CHUNK_SIZE as u64 + 1; // The last chunk always exists
+    HEADER_BYTES as u64 + plaintext + chunks * TAG_BYTES as u64
+}
+
+fn crypto_error(_: chacha20poly1305::aead::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Encryption failed or the key is wrong")
+}
+
+/// Seals plaintext into chunks as it arrives. Up to one chunk stays in
+/// memory until `finish`, so `sync` only makes the sealed chunks durable.
+pub struct EncryptedWriter {
+    file: File,
+    encryptor: EncryptorBE32<XChaCha20Poly1305>,
+    buffer: Vec<u8>,
+    sealed: u64, // Plaintext bytes already written out
+}
+
+impl EncryptedWriter {
+    pub fn create(path: &str, key: &EncryptionKey) -> io::Result<Self> {
+        let mut prefix = [0u8; NONCE_PREFIX_BYTES];
+        OsRng.fill_bytes(&mut prefix);
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&(CHUNK_SIZE as u32).to_le_bytes())?;
+        file.write_all(&prefix)?;
+        Ok(Self {
+            file,
+            encryptor: EncryptorBE32::from_aead(key.cipher(), (&prefix).into()),
+            buffer: Vec::with_capacity(CHUNK_SIZE * 2),
+            sealed: 0,
+        })
+    }
+
+    // Keep at least one byte back so the final chunk is never sealed early
+    fn seal_full_chunks(&mut self) -> io::Result<()> {
+        while self.buffer.len() > CHUNK_SIZE {
+            let chunk = self.encryptor.encrypt_next(&self.buffer[..CHUNK_SIZE]).map_err(crypto_error)?;
+            self.file.write_all(&chunk)?;
+            self.buffer.drain(..CHUNK_SIZE);
+            self.sealed += CHUNK_SIZE as u64;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Keep the first `len` plaintext bytes. Only bytes not yet sealed can
+    /// be dropped.
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        if len < self.sealed {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Cannot cut an encrypted recording before its last chunk",
+            ));
+        }
+        self.buffer.truncate((len - self.sealed) as usize);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let last = self.encryptor.encrypt_last(self.buffer.as_slice()).map_err(crypto_error)?;
+        self.file.write_all(&last)?;
+        self.file.sync_data()
+    }
+}
+
+impl Write for EncryptedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.seal_full_chunks()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decrypt a `.wav.enc` into WAV bytes in memory, with sizes filled in
+pub fn decrypt_file(path: &str, key: &EncryptionKey) -> io::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    if data.len() < HEADER_BYTES + TAG_BYTES || &data[..4] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an encrypted recording"));
+    }
+    if data[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unsupported encrypted recording version {}", data[4]),
+        ));
+    }
+    let chunk_size = u32::from_le_bytes(data[5..9].try_into().unwrap()) as usize;
+    if chunk_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid chunk size"));
+    }
+    let prefix: [u8; NONCE_PREFIX_BYTES] = data[9..HEADER_BYTES].try_into().unwrap();
+    let mut decryptor = DecryptorBE32::from_aead(key.cipher(), (&prefix).into());
+
+    let mut chunks = data[HEADER_BYTES..].chunks(chunk_size + TAG_BYTES).peekable();
+    let mut plaintext = Vec::with_capacity(data.len());
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_some() {
+            plaintext.extend(decryptor.decrypt_next(chunk).map_err(crypto_error)?);
+        } else {
+            plaintext.extend(decryptor.decrypt_last(chunk).map_err(crypto_error)?);
+            break;
+        }
+    }
+
+    patch_wav_sizes(&mut plaintext);
+    Ok(plaintext)
+}
+
+/// Export a plain WAV copy, e.g. to share outside the app
+pub fn decrypt_to_file(input: &str, output: &str, key: &EncryptionKey) -> Result<(), String> {
+    let wav = decrypt_file(input, key)
+        .map_err(|e| format!("Failed to decrypt {}: {}", input, e))?;
+    std::fs::write(output, wav)
+        .map_err(|e| format!("Failed to write {}: {}", output, e))
+}
+
+// Fill in the RIFF size and the size of a trailing data chunk
+fn patch_wav_sizes(wav: &mut [u8]) {
+    if wav.len() < 12 || &wav[..4] != b"RIFF" {
+        return;
+    }
+    let mut block_align = 0;
+    let mut pos = 12;
+    while pos + 8 <= wav.len() {
+        let size = u32::from_le_bytes(wav[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        if &wav[pos..pos + 4] == b"fmt " && pos + 22 <= wav.len() {
+            block_align = u16::from_le_bytes([wav[pos + 20], wav[pos + 21]]);
+        }
+        if &wav[pos..pos + 4] == b"data" {
+            let data_size = (wav.len() - pos - 8) as u64;
+            // Writing into a slice of the right length cannot fail
+            let _ = wav::write_wav_sizes(&mut io::Cursor::new(wav), pos as u64 + 4, data_size, block_align, true);
+            return;
+        }
+        pos += 8 + ((size + 1) & !1);
+    }
+}
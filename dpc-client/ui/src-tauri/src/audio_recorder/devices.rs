@@ -0,0 +1,102 @@
+// Input device enumeration with stable identifiers
+//
+// Names are not unique (two identical USB mics report the same one), so
+// where the platform exposes something stable it is reported as `id`.
+// cpal has no API for this; each platform gets a `DeviceIdSource`, and the
+// fallback reports `None`, leaving callers to match by name.
+//
+// Linux: for ALSA names carrying `CARD=<id>`, the USB vendor, product and
+// serial from sysfs, or else the card's sysfs path (stable per port).
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::Device;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub id: Option<String>, // Stable across reboots and replugging, where known
+    pub is_default: bool,
+}
+
+trait DeviceIdSource {
+    fn device_id(&self, name: &str) -> Option<String>;
+}
+
+#[cfg(not(target_os = "linux"))]
+struct NameOnly;
+
+#[cfg(not(target_os = "linux"))]
+impl DeviceIdSource for NameOnly {
+    fn device_id(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct Sysfs;
+
+#[cfg(target_os = "linux")]
+impl DeviceIdSource for Sysfs {
+    fn device_id(&self, name: &str) -> Option<String> {
+        use std::path::Path;
+
+        // e.g. "hw:CARD=Microphone,DEV=0"; /proc/asound/<id> links to "cardN"
+        let card = name.split([':', ',']).find_map(|part| part.strip_prefix("CARD="))?;
+        let card_dir = std::fs::read_link(Path::new("/proc/asound").join(card)).ok()?;
+        let device = std::fs::canonicalize(Path::new("/sys/class/sound").join(card_dir).join("device")).ok()?;
+
+        // For USB audio `device` is an interface; the descriptors sit on its parent
+        let usb = device.parent()?;
+        let read = |file: &str| std::fs::read_to_string(usb.join(file)).ok().map(|s| s.trim().to_string());
+        match (read("idVendor"), read("idProduct"), read("serial")) {
+            (Some(vendor), Some(product), Some(serial)) => Some(format!("usb:{}:{}:{}", vendor, product, serial)),
+            _ => Some(format!("sysfs:{}", device.display())),
+        }
+    }
+}
+
+fn id_source() -> &'static dyn DeviceIdSource {
+    #[cfg(target_os = "linux")]
+    return &Sysfs;
+    #[cfg(not(target_os = "linux"))]
+    return &NameOnly;
+}
+
+/// Stable id for the device named `name`, where the platform has one
+pub fn device_id(name: &str) -> Option<String> {
+    id_source().device_id(name)
+}
+
+/// Input devices in the host's order
+pub fn list_input_devices() -> Result<Vec<AudioDeviceInfo>, String> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let devices = host.input_devices()
+        .map_err(|e| format!("Failed to list input devices: {}", e))?;
+    Ok(devices
+        .filter_map(|d| d.name().ok())
+        .map(|name| AudioDeviceInfo {
+            id: id_source().device_id(&name),
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+        })
+        .collect())
+}
+
+/// Find an input by stable id or, failing that, by name; the default
+/// input when `key` is `None`
+pub fn find_input_device(key: Option<&str>) -> Result<Device, String> {
+    let host = cpal::default_host();
+    let Some(key) = key else {
+        return host.default_input_device().ok_or_else(|| "No audio input device found".to_string());
+    };
+    let mut devices: Vec<Device> = host.input_devices()
+        .map_err(|e| format!("Failed to list input devices: {}", e))?
+        .collect();
+    let name_of = |d: &Device| d.name().unwrap_or_default();
+    let by_id = devices.iter().position(|d| id_source().device_id(&name_of(d)).as_deref() == Some(key));
+    match by_id.or_else(|| devices.iter().position(|d| name_of(d) == key)) {
+        Some(index) if index < devices.len() => Ok(devices.swap_remove(index)),
+        _ => Err(format!("Input device not found: {}", key)),
+    }
+}
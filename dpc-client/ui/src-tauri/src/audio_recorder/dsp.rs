@@ -0,0 +1,498 @@
+// Sample-level helpers shared by live capture and the file utilities
+
+/// RMS of a frame, normalized to 0.0-1.0 of full scale
+pub fn frame_rms(frame: &[i16]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    let sum_sq: f64 = frame.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    ((sum_sq / frame.len() as f64).sqrt() / 32768.0) as f32
+}
+
+/// RMS of each channel of an interleaved frame, in channel order
+pub fn channel_rms(frame: &[i16], channels: usize) -> Vec<f32> {
+    (0..channels)
+        .map(|ch| {
+            let samples: Vec<i16> = frame.iter().skip(ch).step_by(channels).copied().collect();
+            frame_rms(&samples)
+        })
+        .collect()
+}
+
+/// Largest absolute sample in a frame, as 0.0-1.0 of full scale
+pub fn frame_peak(frame: &[i16]) -> f32 {
+    frame.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0) as f32 / 32768.0
+}
+
+/// Samples at full scale in either direction, i.e. likely clipped
+pub fn count_clipped(frame: &[i16]) -> usize {
+    frame.iter().filter(|&&s| s == i16::MAX || s <= -i16::MAX).count()
+}
+
+/// Mix one interleaved frame (one sample per channel) down to mono by averaging
+pub fn downmix(chunk: &[i16]) -> i16 {
+    let sum: f32 = chunk.iter().map(|&s| s as f32).sum();
+    (sum / chunk.len() as f32) as i16
+}
+
+/// Mix one interleaved frame down to mono with a gain per channel
+/// (e.g. `[1.0, 0.0]` keeps only the first input)
+pub fn downmix_weighted(chunk: &[i16], weights: &[f32]) -> i16 {
+    let sum: f32 = chunk.iter().zip(weights).map(|(&s, &w)| s as f32 * w).sum();
+    sum.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Widest `stereo_width` accepted; beyond this the side channel dominates
+/// and mono compatibility collapses
+pub const MAX_STEREO_WIDTH: f32 = 2.0;
+
+/// Scale the side (L-R) component of interleaved stereo: 0.0 gives mono,
+/// 1.0 leaves the samples untouched, above 1.0 widens
+pub fn apply_stereo_width(samples: &mut [i16], width: f32) {
+    let width = width.clamp(0.0, MAX_STEREO_WIDTH);
+    if width == 1.0 {
+        return;
+    }
+    for pair in samples.chunks_exact_mut(2) {
+        let mid = (pair[0] as f32 + pair[1] as f32) / 2.0;
+        let side = (pair[0] as f32 - pair[1] as f32) / 2.0 * width;
+        pair[0] = (mid + side).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        pair[1] = (mid - side).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// Floor reported for digital silence instead of -inf dBFS
+pub const MIN_DBFS: f32 = -90.0;
+
+/// Convert a 0.0-1.0 amplitude to dBFS, clamped at `MIN_DBFS`
+pub fn linear_to_dbfs(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        return MIN_DBFS;
+    }
+    (20.0 * linear.log10()).max(MIN_DBFS)
+}
+
+/// Convert dBFS to a 0.0-1.0 amplitude (0 dBFS = full scale)
+pub fn dbfs_to_linear(dbfs: f32) -> f32 {
+    if dbfs <= MIN_DBFS {
+        return 0.0;
+    }
+    10f32.powf(dbfs.min(0.0) / 20.0)
+}
+
+/// Convert a gain in dB to a linear factor (unlike `dbfs_to_linear`, boosts
+/// above 0 dB are allowed)
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Scale a sample, clamping instead of wrapping on overflow
+pub fn apply_gain(sample: i16, gain: f32) -> i16 {
+    (sample as f32 * gain).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+// Fade at each end of a tone, so it starts and stops without a click
+const TONE_RAMP_MS: f32 = 5.0;
+
+/// A sine tone as interleaved samples, the same on every channel
+pub fn tone(sample_rate: u32, channels: u16, hz: f32, duration_ms: u64, dbfs: f32) -> Vec<i16> {
+    let frames = (sample_rate as u64 * duration_ms / 1000) as usize;
+    let ramp = (sample_rate as f32 * TONE_RAMP_MS / 1000.0).min(frames as f32 / 2.0).max(1.0);
+    let amplitude = dbfs_to_linear(dbfs) * i16::MAX as f32;
+    let step = std::f32::consts::TAU * hz / sample_rate as f32;
+    (0..frames)
+        .flat_map(|i| {
+            let envelope = (i.min(frames - 1 - i) as f32 / ramp).min(1.0);
+            let sample = ((i as f32 * step).sin() * amplitude * envelope).round() as i16;
+            std::iter::repeat_n(sample, channels as usize)
+        })
+        .collect()
+}
+
+/// Triangular (TPDF) dither: the difference of two uniform values, spanning
+/// -1..1 step of the target depth. A fixed-seed xorshift, so re-encoding
+/// the same file always gives the same output.
+pub struct TpdfDither {
+    state: u64,
+}
+
+impl Default for TpdfDither {
+    fn default() -> Self {
+        Self { state: 0x9E37_79B9_7F4A_7C15 }
+    }
+}
+
+impl TpdfDither {
+    /// Noise in steps of the target depth; scale by one step before adding
+    pub fn next_steps(&mut self) -> f64 {
+        self.uniform() - self.uniform()
+    }
+
+    // 0.0..1.0 from the top 53 bits of a xorshift64*
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The one definition of "silent" used by every silence-related feature.
+/// Accepts either unit from the UI; comparisons always happen on linear RMS.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SilenceThreshold {
+    Dbfs(f32),
+    Linear(f32),
+}
+
+impl Default for SilenceThreshold {
+    fn default() -> Self {
+        SilenceThreshold::Dbfs(-50.0)
+    }
+}
+
+impl SilenceThreshold {
+    pub fn to_linear(self) -> f32 {
+        match self {
+            SilenceThreshold::Dbfs(db) => dbfs_to_linear(db),
+            SilenceThreshold::Linear(l) => l.clamp(0.0, 1.0),
+        }
+    }
+
+    /// True when a linear RMS level (as from `frame_rms`) is below the threshold
+    pub fn is_silent(self, level: f32) -> bool {
+        level < self.to_linear()
+    }
+}
+
+/// Linear gain that moves toward its target over a fixed time instead of
+/// jumping, so muting doesn't click
+pub struct GainRamp {
+    gain: f32,
+    step: f32, // Per frame of samples
+}
+
+impl GainRamp {
+    pub fn new(sample_rate: u32, ramp_ms: u32) -> Self {
+        let ramp_frames = (sample_rate as u64 * ramp_ms as u64 / 1000).max(1);
+        Self { gain: 1.0, step: 1.0 / ramp_frames as f32 }
+    }
+
+    /// Scale interleaved `samples`, stepping toward `target` (0.0-1.0) once
+    /// per frame so all channels move together
+    pub fn apply(&mut self, samples: &mut [i16], channels: usize, target: f32) {
+        if self.gain == target {
+            if target < 1.0 {
+                samples.iter_mut().for_each(|s| *s = apply_gain(*s, target));
+            }
+            return;
+        }
+        for frame in samples.chunks_mut(channels.max(1)) {
+            self.gain = if self.gain < target {
+                (self.gain + self.step).min(target)
+            } else {
+                (self.gain - self.step).max(target)
+            };
+            for sample in frame {
+                *sample = apply_gain(*sample, self.gain);
+            }
+        }
+    }
+}
+
+/// Speaking/not-speaking from per-frame levels, with hysteresis so the
+/// state doesn't flicker: it opens on a frame at or above `open` and closes
+/// once `hold_frames` frames in a row fall below the lower `close`
+pub struct SpeakingDetector {
+    open: f32, // Linear RMS
+    close: f32,
+    hold_frames: u32,
+    quiet_frames: u32,
+    speaking: bool,
+}
+
+impl SpeakingDetector {
+    pub fn new(open: SilenceThreshold, close: SilenceThreshold, hold_frames: u32) -> Self {
+        Self {
+            open: open.to_linear(),
+            close: close.to_linear(),
+            hold_frames,
+            quiet_frames: 0,
+            speaking: false,
+        }
+    }
+
+    /// Feed one frame's RMS; returns the new state when it changes
+    pub fn update(&mut self, level: f32) -> Option<bool> {
+        if !self.speaking {
+            self.speaking = level >= self.open;
+            return self.speaking.then_some(true);
+        }
+        if level >= self.close {
+            self.quiet_frames = 0;
+            return None;
+        }
+        self.quiet_frames += 1;
+        if self.quiet_frames < self.hold_frames {
+            return None;
+        }
+        self.quiet_frames = 0;
+        self.speaking = false;
+        Some(false)
+    }
+}
+
+/// Gain that steps down while the signal keeps clipping and never comes
+/// back up: once `sustain_frames` of a window of `window_frames` frames
+/// clip, it drops by `step_db`, no lower than `floor_db`
+pub struct ClipGuard {
+    gain_db: f32,
+    step_db: f32,
+    floor_db: f32,
+    window_frames: u32,
+    sustain_frames: u32,
+    frames: u32,
+    clipped_frames: u32,
+}
+
+impl ClipGuard {
+    pub fn new(step_db: f32, floor_db: f32, window_frames: u32, sustain_frames: u32) -> Self {
+        Self {
+            gain_db: 0.0,
+            step_db,
+            floor_db,
+            window_frames,
+            sustain_frames,
+            frames: 0,
+            clipped_frames: 0,
+        }
+    }
+
+    /// Feed whether one frame clipped; returns the new gain in dB when it
+    /// steps down
+    pub fn update(&mut self, clipped: bool) -> Option<f32> {
+        self.frames += 1;
+        self.clipped_frames += clipped as u32;
+        let sustained = self.clipped_frames >= self.sustain_frames;
+        if sustained || self.frames >= self.window_frames {
+            // Each step needs a fresh window, so one burst lowers it once
+            self.frames = 0;
+            self.clipped_frames = 0;
+        }
+        if !sustained || self.gain_db <= self.floor_db {
+            return None;
+        }
+        self.gain_db = (self.gain_db - self.step_db).max(self.floor_db);
+        Some(self.gain_db)
+    }
+}
+
+/// Downward compressor settings (all levels in dBFS / dB)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CompressorConfig {
+    pub threshold_db: f32,
+    pub ratio: f32,      // 3.0 = 3 dB over the threshold comes out as 1 dB
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorConfig {
+    // Gentle voice settings: evens out speech without pumping
+    fn default() -> Self {
+        Self {
+            threshold_db: -20.0,
+            ratio: 3.0,
+            attack_ms: 5.0,
+            release_ms: 100.0,
+            makeup_db: 3.0,
+        }
+    }
+}
+
+impl CompressorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [self.threshold_db, self.ratio, self.attack_ms, self.release_ms, self.makeup_db];
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err("Compressor settings must be finite numbers".to_string());
+        }
+        if self.threshold_db > 0.0 {
+            return Err(format!("Compressor threshold must be at most 0 dBFS, got {}", self.threshold_db));
+        }
+        if self.ratio < 1.0 {
+            return Err(format!("Compressor ratio must be at least 1, got {}", self.ratio));
+        }
+        if self.attack_ms <= 0.0 || self.release_ms <= 0.0 {
+            return Err("Compressor attack and release must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Per-sample compressor with a peak envelope follower. Channels share one
+/// detector so the stereo image doesn't shift when one side is louder.
+pub struct Compressor {
+    config: CompressorConfig,
+    attack: f32,  // Smoothing coefficients per sample frame
+    release: f32,
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(config: CompressorConfig, sample_rate: u32) -> Self {
+        let coefficient = |ms: f32| (-1.0 / (ms / 1000.0 * sample_rate as f32)).exp();
+        Self {
+            attack: coefficient(config.attack_ms),
+            release: coefficient(config.release_ms),
+            config,
+            reduction_db: 0.0,
+        }
+    }
+
+    /// Compress interleaved samples in place
+    pub fn process(&mut self, samples: &mut [i16], channels: usize) {
+        let CompressorConfig { threshold_db, ratio, makeup_db, .. } = self.config;
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let peak = frame.iter().map(|&s| (s as f32).abs()).fold(0.0, f32::max) / 32768.0;
+            let over = linear_to_dbfs(peak) - threshold_db;
+            let target = if over > 0.0 { over * (1.0 - 1.0 / ratio) } else { 0.0 };
+            let coefficient = if target > self.reduction_db { self.attack } else { self.release };
+            self.reduction_db = target + coefficient * (self.reduction_db - target);
+
+            let gain = db_to_gain(makeup_db - self.reduction_db);
+            for sample in frame.iter_mut() {
+                *sample = apply_gain(*sample, gain);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_threshold_parses_either_unit() {
+        let db: SilenceThreshold = serde_json::from_str(r#"{"dbfs": -40.0}"#).unwrap();
+        let linear: SilenceThreshold = serde_json::from_str(r#"{"linear": 0.01}"#).unwrap();
+        assert_eq!(db, SilenceThreshold::Dbfs(-40.0));
+        assert_eq!(linear, SilenceThreshold::Linear(0.01));
+        assert!(serde_json::from_str::<SilenceThreshold>(r#"{"percent": 5}"#).is_err());
+        // -40 dBFS and 0.01 linear are the same level
+        assert!((db.to_linear() - linear.to_linear()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn silence_threshold_boundaries() {
+        assert_eq!(SilenceThreshold::Dbfs(0.0).to_linear(), 1.0);
+        assert_eq!(SilenceThreshold::Dbfs(6.0).to_linear(), 1.0);
+        assert_eq!(SilenceThreshold::Dbfs(MIN_DBFS).to_linear(), 0.0);
+        assert_eq!(SilenceThreshold::Dbfs(-200.0).to_linear(), 0.0);
+        assert_eq!(SilenceThreshold::Linear(-0.5).to_linear(), 0.0);
+        assert_eq!(SilenceThreshold::Linear(1.5).to_linear(), 1.0);
+
+        // Exactly at the threshold is not silent; a zero threshold silences nothing
+        let threshold = SilenceThreshold::Linear(0.25);
+        assert!(threshold.is_silent(0.2499));
+        assert!(!threshold.is_silent(0.25));
+        assert!(!SilenceThreshold::Dbfs(MIN_DBFS).is_silent(0.0));
+    }
+
+    #[test]
+    fn stereo_width_extremes() {
+        let stereo: Vec<i16> = (0..200).map(|i| if i % 2 == 0 { i * 100 } else { -(i * 70) }).collect();
+
+        let mut mono = stereo.clone();
+        apply_stereo_width(&mut mono, 0.0);
+        assert!(mono.chunks_exact(2).all(|pair| pair[0] == pair[1]));
+
+        let mut unchanged = stereo.clone();
+        apply_stereo_width(&mut unchanged, 1.0);
+        assert_eq!(unchanged, stereo);
+
+        // Out-of-range widths clamp rather than inverting or exploding the image
+        let mut negative = stereo.clone();
+        apply_stereo_width(&mut negative, -3.0);
+        assert_eq!(negative, mono);
+        let (mut wide, mut widest) = (stereo.clone(), stereo.clone());
+        apply_stereo_width(&mut wide, MAX_STEREO_WIDTH);
+        apply_stereo_width(&mut widest, 100.0);
+        assert_eq!(wide, widest);
+    }
+
+    // Output level of a steady full-band signal at `input_dbfs` once the
+    // envelope has settled (a square wave, so the peak follower sees no ripple)
+    fn compressed_level(config: CompressorConfig, input_dbfs: f32) -> f32 {
+        let amplitude = (dbfs_to_linear(input_dbfs) * 32768.0).round() as i16;
+        let mut samples: Vec<i16> = (0..48000).map(|i| if i % 2 == 0 { amplitude } else { -amplitude }).collect();
+        Compressor::new(config, 48000).process(&mut samples, 1);
+        linear_to_dbfs(frame_peak(&samples[samples.len() - 480..]))
+    }
+
+    #[test]
+    fn compressor_static_curve() {
+        let config = CompressorConfig { threshold_db: -20.0, ratio: 4.0, makeup_db: 0.0, ..CompressorConfig::default() };
+        // 12 dB over the threshold comes out 3 dB over it
+        assert!((compressed_level(config, -8.0) - -17.0).abs() < 0.1);
+        assert!((compressed_level(config, -14.0) - -18.5).abs() < 0.1);
+        // Below the threshold only makeup gain applies
+        assert!((compressed_level(config, -30.0) - -30.0).abs() < 0.1);
+        let makeup = CompressorConfig { makeup_db: 6.0, ..config };
+        assert!((compressed_level(makeup, -30.0) - -24.0).abs() < 0.1);
+        // Ratio 1 is a pass-through
+        let unity = CompressorConfig { ratio: 1.0, ..config };
+        assert!((compressed_level(unity, -3.0) - -3.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn dbfs_linear_round_trip() {
+        assert_eq!(linear_to_dbfs(0.0), MIN_DBFS);
+        assert_eq!(linear_to_dbfs(1.0), 0.0);
+        assert!((dbfs_to_linear(-6.0206) - 0.5).abs() < 1e-4);
+        for db in [-0.1f32, -6.0, -20.0, -50.0, -89.0] {
+            assert!((linear_to_dbfs(dbfs_to_linear(db)) - db).abs() < 1e-3, "{} dBFS", db);
+        }
+    }
+
+    #[test]
+    fn mute_ramps_a_steady_tone() {
+        const RAMP_FRAMES: usize = 480; // 10 ms at 48 kHz
+        // Stereo 1 kHz tone in 20 ms chunks, muted and then unmuted
+        let tone: Vec<i16> = (0..48000)
+            .flat_map(|i| {
+                let s = ((i as f64 * std::f64::consts::TAU * 1000.0 / 48000.0).sin() * 16000.0).round() as i16;
+                [s, s]
+            })
+            .collect();
+        let mut ramp = GainRamp::new(48000, 10);
+        let mut output = tone.clone();
+        let (muted, unmuted) = output.split_at_mut(tone.len() / 2);
+        for (chunk, target) in muted.chunks_mut(1920).map(|c| (c, 0.0)).chain(unmuted.chunks_mut(1920).map(|c| (c, 1.0))) {
+            ramp.apply(chunk, 2, target);
+        }
+
+        // Gain per frame, read off the samples loud enough to measure it
+        let gains: Vec<(usize, f32)> = (0..48000)
+            .filter(|&i| tone[i * 2].unsigned_abs() > 4000)
+            .map(|i| {
+                assert_eq!(output[i * 2], output[i * 2 + 1], "channels diverge at frame {}", i);
+                (i, output[i * 2] as f32 / tone[i * 2] as f32)
+            })
+            .collect();
+        let expected = |i: usize| {
+            let steps = |from: usize| ((i + 1 - from) as f32 / RAMP_FRAMES as f32).min(1.0);
+            if i < 24000 { 1.0 - steps(0) } else { steps(24000) }
+        };
+        for &(i, gain) in &gains {
+            assert!((gain - expected(i)).abs() < 2e-3, "frame {}: gain {} expected {}", i, gain, expected(i));
+        }
+        // Fully silent once the ramp is done, and untouched once unmuted
+        assert!(output[RAMP_FRAMES * 2..48000].iter().all(|&s| s == 0));
+        assert_eq!(output[(24000 + RAMP_FRAMES) * 2..], tone[(24000 + RAMP_FRAMES) * 2..]);
+        // No jump anywhere bigger than the tone's own steepest step
+        let max_step = |samples: &[i16]| samples.windows(3).map(|w| (w[2] as i32 - w[0] as i32).abs()).max().unwrap();
+        assert!(max_step(&output) <= max_step(&tone));
+    }
+}
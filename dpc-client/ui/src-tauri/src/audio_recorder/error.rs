@@ -0,0 +1,71 @@
+// Errors the frontend needs to tell apart
+//
+// Serialized as `{ kind, message }` so the UI can branch on `kind` (e.g. show
+// a "hold to record" hint for `too_short`) and still display `message`.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecorderError {
+    /// Stopped before `min_duration_ms` of audio; the file was discarded
+    TooShort { duration_ms: u64, min_duration_ms: u64 },
+    /// Stopped before any audio arrived; no file was kept
+    EmptyRecording,
+    /// The file is not a WAV we can safely send or read
+    InvalidWav(String),
+    /// The platform offers no way to do this (e.g. OS input volume)
+    Unsupported(String),
+    Other(String),
+}
+
+impl RecorderError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RecorderError::TooShort { .. } => "too_short",
+            RecorderError::EmptyRecording => "empty_recording",
+            RecorderError::InvalidWav(_) => "invalid_wav",
+            RecorderError::Unsupported(_) => "unsupported",
+            RecorderError::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecorderError::TooShort { duration_ms, min_duration_ms } => write!(
+                f,
+                "Recording too short ({} ms, minimum {} ms)",
+                duration_ms, min_duration_ms
+            ),
+            RecorderError::EmptyRecording => f.write_str("Recording stopped before any audio was captured"),
+            RecorderError::InvalidWav(msg) => write!(f, "Invalid WAV file: {}", msg),
+            RecorderError::Unsupported(msg) => write!(f, "Not supported on this system: {}", msg),
+            RecorderError::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+impl From<String> for RecorderError {
+    fn from(msg: String) -> Self {
+        RecorderError::Other(msg)
+    }
+}
+
+impl From<&str> for RecorderError {
+    fn from(msg: &str) -> Self {
+        RecorderError::Other(msg.to_string())
+    }
+}
+
+impl serde::Serialize for RecorderError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("RecorderError", 2)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
@@ -0,0 +1,136 @@
+// Event emission for the recorder
+//
+// Audio threads have no access to a Tauri command context, so the app handle
+// is stored once at setup and every recorder event goes through `emit`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, EventTarget, Manager, Window, WindowEvent};
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+// Window label set with `set_event_target`; None broadcasts to every window
+static EVENT_TARGET: Mutex<Option<String>> = Mutex::new(None);
+// Any app window on screen, kept current by window events on the main
+// thread so audio threads never query the windowing system themselves
+static WINDOW_VISIBLE: AtomicBool = AtomicBool::new(true);
+
+pub const BATCH_PROGRESS: &str = "batch-progress";
+pub const DEVICE_FALLBACK: &str = "device-fallback";
+pub const GAIN_REDUCED: &str = "gain-reduced";
+pub const MICROPHONE_ACTIVE: &str = "microphone-active";
+pub const PLAYBACK_FINISHED: &str = "playback-finished";
+pub const PLAYBACK_LEVEL: &str = "playback-level";
+pub const RECORDING_CONFIG_CHANGED: &str = "recording-config-changed";
+pub const RECORDING_FINALIZED: &str = "recording-finalized";
+pub const RECORDING_INTERRUPTED: &str = "recording-interrupted";
+pub const RECORDING_LEVEL: &str = "recording-level";
+pub const RECORDING_RESUMED: &str = "recording-resumed";
+pub const RECORDING_SPEAKING: &str = "recording-speaking";
+pub const RESAMPLER_DOWNGRADED: &str = "resampler-downgraded";
+
+// High-frequency events slow to this while no window is visible
+const HIDDEN_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn init(app: AppHandle) {
+    update_window_visible(&app, None);
+    let _ = APP_HANDLE.set(app);
+}
+
+/// Every app window's events, from the builder's `on_window_event`, so
+/// windows opened after setup (e.g. a popout) count too. Minimizing, hiding
+/// and restoring all move focus or size, and window events run on the main
+/// thread where querying is cheap.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    // A window being destroyed may still be listed, and no longer answers
+    let closing = matches!(event, WindowEvent::Destroyed).then(|| window.label());
+    update_window_visible(window.app_handle(), closing);
+}
+
+fn update_window_visible(app: &AppHandle, closing: Option<&str>) {
+    let visible = app
+        .webview_windows()
+        .values()
+        .filter(|w| Some(w.label()) != closing)
+        .any(|w| w.is_visible().unwrap_or(true) && !w.is_minimized().unwrap_or(false));
+    WINDOW_VISIBLE.store(visible, Ordering::Relaxed);
+}
+
+/// The handle stored at setup, for code that needs app paths outside a command
+pub fn app_handle() -> Option<&'static AppHandle> {
+    APP_HANDLE.get()
+}
+
+/// `name` inside the app data dir (the dir may not exist yet)
+pub fn app_data_file(name: &str) -> Result<std::path::PathBuf, String> {
+    let app = app_handle().ok_or("Recorder is not initialized")?;
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    Ok(dir.join(name))
+}
+
+/// Send recorder events only to the window labelled `window_label`, or to
+/// every window again with None (the default)
+pub fn set_event_target(window_label: Option<String>) -> Result<(), String> {
+    if let (Some(label), Some(app)) = (&window_label, APP_HANDLE.get()) {
+        if app.get_webview_window(label).is_none() {
+            return Err(format!("No window labelled {:?}", label));
+        }
+    }
+    *EVENT_TARGET.lock().unwrap() = window_label;
+    Ok(())
+}
+
+/// Send an event to the frontend (no-op before `init`). Goes to the window
+/// from `set_event_target` if one is set; once that window has closed,
+/// events are broadcast again.
+pub fn emit<S: serde::Serialize + Clone>(event: &str, payload: S) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let result = {
+        let mut target = EVENT_TARGET.lock().unwrap();
+        match target.as_deref() {
+            Some(label) if app.get_webview_window(label).is_some() => {
+                app.emit_to(EventTarget::webview_window(label), event, payload)
+            }
+            _ => {
+                *target = None;
+                app.emit(event, payload)
+            }
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+/// Rate limiter for high-frequency events, owned by the emitting thread
+pub struct Throttle {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    pub fn new(rate_hz: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / rate_hz.max(1),
+            last: None,
+        }
+    }
+
+    /// Whether an event may go out now; the first call always may
+    pub fn ready(&mut self) -> bool {
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let elapsed = now.duration_since(last);
+            if elapsed < self.interval || (elapsed < HIDDEN_EVENT_INTERVAL && !WINDOW_VISIBLE.load(Ordering::Relaxed)) {
+                return false;
+            }
+        }
+        self.last = Some(now);
+        true
+    }
+}
@@ -0,0 +1,609 @@
+// Exclusive use of an input device (`RecordingConfig::exclusive_mode`)
+//
+// cpal 0.15 opens WASAPI endpoints in shared mode only, so exclusive capture
+// has an `ExclusiveCapture` backend of its own, and platforms without one
+// return `RecorderError::Unsupported`. An exclusive stream gets no
+// conversion from the OS: the device has to take the exact format. So
+// `negotiate` asks it about every format we can record, the recording's
+// rate and channel count first (a match needs no resampling), then the
+// device's own, then common ones, and the stream opens in the first it
+// takes.
+//
+// Windows: WASAPI exclusive mode, event driven, on the endpoint whose
+// friendly name is the cpal device name.
+
+use cpal::{SampleFormat, SampleRate, StreamError, SupportedBufferSize, SupportedStreamConfig};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::convert::ToI16;
+use super::error::RecorderError;
+use super::stream::{self, InputStreamHandle};
+
+// Sample formats asked for at each rate and channel count, preferred first
+const SAMPLE_FORMATS: [SampleFormat; 3] = [SampleFormat::I16, SampleFormat::I32, SampleFormat::F32];
+const COMMON_RATES: [u32; 4] = [48000, 44100, 96000, 16000];
+
+trait ExclusiveCapture: Sync {
+    /// Fails with `Unsupported` where there is no exclusive capture
+    fn available(&self) -> Result<(), RecorderError>;
+    /// The rate and channel count `device` (`None`: the default input) runs
+    /// at in shared mode
+    fn mix_format(&self, device: Option<&str>) -> Result<(u32, u16), RecorderError>;
+    /// Those of `candidates` that `device` takes in exclusive mode, in order
+    fn supported(
+        &self,
+        device: Option<&str>,
+        candidates: &[SupportedStreamConfig],
+    ) -> Result<Vec<SupportedStreamConfig>, RecorderError>;
+    /// Open `device` exclusively in `config` and start it. Called on the
+    /// thread that will poll the session.
+    fn open(&self, device: Option<&str>, config: &SupportedStreamConfig) -> Result<Box<dyn ExclusiveSession>, RecorderError>;
+}
+
+trait ExclusiveSession {
+    /// Wait briefly for packets and hand each to `on_data`: its interleaved
+    /// bytes in the negotiated format, and its capture-to-callback delay
+    /// when the device reports one
+    fn poll(&mut self, on_data: &mut dyn FnMut(&[u8], Option<Duration>)) -> Result<(), StreamError>;
+}
+
+// Fallback for platforms without a backend
+#[cfg(not(target_os = "windows"))]
+struct NoExclusiveCapture;
+
+#[cfg(not(target_os = "windows"))]
+impl ExclusiveCapture for NoExclusiveCapture {
+    fn available(&self) -> Result<(), RecorderError> {
+        Err(RecorderError::Unsupported("exclusive_mode (WASAPI only)".to_string()))
+    }
+
+    fn mix_format(&self, _device: Option<&str>) -> Result<(u32, u16), RecorderError> {
+        self.available().map(|_| (0, 0))
+    }
+
+    fn supported(
+        &self,
+        _device: Option<&str>,
+        _candidates: &[SupportedStreamConfig],
+    ) -> Result<Vec<SupportedStreamConfig>, RecorderError> {
+        self.available().map(|_| Vec::new())
+    }
+
+    fn open(&self, _device: Option<&str>, _config: &SupportedStreamConfig) -> Result<Box<dyn ExclusiveSession>, RecorderError> {
+        Err(RecorderError::Unsupported("exclusive_mode (WASAPI only)".to_string()))
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod wasapi {
+    use super::{ExclusiveCapture, ExclusiveSession, RecorderError};
+    use cpal::{SampleFormat, StreamError, SupportedStreamConfig};
+    use std::time::Duration;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+    use windows::Win32::Foundation::{CloseHandle, E_ACCESSDENIED, HANDLE, RPC_E_CHANGED_MODE, S_OK, WAIT_OBJECT_0};
+    use windows::Win32::Media::Audio::{
+        eCapture, eConsole, IAudioCaptureClient, IAudioClient, IMMDevice, IMMDeviceEnumerator, MMDeviceEnumerator,
+        AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED, AUDCLNT_E_DEVICE_INVALIDATED,
+        AUDCLNT_E_DEVICE_IN_USE, AUDCLNT_E_EXCLUSIVE_MODE_NOT_ALLOWED, AUDCLNT_SHAREMODE_EXCLUSIVE,
+        AUDCLNT_STREAMFLAGS_EVENTCALLBACK, DEVICE_STATE_ACTIVE, WAVEFORMATEX, WAVEFORMATEXTENSIBLE,
+        WAVEFORMATEXTENSIBLE_0,
+    };
+    use windows::Win32::Media::KernelStreaming::{KSDATAFORMAT_SUBTYPE_PCM, WAVE_FORMAT_EXTENSIBLE};
+    use windows::Win32::Media::Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT;
+    use windows::Win32::System::Com::StructuredStorage::{PropVariantClear, PropVariantToStringAlloc};
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+    };
+    use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+    use windows::Win32::System::Threading::{
+        AvRevertMmThreadCharacteristics, AvSetMmThreadCharacteristicsW, CreateEventW, WaitForSingleObject,
+    };
+
+    const POLL_MS: u32 = 100; // How long a poll waits for the device to signal a packet
+    // 24-bit devices hold their samples in 32-bit containers; both read as I32
+    const I32_VALID_BITS: [u16; 2] = [32, 24];
+
+    pub struct Wasapi;
+
+    impl ExclusiveCapture for Wasapi {
+        fn available(&self) -> Result<(), RecorderError> {
+            Ok(())
+        }
+
+        fn mix_format(&self, device: Option<&str>) -> Result<(u32, u16), RecorderError> {
+            let _com = Com::init()?;
+            let client = activate(&endpoint(device)?)?;
+            unsafe {
+                let format = client
+                    .GetMixFormat()
+                    .map_err(|e| format!("Failed to get the device's mix format: {}", e))?;
+                let mix = std::ptr::read_unaligned(format);
+                CoTaskMemFree(Some(format as *const _));
+                Ok((mix.nSamplesPerSec, mix.nChannels))
+            }
+        }
+
+        fn supported(
+            &self,
+            device: Option<&str>,
+            candidates: &[SupportedStreamConfig],
+        ) -> Result<Vec<SupportedStreamConfig>, RecorderError> {
+            let _com = Com::init()?;
+            let client = activate(&endpoint(device)?)?;
+            Ok(candidates.iter().filter(|config| accepted(&client, config).is_some()).cloned().collect())
+        }
+
+        fn open(&self, device: Option<&str>, config: &SupportedStreamConfig) -> Result<Box<dyn ExclusiveSession>, RecorderError> {
+            Ok(Box::new(Session::open(device, config)?))
+        }
+    }
+
+    // COM on this thread for as long as the guard lives
+    struct Com {
+        owned: bool, // False when the thread already had COM in another mode
+    }
+
+    impl Com {
+        fn init() -> Result<Self, String> {
+            let hr = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+            if hr == RPC_E_CHANGED_MODE {
+                return Ok(Com { owned: false });
+            }
+            hr.ok().map_err(|e| format!("Failed to initialize COM: {}", e))?;
+            Ok(Com { owned: true })
+        }
+    }
+
+    impl Drop for Com {
+        fn drop(&mut self) {
+            if self.owned {
+                unsafe { CoUninitialize() };
+            }
+        }
+    }
+
+    // The active capture endpoint cpal calls `name`, or the default one
+    fn endpoint(name: Option<&str>) -> Result<IMMDevice, String> {
+        let enumerator: IMMDeviceEnumerator = unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL) }
+            .map_err(|e| format!("Failed to list audio endpoints: {}", e))?;
+        let Some(name) = name else {
+            return unsafe { enumerator.GetDefaultAudioEndpoint(eCapture, eConsole) }
+                .map_err(|e| format!("No default input device: {}", e));
+        };
+        let endpoints = unsafe { enumerator.EnumAudioEndpoints(eCapture, DEVICE_STATE_ACTIVE) }
+            .map_err(|e| format!("Failed to list audio endpoints: {}", e))?;
+        let count = unsafe { endpoints.GetCount() }.map_err(|e| format!("Failed to list audio endpoints: {}", e))?;
+        (0..count)
+            .filter_map(|i| unsafe { endpoints.Item(i) }.ok())
+            .find(|device| friendly_name(device).as_deref() == Some(name))
+            .ok_or_else(|| format!("Input device not found: {}", name))
+    }
+
+    fn friendly_name(device: &IMMDevice) -> Option<String> {
+        unsafe {
+            let store = device.OpenPropertyStore(STGM_READ).ok()?;
+            let mut value = store.GetValue(&PKEY_Device_FriendlyName).ok()?;
+            let name = PropVariantToStringAlloc(&value).ok().and_then(|text| {
+                let name = text.to_string().ok();
+                CoTaskMemFree(Some(text.0 as *const _));
+                name
+            });
+            let _ = PropVariantClear(&mut value);
+            name
+        }
+    }
+
+    fn activate(endpoint: &IMMDevice) -> Result<IAudioClient, String> {
+        unsafe { endpoint.Activate::<IAudioClient>(CLSCTX_ALL, None) }
+            .map_err(|e| format!("Failed to open the audio client: {}", e))
+    }
+
+    // `config` as WASAPI describes it, if `client` takes it exclusively
+    fn accepted(client: &IAudioClient, config: &SupportedStreamConfig) -> Option<WAVEFORMATEXTENSIBLE> {
+        let container = config.sample_format().sample_size() as u16 * 8;
+        let valid_bits = match config.sample_format() {
+            SampleFormat::I32 => &I32_VALID_BITS[..],
+            _ => &[container][..],
+        };
+        valid_bits.iter().map(|&bits| wave_format(config, bits)).find(|format| {
+            let format = (format as *const WAVEFORMATEXTENSIBLE).cast::<WAVEFORMATEX>();
+            let hr = unsafe { client.IsFormatSupported(AUDCLNT_SHAREMODE_EXCLUSIVE, format, None) };
+            hr == S_OK
+        })
+    }
+
+    fn wave_format(config: &SupportedStreamConfig, valid_bits: u16) -> WAVEFORMATEXTENSIBLE {
+        let channels = config.channels();
+        let rate = config.sample_rate().0;
+        let bits = config.sample_format().sample_size() as u16 * 8;
+        let block_align = channels * bits / 8;
+        WAVEFORMATEXTENSIBLE {
+            Format: WAVEFORMATEX {
+                wFormatTag: WAVE_FORMAT_EXTENSIBLE as u16,
+                nChannels: channels,
+                nSamplesPerSec: rate,
+                nAvgBytesPerSec: rate * block_align as u32,
+                nBlockAlign: block_align,
+                wBitsPerSample: bits,
+                cbSize: (std::mem::size_of::<WAVEFORMATEXTENSIBLE>() - std::mem::size_of::<WAVEFORMATEX>()) as u16,
+            },
+            Samples: WAVEFORMATEXTENSIBLE_0 { wValidBitsPerSample: valid_bits },
+            dwChannelMask: channel_mask(channels),
+            SubFormat: match config.sample_format().is_float() {
+                true => KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+                false => KSDATAFORMAT_SUBTYPE_PCM,
+            },
+        }
+    }
+
+    // Front center for mono; otherwise the first speaker positions in order
+    // (front left and right for stereo)
+    fn channel_mask(channels: u16) -> u32 {
+        match channels {
+            1 => 0x4,
+            n => (1u32 << n.min(18)) - 1,
+        }
+    }
+
+    // Exclusive event-driven streams take the period as the buffer duration
+    fn initialize(client: &IAudioClient, period: i64, format: &WAVEFORMATEXTENSIBLE) -> windows::core::Result<()> {
+        let format = (format as *const WAVEFORMATEXTENSIBLE).cast::<WAVEFORMATEX>();
+        unsafe {
+            client.Initialize(AUDCLNT_SHAREMODE_EXCLUSIVE, AUDCLNT_STREAMFLAGS_EVENTCALLBACK, period, period, format, None)
+        }
+    }
+
+    // Why `name` didn't open, in terms a user can act on
+    fn open_error(name: &str, e: windows::core::Error) -> RecorderError {
+        let code = e.code();
+        let message = if code == AUDCLNT_E_DEVICE_IN_USE {
+            format!("Exclusive access to {} was denied: another app is using it", name)
+        } else if code == AUDCLNT_E_EXCLUSIVE_MODE_NOT_ALLOWED {
+            format!("Exclusive access to {} was denied: exclusive mode is turned off in its Windows sound settings", name)
+        } else if code == E_ACCESSDENIED {
+            format!("Exclusive access to {} was denied: microphone access is off in Windows privacy settings", name)
+        } else {
+            format!("Failed to open {} in exclusive mode: {}", name, e)
+        };
+        RecorderError::Other(message)
+    }
+
+    fn stream_error(e: windows::core::Error) -> StreamError {
+        match e.code() == AUDCLNT_E_DEVICE_INVALIDATED {
+            true => StreamError::DeviceNotAvailable,
+            false => StreamError::BackendSpecific { err: cpal::BackendSpecificError { description: e.to_string() } },
+        }
+    }
+
+    // How long ago a packet was captured, from its QPC position in 100 ns units
+    fn capture_delay(position: u64) -> Option<Duration> {
+        let (mut now, mut frequency) = (0i64, 0i64);
+        unsafe {
+            QueryPerformanceCounter(&mut now).ok()?;
+            QueryPerformanceFrequency(&mut frequency).ok()?;
+        }
+        let now = (now as u128 * 10_000_000 / frequency.max(1) as u128) as u64;
+        now.checked_sub(position).map(|ticks| Duration::from_nanos(ticks * 100))
+    }
+
+    struct Session {
+        client: IAudioClient,
+        capture: IAudioCaptureClient,
+        event: HANDLE,
+        frame_bytes: usize,
+        silence: Vec<u8>, // Handed over for packets the device flags as silent
+        mmcss: Option<HANDLE>,
+        _com: Com, // Last field, so COM outlives the interfaces
+    }
+
+    impl Session {
+        fn open(device: Option<&str>, config: &SupportedStreamConfig) -> Result<Self, RecorderError> {
+            let com = Com::init()?;
+            let name = device.unwrap_or("the default input");
+            let endpoint = endpoint(device)?;
+            let client = activate(&endpoint)?;
+            let format = accepted(&client, config).ok_or_else(|| {
+                format!(
+                    "{} no longer takes {} ch, {} Hz, {} in exclusive mode",
+                    name,
+                    config.channels(),
+                    config.sample_rate().0,
+                    config.sample_format()
+                )
+            })?;
+            let mut period = 0i64;
+            unsafe { client.GetDevicePeriod(None, Some(&mut period as *mut i64)) }
+                .map_err(|e| format!("Failed to get the device period: {}", e))?;
+            let client = match initialize(&client, period, &format) {
+                Ok(()) => client,
+                // The period has to fill the device's buffer exactly; ask
+                // again with the one that does, on a fresh client
+                Err(e) if e.code() == AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED => {
+                    let frames = unsafe { client.GetBufferSize() }.map_err(|e| open_error(name, e))?;
+                    let period = (10_000_000.0 * frames as f64 / config.sample_rate().0 as f64).round() as i64;
+                    let client = activate(&endpoint)?;
+                    initialize(&client, period, &format).map_err(|e| open_error(name, e))?;
+                    client
+                }
+                Err(e) => return Err(open_error(name, e)),
+            };
+
+            let buffer_frames = unsafe { client.GetBufferSize() }.map_err(|e| open_error(name, e))?;
+            let event = unsafe { CreateEventW(None, false, false, PCWSTR::null()) }
+                .map_err(|e| format!("Failed to create the capture event: {}", e))?;
+            let capture = match unsafe { client.SetEventHandle(event).and_then(|_| client.GetService::<IAudioCaptureClient>()) } {
+                Ok(capture) => capture,
+                Err(e) => {
+                    let _ = unsafe { CloseHandle(event) };
+                    return Err(open_error(name, e));
+                }
+            };
+            // Scheduled as audio work, as cpal does for its own streams
+            let mut task = 0u32;
+            let mmcss = unsafe { AvSetMmThreadCharacteristicsW(w!("Pro Audio"), &mut task) }.ok();
+            let frame_bytes = format.Format.nBlockAlign as usize;
+            let session = Session {
+                client,
+                capture,
+                event,
+                frame_bytes,
+                silence: vec![0; buffer_frames as usize * frame_bytes],
+                mmcss,
+                _com: com,
+            };
+            unsafe { session.client.Start() }.map_err(|e| open_error(name, e))?;
+            Ok(session)
+        }
+    }
+
+    impl ExclusiveSession for Session {
+        fn poll(&mut self, on_data: &mut dyn FnMut(&[u8], Option<Duration>)) -> Result<(), StreamError> {
+            if unsafe { WaitForSingleObject(self.event, POLL_MS) } != WAIT_OBJECT_0 {
+                return Ok(());
+            }
+            loop {
+                if unsafe { self.capture.GetNextPacketSize() }.map_err(stream_error)? == 0 {
+                    return Ok(());
+                }
+                let (mut data, mut frames, mut flags, mut position) = (std::ptr::null_mut(), 0u32, 0u32, 0u64);
+                unsafe { self.capture.GetBuffer(&mut data, &mut frames, &mut flags, None, Some(&mut position as *mut u64)) }
+                    .map_err(stream_error)?;
+                let len = frames as usize * self.frame_bytes;
+                let bytes = match flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 {
+                    true => &self.silence[..len.min(self.silence.len())],
+                    // WASAPI holds `frames` frames at `data` until ReleaseBuffer
+                    false => unsafe { std::slice::from_raw_parts(data, len) },
+                };
+                on_data(bytes, capture_delay(position));
+                unsafe { self.capture.ReleaseBuffer(frames) }.map_err(stream_error)?;
+            }
+        }
+    }
+
+    impl Drop for Session {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = self.client.Stop();
+                let _ = CloseHandle(self.event);
+                if let Some(task) = self.mmcss {
+                    let _ = AvRevertMmThreadCharacteristics(task);
+                }
+            }
+        }
+    }
+}
+
+fn backend() -> &'static dyn ExclusiveCapture {
+    #[cfg(target_os = "windows")]
+    return &wasapi::Wasapi;
+    #[cfg(not(target_os = "windows"))]
+    return &NoExclusiveCapture;
+}
+
+/// Fails with `Unsupported` where exclusive capture isn't available, before
+/// any device is opened
+pub fn check_available() -> Result<(), RecorderError> {
+    backend().available()
+}
+
+/// The format to open `device` (`None`: the default input) in exclusively,
+/// for a recording at `rate` Hz with `channels`
+pub fn negotiate(device: Option<&str>, rate: u32, channels: u16) -> Result<SupportedStreamConfig, RecorderError> {
+    negotiate_with(backend(), device, rate, channels)
+}
+
+fn negotiate_with(
+    backend: &dyn ExclusiveCapture,
+    device: Option<&str>,
+    rate: u32,
+    channels: u16,
+) -> Result<SupportedStreamConfig, RecorderError> {
+    let mix = backend.mix_format(device)?;
+    let candidates = candidates((rate, channels), mix);
+    backend.supported(device, &candidates)?.into_iter().next().ok_or_else(|| {
+        RecorderError::Other(format!(
+            "{} takes none of the formats we record in exclusive mode ({} Hz, {} ch wanted)",
+            device.unwrap_or("The default input"),
+            rate,
+            channels
+        ))
+    })
+}
+
+// Every format to ask for, best first: the wanted rate, then the device's,
+// then common ones; at each, the wanted channel count, then the device's,
+// then mono and stereo
+fn candidates(wanted: (u32, u16), mix: (u32, u16)) -> Vec<SupportedStreamConfig> {
+    let rates = unique([wanted.0, mix.0].into_iter().chain(COMMON_RATES));
+    let channel_counts = unique([wanted.1, mix.1, 1, 2]);
+    let mut candidates = Vec::new();
+    for &rate in &rates {
+        for &channels in &channel_counts {
+            candidates.extend(SAMPLE_FORMATS.iter().map(|&format| {
+                SupportedStreamConfig::new(channels, SampleRate(rate), SupportedBufferSize::Unknown, format)
+            }));
+        }
+    }
+    candidates
+}
+
+fn unique<T: PartialEq>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut seen = Vec::new();
+    for item in items {
+        if !seen.contains(&item) {
+            seen.push(item);
+        }
+    }
+    seen
+}
+
+// A running session's capture thread; dropping it stops and closes the device
+struct ExclusiveStream {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for ExclusiveStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Open `device` (`None`: the default input) exclusively in `config`, from
+/// `negotiate`, and hand each packet to `on_data` on the capture thread,
+/// like a cpal stream's callback. Errors go to `on_error` after logging;
+/// the first one ends the stream.
+pub fn open_input_stream<T, D, E>(
+    device: Option<String>,
+    config: SupportedStreamConfig,
+    mut on_data: D,
+    mut on_error: E,
+) -> Result<InputStreamHandle, String>
+where
+    T: ToI16,
+    D: FnMut(&[T], Option<Duration>) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    if T::FORMAT != config.sample_format() {
+        return Err(format!("Exclusive stream is {}, not {}", config.sample_format(), T::FORMAT));
+    }
+    stream::open_on_thread(move || {
+        let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let capture_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut session = match backend().open(device.as_deref(), &config) {
+                Ok(session) => session,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+            let mut on_packet = |bytes: &[u8], delay: Option<Duration>| on_data(as_samples::<T>(bytes), delay);
+            while !capture_stop.load(Ordering::Relaxed) {
+                if let Err(err) = session.poll(&mut on_packet) {
+                    eprintln!("Audio input error: {}", err);
+                    on_error(err);
+                    return;
+                }
+            }
+        });
+        let stream = ExclusiveStream { stop, thread: Some(thread) };
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(stream),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Exclusive capture thread exited unexpectedly".to_string()),
+        }
+    })
+}
+
+// A packet's bytes as samples. WASAPI buffers are aligned to the sample
+// size; a misaligned one would be dropped rather than misread.
+fn as_samples<T: ToI16>(bytes: &[u8]) -> &[T] {
+    // Every bit pattern is a valid integer or float sample
+    let (head, samples, _) = unsafe { bytes.align_to::<T>() };
+    match head.is_empty() {
+        true => samples,
+        false => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A device that takes only `formats` exclusively
+    struct Takes {
+        mix: (u32, u16),
+        formats: Vec<(u32, u16, SampleFormat)>,
+    }
+
+    impl ExclusiveCapture for Takes {
+        fn available(&self) -> Result<(), RecorderError> {
+            Ok(())
+        }
+
+        fn mix_format(&self, _device: Option<&str>) -> Result<(u32, u16), RecorderError> {
+            Ok(self.mix)
+        }
+
+        fn supported(
+            &self,
+            _device: Option<&str>,
+            candidates: &[SupportedStreamConfig],
+        ) -> Result<Vec<SupportedStreamConfig>, RecorderError> {
+            Ok(candidates
+                .iter()
+                .filter(|c| self.formats.contains(&(c.sample_rate().0, c.channels(), c.sample_format())))
+                .cloned()
+                .collect())
+        }
+
+        fn open(&self, _device: Option<&str>, _config: &SupportedStreamConfig) -> Result<Box<dyn ExclusiveSession>, RecorderError> {
+            Err(RecorderError::Other("not a real device".to_string()))
+        }
+    }
+
+    fn negotiated(device: &Takes, rate: u32, channels: u16) -> Result<(u32, u16, SampleFormat), RecorderError> {
+        let config = negotiate_with(device, Some("Mic"), rate, channels)?;
+        Ok((config.sample_rate().0, config.channels(), config.sample_format()))
+    }
+
+    #[test]
+    fn negotiation_prefers_the_recording_format_then_the_device_mix() {
+        let device = Takes {
+            mix: (44100, 2),
+            formats: vec![
+                (44100, 2, SampleFormat::I32),
+                (48000, 2, SampleFormat::F32),
+                (48000, 1, SampleFormat::I32),
+            ],
+        };
+        // The recording's rate and channel count first, in any format the
+        // device has; failing those, the device's own
+        assert_eq!(negotiated(&device, 48000, 1).unwrap(), (48000, 1, SampleFormat::I32));
+        assert_eq!(negotiated(&device, 48000, 2).unwrap(), (48000, 2, SampleFormat::F32));
+        assert_eq!(negotiated(&device, 16000, 1).unwrap(), (44100, 2, SampleFormat::I32));
+
+        let nothing = Takes { mix: (48000, 2), formats: vec![(8000, 1, SampleFormat::U8)] };
+        let err = negotiated(&nothing, 48000, 1).unwrap_err();
+        assert!(err.to_string().contains("Mic takes none"), "{}", err);
+    }
+
+    #[test]
+    fn packets_read_as_samples_of_the_stream_format() {
+        let samples = [1i16, -2, 300];
+        let bytes = unsafe { std::slice::from_raw_parts(samples.as_ptr().cast::<u8>(), 6) };
+        assert_eq!(as_samples::<i16>(bytes), samples);
+        // Misaligned, so not read at all
+        assert!(as_samples::<i16>(&bytes[1..5]).is_empty());
+    }
+}
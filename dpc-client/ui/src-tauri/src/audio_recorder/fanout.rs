@@ -0,0 +1,156 @@
+// Extra copies of a recording written from the same frames (`extra_outputs`)
+//
+// The encoder writes through a `FanOut`: the main file directly, and each
+// extra copy on its own thread fed over an unbounded channel, so a slow disk
+// behind one copy never holds up the encoder or capture. A copy that fails
+// stops on its own and reports the error when the recording finishes; the
+// main file carries on.
+
+use std::io;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use super::wav::{AudioContainer, WavMetadata, WavWriter};
+
+/// A copy written next to the main file, as listed in `recording-finalized` and by `stop_recording`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExtraOutputFile {
+    pub path: String,
+    pub container: AudioContainer,
+    pub size_bytes: u64,
+    pub error: Option<String>, // Set if this copy failed; the main file is unaffected
+}
+
+enum MirrorOp {
+    Write(Vec<i16>),
+    Truncate(usize),
+}
+
+struct Mirror {
+    path: String,
+    container: AudioContainer,
+    tx: mpsc::Sender<MirrorOp>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Mirror {
+    // Create the file here, so a bad path fails the start rather than
+    // showing up only in the result
+    fn spawn(
+        path: String,
+        sample_rate: u32,
+        channels: u16,
+        container: AudioContainer,
+        metadata: Option<&WavMetadata>,
+    ) -> io::Result<Self> {
+        // AIFF has no INFO chunk; the copy goes untagged
+        let metadata = metadata.filter(|_| container == AudioContainer::Wav);
+        let mut writer = WavWriter::with_metadata(&path, sample_rate, channels, container, metadata)?;
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for op in rx {
+                match op {
+                    MirrorOp::Write(samples) => writer.write_samples(&samples)?,
+                    MirrorOp::Truncate(samples) => writer.truncate(samples)?,
+                }
+            }
+            writer.finish()
+        });
+        Ok(Self { path, container, tx, handle })
+    }
+
+    // Wait for everything sent so far, then finish the file
+    fn finish(self) -> ExtraOutputFile {
+        drop(self.tx);
+        let error = match self.handle.join() {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("Failed to write {}: {}", self.path, e)),
+            Err(_) => Some(format!("Writer for {} panicked", self.path)),
+        };
+        ExtraOutputFile {
+            size_bytes: std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+            path: self.path,
+            container: self.container,
+            error,
+        }
+    }
+}
+
+/// The main writer plus its extra copies, behind the `WavWriter` calls the
+/// encoder makes. Flushes and syncs only reach the main file.
+pub struct FanOut {
+    primary: WavWriter,
+    mirrors: Vec<Mirror>,
+}
+
+impl FanOut {
+    /// Start a copy of `primary` at each of `outputs`
+    pub fn new(
+        primary: WavWriter,
+        outputs: &[(String, AudioContainer)],
+        sample_rate: u32,
+        channels: u16,
+        metadata: Option<&WavMetadata>,
+    ) -> io::Result<Self> {
+        let mut fan_out = Self { primary, mirrors: Vec::with_capacity(outputs.len()) };
+        for (path, container) in outputs {
+            match Mirror::spawn(path.clone(), sample_rate, channels, *container, metadata) {
+                Ok(mirror) => fan_out.mirrors.push(mirror),
+                Err(e) => {
+                    fan_out.discard();
+                    return Err(io::Error::new(e.kind(), format!("{}: {}", path, e)));
+                }
+            }
+        }
+        Ok(fan_out)
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for mirror in &self.mirrors {
+            // A failed copy has hung up; it reports that on finish
+            let _ = mirror.tx.send(MirrorOp::Write(samples.to_vec()));
+        }
+        self.primary.write_samples(samples)
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.primary.sync()
+    }
+
+    pub fn flush_partial(&mut self, fsync: bool) -> io::Result<u64> {
+        self.primary.flush_partial(fsync)
+    }
+
+    pub fn truncate(&mut self, samples: usize) -> io::Result<()> {
+        for mirror in &self.mirrors {
+            let _ = mirror.tx.send(MirrorOp::Truncate(samples));
+        }
+        self.primary.truncate(samples)
+    }
+
+    /// Finish the main file, then wait for each copy
+    pub fn finish(self) -> io::Result<Vec<ExtraOutputFile>> {
+        let result = self.primary.finish();
+        let extras: Vec<ExtraOutputFile> = self.mirrors.into_iter().map(Mirror::finish).collect();
+        match result {
+            Ok(()) => Ok(extras),
+            Err(e) => {
+                remove_extras(&extras);
+                Err(e)
+            }
+        }
+    }
+
+    /// Give up on the recording: close the main file and delete every copy
+    pub fn discard(self) {
+        drop(self.primary);
+        let extras: Vec<ExtraOutputFile> = self.mirrors.into_iter().map(Mirror::finish).collect();
+        remove_extras(&extras);
+    }
+}
+
+pub fn remove_extras(extras: &[ExtraOutputFile]) {
+    for extra in extras {
+        let _ = std::fs::remove_file(&extra.path);
+    }
+}
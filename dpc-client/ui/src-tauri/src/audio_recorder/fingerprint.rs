@@ -0,0 +1,162 @@
+// Compact audio fingerprints for matching and deduplicating voice notes
+//
+// The mono stream is analysed at the file's own rate, in 64 ms frames, and
+// split into 8 equal segments. Nothing is resampled first: decimating to a
+// voice-band rate would fold everything above its Nyquist back into the
+// bands. For each segment we average the energy of 16 log-spaced bands between
+// 300 and 3400 Hz (the voice band) and emit 16 bits: 15 "band louder than
+// the next band" bits plus one "segment louder than the previous" bit. The
+// result is 128 bits, formatted as `fp1:<32 hex digits>`.
+//
+// Compare fingerprints by Hamming distance over the 128 bits: re-encodes of
+// the same recording typically differ by under 10 bits, unrelated recordings
+// by around 64. Files shorter than one second give too few frames per
+// segment to be stable and are marked `fp1-low:` instead.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+use super::dsp;
+use super::tools;
+
+const FRAME_MS: u32 = 64;
+const SEGMENTS: usize = 8;
+const BANDS: usize = 16;
+const LOW_HZ: f32 = 300.0;
+const HIGH_HZ: f32 = 3400.0;
+const MIN_CONFIDENT_MS: u64 = 1000;
+
+/// Fingerprint a WAV file (see the module comment for the format)
+pub fn fingerprint_wav(path: String) -> Result<String, String> {
+    let (spec, _, _, samples) = tools::read_wav(&path)?;
+
+    let mut mono: Vec<i16> = samples.chunks_exact(spec.channels as usize).map(dsp::downmix).collect();
+    let duration_ms = mono.len() as u64 * 1000 / spec.sample_rate as u64;
+    let fft_size = (spec.sample_rate * FRAME_MS / 1000) as usize;
+    // Very short clips still get one analysis frame
+    if mono.len() < fft_size {
+        mono.resize(fft_size, 0);
+    }
+
+    let bands = band_energies(&mono, spec.sample_rate, fft_size);
+    let segment_len = bands.len().div_ceil(SEGMENTS);
+    let segments: Vec<[f32; BANDS]> = (0..SEGMENTS)
+        .map(|s| {
+            let start = (s * segment_len).min(bands.len() - 1);
+            let end = ((s + 1) * segment_len).clamp(start + 1, bands.len());
+            let mut sum = [0f32; BANDS];
+            for frame in &bands[start..end] {
+                for (acc, e) in sum.iter_mut().zip(frame) {
+                    *acc += e;
+                }
+            }
+            sum.map(|e| e / (end - start) as f32)
+        })
+        .collect();
+
+    let mut bits: u128 = 0;
+    for (s, segment) in segments.iter().enumerate() {
+        for b in 0..BANDS - 1 {
+            bits = (bits << 1) | (segment[b] > segment[b + 1]) as u128;
+        }
+        let total: f32 = segment.iter().sum();
+        let previous: f32 = if s > 0 { segments[s - 1].iter().sum() } else { 0.0 };
+        bits = (bits << 1) | (total > previous) as u128;
+    }
+
+    let prefix = if duration_ms < MIN_CONFIDENT_MS { "fp1-low" } else { "fp1" };
+    Ok(format!("{}:{:032x}", prefix, bits))
+}
+
+// Per-frame energy in each band, from a Hann-windowed STFT with half-frame hops
+fn band_energies(samples: &[i16], sample_rate: u32, fft_size: usize) -> Vec<[f32; BANDS]> {
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(fft_size);
+    let window: Vec<f32> = (0..fft_size)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / fft_size as f32).cos())
+        .collect();
+    let bin_hz = sample_rate as f32 / fft_size as f32;
+    let edges: Vec<usize> = (0..=BANDS)
+        .map(|i| {
+            let hz = LOW_HZ * (HIGH_HZ / LOW_HZ).powf(i as f32 / BANDS as f32);
+            (hz / bin_hz).round() as usize
+        })
+        .collect();
+
+    let mut buffer = vec![Complex::default(); fft_size];
+    (0..=samples.len() - fft_size)
+        .step_by(fft_size / 2)
+        .map(|start| {
+            for (i, slot) in buffer.iter_mut().enumerate() {
+                *slot = Complex::new(samples[start + i] as f32 * window[i], 0.0);
+            }
+            fft.process(&mut buffer);
+            let mut energies = [0f32; BANDS];
+            for (b, energy) in energies.iter_mut().enumerate() {
+                let (lo, hi) = (edges[b], edges[b + 1].max(edges[b] + 1));
+                *energy = buffer[lo..hi].iter().map(|c| c.norm_sqr()).sum();
+            }
+            energies
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::wav::WavWriter;
+
+    // A tone that moves through the voice band, one step per segment, over a
+    // faint comb of partials up to 8 kHz, and optionally a loud tone above
+    // 4 kHz. A comb rather than noise, so each rate samples the same signal.
+    fn write_clip(name: &str, sample_rate: u32, high_tone: bool) -> String {
+        let mut samples = Vec::new();
+        for hz in [400.0, 900.0, 1500.0, 2500.0, 700.0, 3000.0, 1200.0, 500.0] {
+            samples.extend(dsp::tone(sample_rate, 1, hz, 200, -12.0));
+        }
+        if high_tone {
+            let high = dsp::tone(sample_rate, 1, 5000.0, 1600, -9.0);
+            for (s, h) in samples.iter_mut().zip(high) {
+                *s = s.saturating_add(h);
+            }
+        }
+        let partial = dsp::dbfs_to_linear(-60.0) * i16::MAX as f32;
+        for (i, s) in samples.iter_mut().enumerate() {
+            let t = i as f32 / sample_rate as f32;
+            let comb: f32 = (0..250)
+                .map(|k| (std::f32::consts::TAU * (100.0 + 31.0 * k as f32) * t + k as f32).sin())
+                .sum();
+            *s = s.saturating_add((comb * partial) as i16);
+        }
+
+        let path = std::env::temp_dir().join(format!("dpc-fingerprint-test-{}-{}.wav", std::process::id(), name));
+        let path = path.to_string_lossy().to_string();
+        let mut writer = WavWriter::new(&path, sample_rate, 1).unwrap();
+        writer.write_samples(&samples).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    fn distance(a: &str, b: &str) -> u32 {
+        let bits = |fp: &str| u128::from_str_radix(fp.split_once(':').unwrap().1, 16).unwrap();
+        (bits(a) ^ bits(b)).count_ones()
+    }
+
+    #[test]
+    fn content_above_the_voice_band_does_not_fold_into_it() {
+        let plain = write_clip("plain", 48000, false);
+        let with_high = write_clip("high", 48000, true);
+        let at_44k = write_clip("44k", 44100, false);
+        let fingerprints: Vec<String> = [&plain, &with_high, &at_44k]
+            .iter()
+            .map(|path| fingerprint_wav(path.to_string()).unwrap())
+            .collect();
+        for path in [plain, with_high, at_44k] {
+            let _ = std::fs::remove_file(path);
+        }
+
+        assert!(fingerprints[0].starts_with("fp1:"), "{}", fingerprints[0]);
+        // Decimating first and hashing the aliases flipped several of these bits
+        assert_eq!(distance(&fingerprints[0], &fingerprints[1]), 0, "{:?}", fingerprints);
+        assert_eq!(distance(&fingerprints[0], &fingerprints[2]), 0, "{:?}", fingerprints);
+    }
+}
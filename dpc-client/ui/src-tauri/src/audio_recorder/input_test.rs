@@ -0,0 +1,78 @@
+// Short test captures for picking an input device
+//
+// Each capture opens the device, records for the requested time and closes
+// the stream before returning, so consecutive tests never hold two devices
+// at once (some drivers open inputs exclusively).
+
+use cpal::traits::DeviceTrait;
+use std::time::Duration;
+
+use super::devices;
+use super::dsp;
+use super::stream;
+
+const MIN_TEST_MS: u64 = 100;
+const MAX_TEST_MS: u64 = 10_000;
+
+#[derive(Debug, serde::Serialize)]
+pub struct InputTestResult {
+    pub device_name: String,
+    pub sample_rate: u32,
+    pub duration_ms: u64,
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    /// Level of the quietest 10% of 20ms frames: the background noise
+    pub noise_floor_dbfs: f32,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ComparisonResult {
+    pub device_a: InputTestResult,
+    pub device_b: InputTestResult,
+}
+
+/// Capture briefly from a device, given by id or name (the default when
+/// `device_name` is `None`), and report its levels
+pub fn test_input_device(device_name: Option<String>, duration_ms: u64) -> Result<InputTestResult, String> {
+    if !(MIN_TEST_MS..=MAX_TEST_MS).contains(&duration_ms) {
+        return Err(format!("Test duration must be {}-{} ms", MIN_TEST_MS, MAX_TEST_MS));
+    }
+    if super::get_global_state().lock().unwrap().is_recording {
+        return Err("Cannot test an input device while recording".to_string());
+    }
+
+    let device = devices::find_input_device(device_name.as_deref())?;
+    let name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+    let (samples, sample_rate) = stream::capture_mono(device, Duration::from_millis(duration_ms))?;
+    if samples.is_empty() {
+        return Err(format!("{} produced no audio", name));
+    }
+
+    let peak = samples.iter().map(|&s| (s as f32).abs()).fold(0.0, f32::max) / 32768.0;
+    let frame = (sample_rate as usize / 50).max(1);
+    let mut levels: Vec<f32> = samples.chunks(frame).map(dsp::frame_rms).collect();
+    levels.sort_by(|a, b| a.total_cmp(b));
+
+    Ok(InputTestResult {
+        device_name: name,
+        sample_rate,
+        duration_ms: samples.len() as u64 * 1000 / sample_rate as u64,
+        peak_dbfs: dsp::linear_to_dbfs(peak),
+        rms_dbfs: dsp::linear_to_dbfs(dsp::frame_rms(&samples)),
+        noise_floor_dbfs: dsp::linear_to_dbfs(levels[levels.len() / 10]),
+    })
+}
+
+/// Ambient RMS of the default input over `duration_ms`, in dBFS (`MIN_DBFS`
+/// for digital silence), for suggesting a silence or gate threshold above it
+pub fn measure_noise_floor(duration_ms: u64) -> Result<f32, String> {
+    Ok(test_input_device(None, duration_ms)?.rms_dbfs)
+}
+
+/// Test two devices one after the other; results follow the argument order
+pub fn compare_devices(device_a: String, device_b: String, duration_ms: u64) -> Result<ComparisonResult, String> {
+    Ok(ComparisonResult {
+        device_a: test_input_device(Some(device_a), duration_ms)?,
+        device_b: test_input_device(Some(device_b), duration_ms)?,
+    })
+}
@@ -0,0 +1,115 @@
+// Loopback latency measurement (developer diagnostic)
+//
+// Plays a short windowed chirp on the default output while capturing the
+// default input, then cross-correlates the capture against the chirp. The
+// result is the acoustic round trip including both devices' buffering. With
+// no acoustic path (headphones, muted mic) the correlation peak stays low
+// and we report that instead of a number.
+
+use cpal::traits::HostTrait;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+use super::playback;
+use super::stream::MonoCapture;
+
+const CHIRP_RATE: u32 = 48000;
+const CHIRP_MS: u64 = 60;
+const CHIRP_START_HZ: f64 = 500.0;
+const CHIRP_END_HZ: f64 = 4000.0;
+const LEAD_IN_MS: u64 = 200; // Silence before the chirp absorbs output start-up jitter
+const MAX_LATENCY_MS: u64 = 500;
+const MIN_CORRELATION: f32 = 0.3; // Normalized; uncorrelated noise sits well below this
+
+#[derive(Debug, serde::Serialize)]
+pub struct LatencyMeasurement {
+    pub latency_ms: f64,
+    pub correlation: f32,        // Peak normalized correlation (0-1)
+    pub input_sample_rate: u32,
+}
+
+/// Measure the output-to-input round trip through the default devices
+pub fn measure_loopback_latency() -> Result<LatencyMeasurement, String> {
+    if super::get_global_state().lock().unwrap().is_recording {
+        return Err("Cannot measure latency while recording".to_string());
+    }
+
+    let host = cpal::default_host();
+    let device = host
+        .default_input_device()
+        .ok_or("No audio input device found")?;
+    let mut capture = MonoCapture::open(device)?;
+    let input_rate = capture.sample_rate;
+
+    // Wait for the microphone to deliver audio so the capture has a zero point
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let capture_start = loop {
+        if let Some(started) = capture.started() {
+            break started;
+        }
+        if Instant::now() > deadline {
+            return Err("Microphone produced no audio".to_string());
+        }
+        capture.collect_for(Duration::from_millis(5));
+    };
+
+    let mut signal = vec![0i16; (CHIRP_RATE as u64 * LEAD_IN_MS / 1000) as usize];
+    signal.extend(chirp(CHIRP_RATE).iter().map(|&s| (s * i16::MAX as f32) as i16));
+    playback::play_samples(signal, CHIRP_RATE, 1)?;
+    let play_start = Instant::now();
+
+    capture.collect_for(Duration::from_millis(LEAD_IN_MS + CHIRP_MS + MAX_LATENCY_MS));
+    playback::stop_playback();
+
+    let captured = capture.finish()?;
+    let expected = play_start.duration_since(capture_start) + Duration::from_millis(LEAD_IN_MS);
+    let search_from = (expected.as_secs_f64() * input_rate as f64) as usize;
+    let search_len = (input_rate as u64 * MAX_LATENCY_MS / 1000) as usize;
+
+    let reference = chirp(input_rate);
+    let (lag, correlation) = find_peak(&captured, &reference, search_from, search_len)
+        .filter(|&(_, corr)| corr >= MIN_CORRELATION)
+        .ok_or("No correlation found (no acoustic path between output and input?)")?;
+
+    Ok(LatencyMeasurement {
+        latency_ms: (lag - search_from) as f64 * 1000.0 / input_rate as f64,
+        correlation,
+        input_sample_rate: input_rate,
+    })
+}
+
+// Hann-windowed linear sweep in -0.5..0.5
+fn chirp(sample_rate: u32) -> Vec<f32> {
+    let len = (sample_rate as u64 * CHIRP_MS / 1000) as usize;
+    let duration = CHIRP_MS as f64 / 1000.0;
+    let sweep = (CHIRP_END_HZ - CHIRP_START_HZ) / (2.0 * duration);
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let phase = 2.0 * PI * (CHIRP_START_HZ * t + sweep * t * t);
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / len as f64).cos();
+            (0.5 * window * phase.sin()) as f32
+        })
+        .collect()
+}
+
+// Best lag in [from, from + len) by normalized cross-correlation
+fn find_peak(signal: &[i16], reference: &[f32], from: usize, len: usize) -> Option<(usize, f32)> {
+    let ref_energy: f64 = reference.iter().map(|&r| (r as f64).powi(2)).sum();
+    let last = (from + len).min(signal.len().checked_sub(reference.len())? + 1);
+
+    let mut best: Option<(usize, f32)> = None;
+    for lag in from..last {
+        let window = &signal[lag..lag + reference.len()];
+        let energy: f64 = window.iter().map(|&s| (s as f64).powi(2)).sum();
+        if energy < 1.0 {
+            continue; // Digital silence has no meaningful correlation
+        }
+        let dot: f64 = window.iter().zip(reference).map(|(&s, &r)| s as f64 * r as f64).sum();
+        let corr = (dot / (energy * ref_energy).sqrt()).abs() as f32;
+        if best.is_none_or(|(_, b)| corr > b) {
+            best = Some((lag, corr));
+        }
+    }
+    best
+}
@@ -0,0 +1,121 @@
+// Live PCM tap: the processed capture stream, sent to the frontend as binary
+//
+// Frames go over a Tauri `ipc::Channel` as raw bytes rather than events. An
+// event carries JSON, so PCM would have to be base64 (+33%) inside a JSON
+// string; a raw channel message is the bytes themselves. For 48 kHz mono that
+// is 96.6 KB/s of chunks against 130.8 KB/s of event payload JSON before the
+// event envelope (see `channel_beats_event_payload`).
+//
+// Every message is one chunk, all fields little-endian:
+//
+//   offset 0   u32  sequence number, from 0 per `start_live_pcm`
+//   offset 4   u32  sample rate (Hz)
+//   offset 8   u16  channels
+//   offset 10  u16  reserved, 0
+//   offset 12  i16  interleaved samples to the end of the message
+//
+// A gap in the sequence means chunks were lost (the tap was replaced or the
+// webview stalled); the frame count is (length - 12) / 2 / channels.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::ipc::{Channel, InvokeResponseBody};
+
+const CHUNK_HEADER_BYTES: usize = 12;
+
+struct LiveTap {
+    channel: Channel,
+    sequence: u32,
+}
+
+static TAP: Mutex<Option<LiveTap>> = Mutex::new(None);
+// Whether `TAP` holds a channel, so the capture thread only takes the lock
+// while someone is listening
+static TAP_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Start sending captured frames to `channel`, replacing any previous tap
+pub fn start_live_pcm(channel: Channel) {
+    *TAP.lock().unwrap() = Some(LiveTap { channel, sequence: 0 });
+    TAP_OPEN.store(true, Ordering::Release);
+}
+
+pub fn stop_live_pcm() {
+    TAP_OPEN.store(false, Ordering::Release);
+    *TAP.lock().unwrap() = None;
+}
+
+/// Called by the capture thread for each processed frame; a no-op unless a
+/// tap is open
+pub fn feed(frame: &[i16], sample_rate: u32, channels: u16) {
+    if !TAP_OPEN.load(Ordering::Acquire) {
+        return;
+    }
+    let Ok(mut tap) = TAP.lock() else {
+        return;
+    };
+    let Some(live) = tap.as_mut() else {
+        return;
+    };
+
+    let chunk = encode_chunk(live.sequence, sample_rate, channels, frame);
+    live.sequence = live.sequence.wrapping_add(1);
+
+    // The webview side is gone (page reloaded); nobody will read further chunks
+    if live.channel.send(InvokeResponseBody::Raw(chunk)).is_err() {
+        TAP_OPEN.store(false, Ordering::Release);
+        *tap = None;
+    }
+}
+
+fn encode_chunk(sequence: u32, sample_rate: u32, channels: u16, frame: &[i16]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(CHUNK_HEADER_BYTES + frame.len() * 2);
+    chunk.extend_from_slice(&sequence.to_le_bytes());
+    chunk.extend_from_slice(&sample_rate.to_le_bytes());
+    chunk.extend_from_slice(&channels.to_le_bytes());
+    chunk.extend_from_slice(&0u16.to_le_bytes());
+    for sample in frame {
+        chunk.extend_from_slice(&sample.to_le_bytes());
+    }
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    #[test]
+    fn chunk_layout() {
+        let chunk = encode_chunk(7, 48000, 2, &[1, -2, i16::MAX, i16::MIN]);
+        assert_eq!(chunk.len(), CHUNK_HEADER_BYTES + 8);
+        assert_eq!(chunk[0..4], 7u32.to_le_bytes());
+        assert_eq!(chunk[4..8], 48000u32.to_le_bytes());
+        assert_eq!(chunk[8..10], 2u16.to_le_bytes());
+        assert_eq!(chunk[10..12], [0, 0]);
+        let samples: Vec<i16> = chunk[12..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(samples, [1, -2, i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn channel_beats_event_payload() {
+        // One second of 48 kHz mono in the processing thread's 20 ms frames
+        let frame: Vec<i16> = (0..960).map(|i| (i * 37 % 65536 - 32768) as i16).collect();
+        let mut channel_bytes = 0;
+        let mut event_bytes = 0;
+        for sequence in 0..50u32 {
+            let chunk = encode_chunk(sequence, 48000, 1, &frame);
+            channel_bytes += chunk.len();
+            // The same frame as an event payload: header fields plus base64 PCM
+            let payload = serde_json::json!({
+                "sequence": sequence,
+                "sampleRate": 48000,
+                "channels": 1,
+                "data": base64::engine::general_purpose::STANDARD.encode(&chunk[CHUNK_HEADER_BYTES..]),
+            });
+            event_bytes += serde_json::to_vec(&payload).unwrap().len();
+        }
+        assert_eq!(channel_bytes, 96_600);
+        assert_eq!(event_bytes, 130_840);
+        assert!(channel_bytes * 4 <= event_bytes * 3, "{} vs {} bytes/s", channel_bytes, event_bytes);
+    }
+}
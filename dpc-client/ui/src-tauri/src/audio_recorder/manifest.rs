@@ -0,0 +1,81 @@
+// Provenance manifest written next to a recording with `write_manifest`
+//
+// `voice_123.wav` gets `voice_123.manifest.json` (version 1):
+//
+//   {
+//     "version": 1,
+//     "app_version": "0.9.0",
+//     "session_id": "…",
+//     "started_at": "2024-05-01T10:00:00+02:00",   // Local time
+//     "device": { "name": …, "id": …, "sample_rate": 48000, "channels": 2,
+//                 "sample_format": "f32", "input_volume": {…} | null },
+//     "config": { … },            // The RecordingConfig used, as sent
+//     "resample": {…} | null,     // Device rate -> file rate, if converted
+//     "duration_ms": 5230,
+//     "size_bytes": 502124
+//   }
+//
+// `config` carries every processing setting (channel weights, compressor,
+// noise subtraction, width, trims), so the manifest needs no parallel list.
+
+use std::path::{Path, PathBuf};
+
+use super::os_volume::InputVolume;
+use super::resample::ResampleInfo;
+use super::RecordingConfig;
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceManifest {
+    pub name: Option<String>,
+    pub id: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: String,
+    pub input_volume: Option<InputVolume>, // OS mixer level of the default input at start, where readable
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecordingManifest {
+    pub version: u32,
+    pub app_version: &'static str,
+    pub session_id: String,
+    pub started_at: String,
+    pub device: DeviceManifest,
+    pub config: RecordingConfig,
+    pub resample: Option<ResampleInfo>,
+    pub duration_ms: u64,
+    pub size_bytes: u64,
+}
+
+impl RecordingManifest {
+    /// Everything known at start; the encoder fills in the length on finalize
+    pub fn new(session_id: String, device: DeviceManifest, config: RecordingConfig, resample: Option<ResampleInfo>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            app_version: env!("CARGO_PKG_VERSION"),
+            session_id,
+            started_at: chrono::Local::now().to_rfc3339(),
+            device,
+            config,
+            resample,
+            duration_ms: 0,
+            size_bytes: 0,
+        }
+    }
+}
+
+/// `dir/voice_123.wav` -> `dir/voice_123.manifest.json`
+pub fn manifest_path(wav_path: &Path) -> PathBuf {
+    wav_path.with_extension("manifest.json")
+}
+
+pub fn write_manifest(wav_path: &Path, manifest: &RecordingManifest) -> Result<PathBuf, String> {
+    let path = manifest_path(wav_path);
+    let json = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
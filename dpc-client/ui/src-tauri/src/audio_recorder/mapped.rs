@@ -0,0 +1,100 @@
+// Memory-mapped output for `RecordingConfig::mmap_output`
+//
+// The file is preallocated and mapped up front, so writing a sample is a
+// copy into memory rather than a syscall. A recording that outgrows the
+// mapping doubles it (one remap, then cheap again); `finish` truncates the
+// file to what was written. Until then the file on disk is the full
+// preallocation, so a crash leaves silence after the header's data size
+// (or, if the sizes were never written, after the audio).
+
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+
+const MIN_CAPACITY: u64 = 64 * 1024;
+
+pub struct MappedWriter {
+    file: File,
+    map: Option<MmapMut>, // Unmapped only while the file is resized
+    pos: u64,             // Write cursor
+    len: u64,             // End of the written data
+}
+
+impl MappedWriter {
+    pub fn create(path: &str, capacity: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let mut writer = Self { file, map: None, pos: 0, len: 0 };
+        writer.remap(capacity.max(MIN_CAPACITY))?;
+        Ok(writer)
+    }
+
+    // Resize the file to `capacity` and map all of it. The old mapping is
+    // dropped first: Windows refuses to resize a mapped file.
+    fn remap(&mut self, capacity: u64) -> io::Result<()> {
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+        self.file.set_len(capacity)?;
+        // Safety: the file was created by us for this recording and nothing
+        // else is expected to resize it while mapped
+        self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        Ok(())
+    }
+
+    fn map(&mut self) -> &mut MmapMut {
+        self.map.as_mut().expect("mapping is only absent during remap")
+    }
+
+    /// Write dirty pages back to the file
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.map().flush()
+    }
+
+    /// Forget everything from `len` on
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.len = self.len.min(len);
+        self.pos = self.len;
+        Ok(())
+    }
+
+    /// Unmap and cut the file down to the written length
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+        self.file.set_len(self.len)?;
+        self.file.sync_data()
+    }
+}
+
+impl Write for MappedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.pos + buf.len() as u64;
+        let capacity = self.map().len() as u64;
+        if end > capacity {
+            self.remap(end.max(capacity * 2))?;
+        }
+        let start = self.pos as usize;
+        self.map()[start..start + buf.len()].copy_from_slice(buf);
+        self.pos = end;
+        self.len = self.len.max(end);
+        Ok(buf.len())
+    }
+
+    // Stores to the mapping are already visible to readers of the file
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MappedWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the file"))?;
+        Ok(self.pos)
+    }
+}
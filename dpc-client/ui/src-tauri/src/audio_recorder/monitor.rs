@@ -0,0 +1,272 @@
+// Input monitoring: hear yourself through the default output device
+//
+// The processing thread hands each processed frame to a `MonitorFeed`; the
+// output stream drains it with as little buffering as possible. Everything here (pan,
+// ducking) shapes only what is heard; the recorded file never passes through it.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use super::convert;
+use super::dsp;
+use super::resample::{self, ResamplerState};
+
+// Anything queued beyond this is dropped: stale monitor audio is worse than a gap
+const MAX_MONITOR_LATENCY_MS: u32 = 100;
+
+// Ducks fade in and out over this long rather than stepping (which clicks)
+const DUCK_RAMP_MS: f32 = 50.0;
+
+static PAN: AtomicU32 = AtomicU32::new(0); // f32 bits, -1.0 (left) ..= 1.0 (right)
+// Bumped each time monitoring turns on or off, so a `MonitorFeed` knows to
+// pick up the new output; starts above a fresh feed's 0 so every feed
+// looks once
+static MONITOR_GENERATION: AtomicU64 = AtomicU64::new(1);
+// The current duck, read lock-free by the output callback. Fields are
+// stored one at a time, start last; a duck landing mid-callback can pair a
+// new start with an old depth for that one callback, which the per-callback
+// glide hides.
+static DUCK_START_MS: AtomicU64 = AtomicU64::new(0); // Since `epoch()`
+static DUCK_HOLD_MS: AtomicU32 = AtomicU32::new(0);
+static DUCK_DB: AtomicU32 = AtomicU32::new(0); // f32 bits; 0.0 is no duck
+
+struct MonitorShared {
+    samples: Mutex<VecDeque<i16>>, // Mono, at `output_rate`
+    output_rate: u32,
+    max_samples: usize,
+}
+
+struct MonitorHandle {
+    shared: Arc<MonitorShared>,
+    stop_tx: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+fn current_monitor() -> &'static Mutex<Option<MonitorHandle>> {
+    static MONITOR: OnceLock<Mutex<Option<MonitorHandle>>> = OnceLock::new();
+    MONITOR.get_or_init(|| Mutex::new(None))
+}
+
+/// Turn monitoring on or off
+pub fn set_monitoring(enabled: bool) -> Result<(), String> {
+    let mut monitor = current_monitor().lock().unwrap();
+    if !enabled {
+        if let Some(handle) = monitor.take() {
+            MONITOR_GENERATION.fetch_add(1, Ordering::Release);
+            let _ = handle.stop_tx.send(());
+            let _ = handle.thread.join();
+        }
+        return Ok(());
+    }
+    if monitor.is_some() {
+        return Ok(());
+    }
+
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("No audio output device found")?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get default output config: {}", e))?;
+    let sample_format = config.sample_format();
+    let config = config.config();
+
+    epoch(); // Start the duck clock here rather than on the device thread
+    let max_samples = (config.sample_rate.0 * MAX_MONITOR_LATENCY_MS / 1000) as usize;
+    let shared = Arc::new(MonitorShared {
+        // Room for a frame on top of the cap, so feeding never reallocates
+        samples: Mutex::new(VecDeque::with_capacity(2 * max_samples)),
+        output_rate: config.sample_rate.0,
+        max_samples,
+    });
+
+    let (stop_tx, thread) = match sample_format {
+        SampleFormat::F32 => start_output::<f32>(device, config, shared.clone())?,
+        SampleFormat::I16 => start_output::<i16>(device, config, shared.clone())?,
+        SampleFormat::U16 => start_output::<u16>(device, config, shared.clone())?,
+        other => return Err(format!("Unsupported output sample format: {:?}", other)),
+    };
+    *monitor = Some(MonitorHandle { shared, stop_tx, thread });
+    MONITOR_GENERATION.fetch_add(1, Ordering::Release);
+    Ok(())
+}
+
+/// Route the monitor to one ear: -1.0 is left, 0.0 center, 1.0 right
+pub fn set_monitor_pan(pan: f32) {
+    let pan = if pan.is_finite() { pan.clamp(-1.0, 1.0) } else { 0.0 };
+    PAN.store(pan.to_bits(), Ordering::Relaxed);
+}
+
+/// The capture side of monitoring, owned by the processing thread. It keeps
+/// hold of the current output between frames and reuses its buffers, so a
+/// frame costs no global lock and no allocation.
+#[derive(Default)]
+pub struct MonitorFeed {
+    shared: Option<Arc<MonitorShared>>,
+    generation: u64,
+    resampler: ResamplerState,
+    input_rate: u32,
+    mono: Vec<i16>,
+    resampled: Vec<i16>,
+}
+
+impl MonitorFeed {
+    /// Queue a captured frame for monitoring (no-op when monitoring is off)
+    pub fn feed(&mut self, frame: &[i16], sample_rate: u32, channels: u16) {
+        let generation = MONITOR_GENERATION.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.shared = current_monitor().lock().ok().and_then(|m| m.as_ref().map(|h| h.shared.clone()));
+            self.resampler.reset();
+        }
+        let Some(shared) = &self.shared else {
+            return;
+        };
+        if self.input_rate != sample_rate {
+            self.resampler.reset();
+            self.input_rate = sample_rate;
+        }
+
+        self.mono.clear();
+        if channels > 1 {
+            self.mono.extend(frame.chunks_exact(channels as usize).map(dsp::downmix));
+        } else {
+            self.mono.extend_from_slice(frame);
+        }
+        self.resampled.clear();
+        resample::resample_mono_into(&self.mono, sample_rate, shared.output_rate, &mut self.resampler, &mut self.resampled);
+
+        let Ok(mut samples) = shared.samples.lock() else {
+            return;
+        };
+        samples.extend(&self.resampled);
+        let excess = samples.len().saturating_sub(shared.max_samples);
+        samples.drain(..excess);
+    }
+}
+
+/// Attenuate the monitor by `attenuation_db` for `duration_ms` (plus a short
+/// ramp either side), e.g. while a notification sound plays
+pub fn duck_monitor(duration_ms: u64, attenuation_db: f32) -> Result<(), String> {
+    if !attenuation_db.is_finite() || attenuation_db < 0.0 {
+        return Err(format!("Invalid attenuation: {} dB", attenuation_db));
+    }
+    DUCK_HOLD_MS.store(duration_ms.min(u32::MAX as u64) as u32, Ordering::Relaxed);
+    DUCK_DB.store(attenuation_db.to_bits(), Ordering::Relaxed);
+    DUCK_START_MS.store(epoch().elapsed().as_millis() as u64, Ordering::Release);
+    Ok(())
+}
+
+// Clock for `DUCK_START_MS`, so a start time fits in an atomic
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+// Linear gain from the duck envelope right now (1.0 when not ducking)
+fn duck_gain() -> f32 {
+    let started = DUCK_START_MS.load(Ordering::Acquire);
+    let attenuation_db = f32::from_bits(DUCK_DB.load(Ordering::Relaxed));
+    let hold_ms = DUCK_HOLD_MS.load(Ordering::Relaxed) as f32;
+    let t = epoch().elapsed().as_millis().saturating_sub(started as u128) as f32;
+    let depth = if t < DUCK_RAMP_MS {
+        t / DUCK_RAMP_MS
+    } else if t < DUCK_RAMP_MS + hold_ms {
+        1.0
+    } else if t < 2.0 * DUCK_RAMP_MS + hold_ms {
+        1.0 - (t - DUCK_RAMP_MS - hold_ms) / DUCK_RAMP_MS
+    } else {
+        return 1.0;
+    };
+    dsp::dbfs_to_linear(-attenuation_db * depth)
+}
+
+// Equal-power gains for the current pan position
+fn pan_gains() -> (f32, f32) {
+    let pan = f32::from_bits(PAN.load(Ordering::Relaxed));
+    let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+fn start_output<T>(
+    device: Device,
+    config: StreamConfig,
+    shared: Arc<MonitorShared>,
+) -> Result<(Sender<()>, JoinHandle<()>), String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let channels = config.channels as usize;
+
+    let thread = thread::spawn(move || {
+        let mut last_duck = 1.0f32;
+        let data_callback = move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let (left, right) = pan_gains();
+            // Glide from the previous callback's duck gain to this one's
+            let duck = duck_gain();
+            let frames = (out.len() / channels.max(1)).max(1) as f32;
+            // Never wait on (or panic over) the capture side from the device
+            // thread; a callback of silence is the lesser evil
+            let Ok(mut samples) = shared.samples.try_lock() else {
+                out.fill(T::from_sample(0.0f32));
+                return;
+            };
+            for (i, frame) in out.chunks_mut(channels).enumerate() {
+                let gain = last_duck + (duck - last_duck) * (i as f32 + 1.0) / frames;
+                let s = convert::i16_to_f32(samples.pop_front().unwrap_or(0)) * gain;
+                for (ch, slot) in frame.iter_mut().enumerate() {
+                    let gain = match (channels, ch) {
+                        (1, _) => 1.0,
+                        (_, 0) => left,
+                        (_, 1) => right,
+                        _ => 0.0,
+                    };
+                    *slot = T::from_sample(s * gain);
+                }
+            }
+            last_duck = duck;
+        };
+        let err_callback = |err| {
+            eprintln!("Audio monitor error: {}", err);
+        };
+
+        let stream = match device
+            .build_output_stream(&config, data_callback, err_callback, None)
+            .map_err(|e| format!("Failed to build monitor stream: {}", e))
+            .and_then(|stream| {
+                stream
+                    .play()
+                    .map(|_| stream)
+                    .map_err(|e| format!("Failed to play monitor stream: {}", e))
+            }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready_tx.send(Ok(()));
+        let _ = stop_rx.recv();
+        drop(stream);
+    });
+
+    match ready_rx.recv() {
+        Ok(Ok(())) => Ok((stop_tx, thread)),
+        Ok(Err(e)) => {
+            let _ = thread.join();
+            Err(e)
+        }
+        Err(_) => {
+            let _ = thread.join();
+            Err("Audio monitor thread exited unexpectedly".to_string())
+        }
+    }
+}
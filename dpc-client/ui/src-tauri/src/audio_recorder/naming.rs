@@ -0,0 +1,61 @@
+// Recording filenames from templates
+//
+// Placeholders: `{timestamp}` (unix seconds), `{date}` (YYYY-MM-DD),
+// `{time}` (HH-MM-SS), both local, and `{seq}`, a counter persisted in the
+// app data dir so it keeps counting across restarts. The default template
+// `voice_{timestamp}` gives the original `voice_<secs>` names. The container
+// extension is added by the caller.
+
+use std::sync::Mutex;
+
+use super::events;
+
+pub const DEFAULT_TEMPLATE: &str = "voice_{timestamp}";
+const SEQUENCE_FILE: &str = "recording_sequence";
+
+// Serializes read-increment-write of the counter file
+static SEQUENCE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Expand a template into a safe file stem
+pub fn expand_template(template: &str) -> Result<String, String> {
+    let now = chrono::Local::now();
+    let mut name = template
+        .replace("{timestamp}", &now.timestamp().to_string())
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H-%M-%S").to_string());
+    if name.contains("{seq}") {
+        name = name.replace("{seq}", &format!("{:04}", next_sequence()?));
+    }
+
+    let name = sanitize(&name);
+    if name.is_empty() {
+        return Err(format!("Filename template '{}' expands to an empty name", template));
+    }
+    Ok(name)
+}
+
+// Keep names portable: no path separators or characters Windows rejects
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "-_. ".contains(c) { c } else { '_' })
+        .collect();
+    cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string()
+}
+
+fn next_sequence() -> Result<u64, String> {
+    let _guard = SEQUENCE_LOCK.lock().unwrap();
+    let path = events::app_data_file(SEQUENCE_FILE)?;
+    let current = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    let next = current + 1;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(&path, next.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(next)
+}
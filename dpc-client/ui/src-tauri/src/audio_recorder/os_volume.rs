@@ -0,0 +1,104 @@
+// OS-level input volume: the level the device/mixer delivers to every app
+//
+// This is not a software gain on our samples; changing it changes what the
+// microphone sends before cpal ever sees it. cpal has no API for this, so
+// each platform gets a `VolumeControl` backend, and platforms without one
+// return `RecorderError::Unsupported`. Only the default input is covered.
+//
+// Linux: PulseAudio/PipeWire through `pactl` on `@DEFAULT_SOURCE@`.
+
+use super::error::RecorderError;
+
+/// Volumes are linear fractions of the device's nominal level (1.0 = 100%)
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct InputVolume {
+    pub volume: f32,
+    pub min: f32,
+    pub max: f32, // Above 1.0 the mixer amplifies in software
+}
+
+trait VolumeControl {
+    fn get(&self) -> Result<InputVolume, RecorderError>;
+    fn set(&self, volume: f32) -> Result<(), RecorderError>;
+}
+
+// Fallback for platforms without a backend
+#[cfg(not(target_os = "linux"))]
+struct NoVolumeControl;
+
+#[cfg(not(target_os = "linux"))]
+impl VolumeControl for NoVolumeControl {
+    fn get(&self) -> Result<InputVolume, RecorderError> {
+        Err(RecorderError::Unsupported("OS input volume".to_string()))
+    }
+
+    fn set(&self, _volume: f32) -> Result<(), RecorderError> {
+        Err(RecorderError::Unsupported("OS input volume".to_string()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod pactl {
+    use super::{InputVolume, RecorderError, VolumeControl};
+    use std::process::Command;
+
+    const SOURCE: &str = "@DEFAULT_SOURCE@";
+    const MAX_VOLUME: f32 = 1.5; // pavucontrol's ceiling
+
+    pub struct Pactl;
+
+    fn run(args: &[&str]) -> Result<String, RecorderError> {
+        let output = Command::new("pactl").args(args).output().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => RecorderError::Unsupported("pactl is not installed".to_string()),
+            _ => RecorderError::Other(format!("Failed to run pactl: {}", e)),
+        })?;
+        if !output.status.success() {
+            return Err(format!("pactl failed: {}", String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    impl VolumeControl for Pactl {
+        fn get(&self) -> Result<InputVolume, RecorderError> {
+            // "Volume: front-left: 65536 / 100% / 0.00 dB,   front-right: ..."
+            let output = run(&["get-source-volume", SOURCE])?;
+            let percents: Vec<f32> = output
+                .split_whitespace()
+                .filter_map(|word| word.strip_suffix('%')?.parse().ok())
+                .collect();
+            if percents.is_empty() {
+                return Err(format!("Unexpected pactl output: {}", output.trim()).into());
+            }
+            let volume = percents.iter().sum::<f32>() / percents.len() as f32 / 100.0;
+            Ok(InputVolume { volume, min: 0.0, max: MAX_VOLUME })
+        }
+
+        fn set(&self, volume: f32) -> Result<(), RecorderError> {
+            let percent = format!("{}%", (volume * 100.0).round() as u32);
+            run(&["set-source-volume", SOURCE, &percent]).map(|_| ())
+        }
+    }
+}
+
+fn backend() -> &'static dyn VolumeControl {
+    #[cfg(target_os = "linux")]
+    return &pactl::Pactl;
+    #[cfg(not(target_os = "linux"))]
+    return &NoVolumeControl;
+}
+
+/// Current OS input level of the default device, with its range
+pub fn get_input_volume() -> Result<InputVolume, RecorderError> {
+    backend().get()
+}
+
+/// Set the OS input level of the default device; returns the level it
+/// reports afterwards (mixers round to their own steps)
+pub fn set_input_volume(volume: f32) -> Result<InputVolume, RecorderError> {
+    let current = backend().get()?;
+    if !volume.is_finite() || !(current.min..=current.max).contains(&volume) {
+        return Err(format!("Input volume must be between {} and {}, got {}", current.min, current.max, volume).into());
+    }
+    backend().set(volume)?;
+    backend().get()
+}
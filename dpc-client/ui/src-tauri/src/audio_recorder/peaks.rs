@@ -0,0 +1,147 @@
+// Waveform peaks and the `.peaks.json` sidecar cache
+//
+// Sidecar format (version 1), written next to `voice_123.wav` as
+// `voice_123.peaks.json`:
+//
+//   {
+//     "version": 1,
+//     "sample_rate": 48000,      // of the source WAV
+//     "channels": 1,
+//     "samples_per_peak": 480,   // frames (per-channel samples) per bucket
+//     "peaks": [[-1200, 1350], ...]
+//   }
+//
+// Each entry is the [min, max] raw 16-bit sample value over one bucket,
+// across all channels. Divide by 32768 for a -1.0..1.0 range.
+
+use std::path::{Path, PathBuf};
+
+use super::wav::WavReader;
+
+const PEAKS_VERSION: u32 = 1;
+const PEAK_BUCKET_MS: u32 = 10;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Peaks {
+    pub version: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples_per_peak: u32,
+    pub peaks: Vec<(i16, i16)>,
+}
+
+impl Peaks {
+    /// Merge adjacent buckets so there are at most `points` entries
+    pub fn downsample(mut self, points: usize) -> Self {
+        if points == 0 || self.peaks.len() <= points {
+            return self;
+        }
+        let group = self.peaks.len().div_ceil(points);
+        self.peaks = self
+            .peaks
+            .chunks(group)
+            .map(|c| {
+                let min = c.iter().map(|p| p.0).min().unwrap_or(0);
+                let max = c.iter().map(|p| p.1).max().unwrap_or(0);
+                (min, max)
+            })
+            .collect();
+        self.samples_per_peak *= group as u32;
+        self
+    }
+}
+
+/// Accumulates peaks incrementally as samples are written
+pub struct PeaksBuilder {
+    peaks: Peaks,
+    bucket_samples: usize, // Interleaved samples per bucket
+    filled: usize,
+    current: (i16, i16),
+}
+
+impl PeaksBuilder {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let samples_per_peak = (sample_rate * PEAK_BUCKET_MS / 1000).max(1);
+        Self {
+            peaks: Peaks {
+                version: PEAKS_VERSION,
+                sample_rate,
+                channels,
+                samples_per_peak,
+                peaks: Vec::new(),
+            },
+            bucket_samples: samples_per_peak as usize * channels.max(1) as usize,
+            filled: 0,
+            current: (i16::MAX, i16::MIN),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[i16]) {
+        for &s in samples {
+            self.current.0 = self.current.0.min(s);
+            self.current.1 = self.current.1.max(s);
+            self.filled += 1;
+            if self.filled == self.bucket_samples {
+                self.peaks.peaks.push(self.current);
+                self.filled = 0;
+                self.current = (i16::MAX, i16::MIN);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Peaks {
+        if self.filled > 0 {
+            self.peaks.peaks.push(self.current);
+        }
+        self.peaks
+    }
+}
+
+/// `dir/voice_123.wav` -> `dir/voice_123.peaks.json`
+pub fn sidecar_path(wav_path: &Path) -> PathBuf {
+    wav_path.with_extension("peaks.json")
+}
+
+pub fn write_sidecar(wav_path: &Path, peaks: &Peaks) -> Result<PathBuf, String> {
+    let path = sidecar_path(wav_path);
+    let json = serde_json::to_vec(peaks)
+        .map_err(|e| format!("Failed to serialize peaks: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Waveform peaks for a recording, from the sidecar when present
+pub fn generate_waveform(path: String, points: Option<usize>) -> Result<Peaks, String> {
+    let sidecar = sidecar_path(Path::new(&path));
+    // A sidecar older than its WAV describes audio that has since been edited
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    let fresh = matches!(
+        (modified(&sidecar), modified(Path::new(&path))),
+        (Some(cache), Some(wav)) if cache >= wav
+    );
+    let cached = fresh
+        .then(|| std::fs::read(&sidecar).ok())
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice::<Peaks>(&bytes).ok())
+        .filter(|p| p.version == PEAKS_VERSION);
+
+    let peaks = match cached {
+        Some(peaks) => peaks,
+        None => {
+            let mut reader = WavReader::open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+            let spec = reader.spec();
+            let samples = reader.read_samples()
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let mut builder = PeaksBuilder::new(spec.sample_rate, spec.channels);
+            builder.push(&samples);
+            builder.finish()
+        }
+    };
+
+    Ok(match points {
+        Some(points) => peaks.downsample(points),
+        None => peaks,
+    })
+}
@@ -0,0 +1,267 @@
+// Preview playback of WAV recordings through the default output device
+//
+// The output device may not run at the file's rate (e.g. a 44.1 kHz file on
+// a 48 kHz-only device); playing it unconverted would shift the pitch. We pick
+// an output config at the file's rate when the device offers one, otherwise
+// resample the whole file up front (voice notes are small).
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::crypto::{self, EncryptionKey};
+use super::dsp;
+use super::events;
+use super::resample::{self, ResamplerState};
+use super::tools;
+use super::wav::WavReader;
+
+const LEVEL_EVENT_RATE_HZ: u32 = 20;
+
+// f32 bits: RMS of the audio most recently handed to the output, 0.0-1.0
+static PLAYBACK_LEVEL: AtomicU32 = AtomicU32::new(0);
+
+/// Payload of `playback-level`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlaybackLevelEvent {
+    pub level: f32,       // 0.0-1.0; a final 0 is sent when playback ends
+    pub position_ms: u64, // Into the (possibly resampled) audio
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PlaybackInfo {
+    pub duration_ms: u64,
+    pub file_sample_rate: u32,
+    pub output_sample_rate: u32,
+    pub output_channels: u16,
+    pub resampled: bool,
+}
+
+struct PlaybackHandle {
+    stop_tx: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+fn current_playback() -> &'static Mutex<Option<PlaybackHandle>> {
+    static PLAYBACK: OnceLock<Mutex<Option<PlaybackHandle>>> = OnceLock::new();
+    PLAYBACK.get_or_init(|| Mutex::new(None))
+}
+
+/// Play a WAV file, replacing any preview already playing
+pub fn play_audio(path: String) -> Result<PlaybackInfo, String> {
+    let (spec, _, _, samples) = tools::read_wav(&path)?;
+    play_samples(samples, spec.sample_rate, spec.channels)
+}
+
+/// Play a `.wav.enc`, decrypting in memory only
+pub fn play_encrypted(path: String, key: &EncryptionKey) -> Result<PlaybackInfo, String> {
+    let wav = crypto::decrypt_file(&path, key)
+        .map_err(|e| format!("Failed to decrypt {}: {}", path, e))?;
+    let reader = WavReader::from_bytes(wav)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut reader = tools::checked(reader, &path)?;
+    let spec = reader.spec();
+    let samples = reader.read_samples()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    play_samples(samples, spec.sample_rate, spec.channels)
+}
+
+/// Play interleaved 16-bit audio from memory, replacing any preview already playing
+pub(super) fn play_samples(samples: Vec<i16>, sample_rate: u32, channels: u16) -> Result<PlaybackInfo, String> {
+    stop_playback();
+
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or("No audio output device found")?;
+    let (stream_config, sample_format) = choose_output_config(&device, sample_rate, channels)?;
+
+    // Fast path: the device runs at the file's rate, no conversion needed
+    let resampled = stream_config.sample_rate.0 != sample_rate;
+    let samples = if resampled {
+        let mut states = vec![ResamplerState::default(); channels as usize];
+        resample::resample_interleaved(
+            &samples,
+            channels as usize,
+            sample_rate,
+            stream_config.sample_rate.0,
+            &mut states,
+        )
+    } else {
+        samples
+    };
+    let samples = map_channels(&samples, channels as usize, stream_config.channels as usize);
+
+    let frames = samples.len() / stream_config.channels as usize;
+    let info = PlaybackInfo {
+        duration_ms: frames as u64 * 1000 / stream_config.sample_rate.0 as u64,
+        file_sample_rate: sample_rate,
+        output_sample_rate: stream_config.sample_rate.0,
+        output_channels: stream_config.channels,
+        resampled,
+    };
+
+    let handle = match sample_format {
+        SampleFormat::F32 => start_output::<f32>(device, stream_config, samples)?,
+        SampleFormat::I16 => start_output::<i16>(device, stream_config, samples)?,
+        SampleFormat::U16 => start_output::<u16>(device, stream_config, samples)?,
+        other => return Err(format!("Unsupported output sample format: {:?}", other)),
+    };
+    *current_playback().lock().unwrap() = Some(handle);
+
+    Ok(info)
+}
+
+/// Level of the preview playing now; 0.0 when nothing is playing
+pub fn get_playback_level() -> f32 {
+    f32::from_bits(PLAYBACK_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Stop the current preview, if any
+pub fn stop_playback() {
+    let handle = current_playback().lock().unwrap().take();
+    if let Some(handle) = handle {
+        let _ = handle.stop_tx.send(());
+        let _ = handle.thread.join();
+    }
+}
+
+// Prefer a supported config at the file's own rate; fall back to the default
+fn choose_output_config(
+    device: &Device,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<(StreamConfig, SampleFormat), String> {
+    let rate = cpal::SampleRate(sample_rate);
+    if let Ok(configs) = device.supported_output_configs() {
+        let mut matching: Vec<_> = configs
+            .filter(|c| c.min_sample_rate() <= rate && rate <= c.max_sample_rate())
+            .collect();
+        // Same channel count first, then the smallest that can hold the file
+        matching.sort_by_key(|c| (c.channels() != channels, c.channels()));
+        if let Some(config) = matching.into_iter().next() {
+            let config = config.with_sample_rate(rate);
+            return Ok((config.config(), config.sample_format()));
+        }
+    }
+
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get default output config: {}", e))?;
+    Ok((config.config(), config.sample_format()))
+}
+
+// Convert interleaved audio between channel counts
+fn map_channels(samples: &[i16], from: usize, to: usize) -> Vec<i16> {
+    if from == to {
+        return samples.to_vec();
+    }
+    let mut output = Vec::with_capacity(samples.len() / from * to);
+    for frame in samples.chunks_exact(from) {
+        if to == 1 {
+            output.push(dsp::downmix(frame));
+        } else if from == 1 {
+            output.extend(std::iter::repeat_n(frame[0], to));
+        } else {
+            output.extend((0..to).map(|ch| frame.get(ch).copied().unwrap_or(0)));
+        }
+    }
+    output
+}
+
+fn start_output<T>(
+    device: Device,
+    config: StreamConfig,
+    samples: Vec<i16>,
+) -> Result<PlaybackHandle, String>
+where
+    T: SizedSample + FromSample<i16>,
+{
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+    let thread = thread::spawn(move || {
+        let samples = Arc::new(samples);
+        let position = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let (cb_samples, cb_position, cb_finished) = (samples.clone(), position.clone(), finished.clone());
+        let data_callback = move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let start = cb_position.load(Ordering::Relaxed);
+            for (i, slot) in out.iter_mut().enumerate() {
+                *slot = T::from_sample(cb_samples.get(start + i).copied().unwrap_or(0));
+            }
+            let end = (start + out.len()).min(cb_samples.len());
+            let level = dsp::frame_rms(&cb_samples[start.min(end)..end]);
+            PLAYBACK_LEVEL.store(level.to_bits(), Ordering::Relaxed);
+            cb_position.store(end, Ordering::Relaxed);
+            if end >= cb_samples.len() {
+                cb_finished.store(true, Ordering::Relaxed);
+            }
+        };
+        let err_callback = |err| {
+            eprintln!("Audio output error: {}", err);
+        };
+
+        let stream = match device
+            .build_output_stream(&config, data_callback, err_callback, None)
+            .map_err(|e| format!("Failed to build output stream: {}", e))
+            .and_then(|stream| {
+                stream
+                    .play()
+                    .map(|_| stream)
+                    .map_err(|e| format!("Failed to play stream: {}", e))
+            }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready_tx.send(Ok(()));
+
+        // Play until the buffer drains or stop is requested
+        let frame_rate = config.sample_rate.0 as u64 * config.channels as u64;
+        let position_ms = |position: usize| position as u64 * 1000 / frame_rate.max(1);
+        let mut throttle = events::Throttle::new(LEVEL_EVENT_RATE_HZ);
+        loop {
+            match stop_rx.recv_timeout(Duration::from_millis(50)) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    if finished.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if throttle.ready() {
+                        events::emit(events::PLAYBACK_LEVEL, PlaybackLevelEvent {
+                            level: get_playback_level(),
+                            position_ms: position_ms(position.load(Ordering::Relaxed)),
+                        });
+                    }
+                }
+            }
+        }
+        drop(stream);
+        PLAYBACK_LEVEL.store(0, Ordering::Relaxed);
+        events::emit(events::PLAYBACK_LEVEL, PlaybackLevelEvent {
+            level: 0.0,
+            position_ms: position_ms(position.load(Ordering::Relaxed)),
+        });
+        events::emit(events::PLAYBACK_FINISHED, ());
+    });
+
+    match ready_rx.recv() {
+        Ok(Ok(())) => Ok(PlaybackHandle { stop_tx, thread }),
+        Ok(Err(e)) => {
+            let _ = thread.join();
+            Err(e)
+        }
+        Err(_) => {
+            let _ = thread.join();
+            Err("Audio output thread exited unexpectedly".to_string())
+        }
+    }
+}
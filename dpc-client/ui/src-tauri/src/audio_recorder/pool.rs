@@ -0,0 +1,121 @@
+// Recycled buffers for handing audio out of a device callback
+//
+// A callback must not block or allocate, so it never takes a lock or builds
+// a new Vec: it takes an empty buffer from a fixed pool, fills it and sends
+// it on, and the consumer hands each buffer back once it has copied it out.
+// With every buffer still out (the consumer fell behind) the callback's
+// audio is dropped and counted as an overrun instead of queueing without
+// bound. Both sides are bounded channels, which never allocate per message.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub struct PoolSender<T> {
+    free: Receiver<Vec<T>>,
+    filled: SyncSender<Vec<T>>,
+    overruns: Arc<AtomicU64>,
+}
+
+pub struct PoolReceiver<T> {
+    free: SyncSender<Vec<T>>,
+    filled: Receiver<Vec<T>>,
+    overruns: Arc<AtomicU64>,
+}
+
+/// `buffers` buffers (minimum 1) of `capacity` items each, allocated here
+/// rather than on the callback's thread
+pub fn buffer_pool<T>(buffers: usize, capacity: usize) -> (PoolSender<T>, PoolReceiver<T>) {
+    buffer_pool_counting(buffers, capacity, Arc::new(AtomicU64::new(0)))
+}
+
+/// `buffer_pool`, counting overruns into `overruns`, which several pools
+/// can share
+pub fn buffer_pool_counting<T>(
+    buffers: usize,
+    capacity: usize,
+    overruns: Arc<AtomicU64>,
+) -> (PoolSender<T>, PoolReceiver<T>) {
+    let buffers = buffers.max(1);
+    let (free_tx, free_rx) = mpsc::sync_channel(buffers);
+    let (filled_tx, filled_rx) = mpsc::sync_channel(buffers);
+    for _ in 0..buffers {
+        let _ = free_tx.try_send(Vec::with_capacity(capacity));
+    }
+    (
+        PoolSender { free: free_rx, filled: filled_tx, overruns: overruns.clone() },
+        PoolReceiver { free: free_tx, filled: filled_rx, overruns },
+    )
+}
+
+impl<T> PoolSender<T> {
+    /// Fill a free buffer with `fill` and pass it on. Returns false, and
+    /// counts an overrun, when no buffer is free.
+    pub fn send_with(&self, fill: impl FnOnce(&mut Vec<T>)) -> bool {
+        let Ok(mut buffer) = self.free.try_recv() else {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+            return false;
+        };
+        buffer.clear();
+        fill(&mut buffer);
+        // Never full, as there are only as many buffers as slots; fails
+        // only once the consumer has gone
+        let _ = self.filled.try_send(buffer);
+        true
+    }
+}
+
+impl<T> PoolReceiver<T> {
+    /// The next filled buffer, once one arrives. `None` once the sender has
+    /// gone and every buffer it sent has been received.
+    pub fn recv(&self) -> Option<Vec<T>> {
+        self.filled.recv().ok()
+    }
+
+    /// `recv`, giving up after `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<T>, RecvTimeoutError> {
+        self.filled.recv_timeout(timeout)
+    }
+
+    /// Give a received buffer back for the sender to fill again
+    pub fn recycle(&self, buffer: Vec<T>) {
+        let _ = self.free.try_send(buffer);
+    }
+
+    /// Callbacks whose audio was dropped for want of a free buffer
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_and_a_full_pool_counts_overruns() {
+        let (tx, rx) = buffer_pool::<i16>(2, 4);
+        assert!(tx.send_with(|b| b.extend([1, 2])));
+        assert!(tx.send_with(|b| b.extend([3])));
+        // Both buffers are with the consumer: the third callback is dropped
+        assert!(!tx.send_with(|b| b.extend([4])));
+        assert_eq!(rx.overruns(), 1);
+
+        let first = rx.recv().unwrap();
+        assert_eq!(first, [1, 2]);
+        let reused = first.as_ptr();
+        rx.recycle(first);
+        let second = rx.recv().unwrap();
+        assert_eq!(second, [3]);
+        rx.recycle(second);
+
+        // The recycled buffer comes back cleared, without a new allocation
+        assert!(tx.send_with(|b| b.push(5)));
+        let again = rx.recv().unwrap();
+        assert_eq!((again.as_slice(), again.as_ptr()), (&[5][..], reused));
+
+        drop(tx);
+        assert_eq!(rx.recv(), None);
+    }
+}
@@ -0,0 +1,272 @@
+// Named recording presets
+//
+// Built-in presets are fixed; user presets are stored as a JSON map of
+// name -> `RecordingConfig` in `recording_presets.json` under the app data
+// dir. `apply_preset` selects the preset used when `start_recording` is
+// called without an explicit config or preset name.
+//
+// The config of the last recording that started successfully is kept in
+// `last_recording_config.json`, so a returning user gets their previous
+// settings. `resolve_config` documents the full precedence. The encryption
+// key is never stored; an encrypted last recording is marked instead, so
+// the next one can't quietly come out in plaintext.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::events;
+use super::RecordingConfig;
+
+const PRESETS_FILE: &str = "recording_presets.json";
+const LAST_CONFIG_FILE: &str = "last_recording_config.json";
+pub const VOICE_NOTE: &str = "voice_note";
+pub const MUSIC: &str = "music";
+
+static ACTIVE_PRESET: Mutex<Option<String>> = Mutex::new(None);
+
+// `last_recording_config.json`: the config, and whether it carried a key
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LastConfig {
+    #[serde(flatten)]
+    config: RecordingConfig,
+    #[serde(default)] // Absent in files from before the marker
+    encrypted: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PresetInfo {
+    pub name: String,
+    pub builtin: bool,
+    pub config: RecordingConfig,
+}
+
+fn builtin(name: &str) -> Option<RecordingConfig> {
+    match name {
+        // The messenger's voice message format (48 kHz mono)
+        VOICE_NOTE => Some(RecordingConfig::default()),
+        // Keep the device's native rate and channels
+        MUSIC => Some(RecordingConfig {
+            passthrough: true,
+            write_peaks: true,
+            ..RecordingConfig::default()
+        }),
+        _ => None,
+    }
+}
+
+fn presets_path() -> Result<PathBuf, String> {
+    events::app_data_file(PRESETS_FILE)
+}
+
+fn load_user_presets() -> Result<BTreeMap<String, RecordingConfig>, String> {
+    let path = presets_path()?;
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Built-in presets first, then saved ones by name
+pub fn list_presets() -> Result<Vec<PresetInfo>, String> {
+    let mut presets: Vec<PresetInfo> = [VOICE_NOTE, MUSIC]
+        .iter()
+        .filter_map(|&name| {
+            builtin(name).map(|config| PresetInfo { name: name.to_string(), builtin: true, config })
+        })
+        .collect();
+    presets.extend(
+        load_user_presets()?
+            .into_iter()
+            .map(|(name, config)| PresetInfo { name, builtin: false, config }),
+    );
+    Ok(presets)
+}
+
+/// Save a user preset; an existing one is only replaced with `overwrite`
+pub fn save_preset(name: String, config: RecordingConfig, overwrite: bool) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+    if builtin(&name).is_some() {
+        return Err(format!("'{}' is a built-in preset and cannot be replaced", name));
+    }
+    config.validate()?;
+
+    let mut presets = load_user_presets()?;
+    if presets.contains_key(&name) && !overwrite {
+        return Err(format!("Preset '{}' already exists", name));
+    }
+    presets.insert(name, config);
+
+    let path = presets_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let json = serde_json::to_vec_pretty(&presets)
+        .map_err(|e| format!("Failed to serialize presets: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Look up a preset by name
+pub fn load_preset(name: &str) -> Result<RecordingConfig, String> {
+    if let Some(config) = builtin(name) {
+        return Ok(config);
+    }
+    load_user_presets()?
+        .remove(name)
+        .ok_or_else(|| format!("Unknown preset: {}", name))
+}
+
+/// Make `name` the preset used by default, returning its config
+pub fn apply_preset(name: String) -> Result<RecordingConfig, String> {
+    let config = load_preset(&name)?;
+    *ACTIVE_PRESET.lock().unwrap() = Some(name);
+    Ok(config)
+}
+
+/// The config for a recording, first match wins:
+/// 1. the explicit `config` argument
+/// 2. the `preset` argument
+/// 3. the preset chosen with `apply_preset` this session
+/// 4. the config of the last recording that started (`remember_config`);
+///    an error if that recording was encrypted, as its key isn't kept
+/// 5. the voice note defaults
+pub fn resolve_config(config: Option<RecordingConfig>, preset: Option<String>) -> Result<RecordingConfig, String> {
+    let preset = preset.or_else(|| ACTIVE_PRESET.lock().unwrap().clone());
+    let last_path = events::app_data_file(LAST_CONFIG_FILE).ok();
+    resolve_from(config, preset, last_path.as_deref())
+}
+
+// `resolve_config` once the session preset and the last config's path are known
+fn resolve_from(
+    config: Option<RecordingConfig>,
+    preset: Option<String>,
+    last_path: Option<&Path>,
+) -> Result<RecordingConfig, String> {
+    if let Some(config) = config {
+        return Ok(config);
+    }
+    match preset {
+        Some(name) => load_preset(&name),
+        None => match last_path.and_then(last_config) {
+            Some(last) if last.encrypted => Err(
+                "The last recording was encrypted; pass a config with its encryption_key to record with it again"
+                    .to_string(),
+            ),
+            Some(last) => Ok(last.config),
+            None => Ok(RecordingConfig::default()),
+        },
+    }
+}
+
+// A missing or unreadable file (e.g. from an older version that no longer
+// validates) just means "no previous config"
+fn last_config(path: &Path) -> Option<LastConfig> {
+    let bytes = std::fs::read(path).ok()?;
+    match serde_json::from_slice::<LastConfig>(&bytes) {
+        Ok(last) if last.config.validate().is_ok() => Some(last),
+        _ => {
+            eprintln!("Ignoring unusable {}", path.display());
+            None
+        }
+    }
+}
+
+/// Persist the config of a recording that started, for `resolve_config`.
+/// The encryption key is never serialized, only that there was one.
+pub fn remember_config(config: &RecordingConfig) -> Result<(), String> {
+    save_config(&events::app_data_file(LAST_CONFIG_FILE)?, config)
+}
+
+fn save_config(path: &Path, config: &RecordingConfig) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let last = LastConfig { config: config.clone(), encrypted: config.encryption_key.is_some() };
+    let json = serde_json::to_vec_pretty(&last)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    std::fs::write(path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dpc-presets-test-{}-{}", std::process::id(), name))
+    }
+
+    fn with_min_duration(ms: u64) -> RecordingConfig {
+        RecordingConfig {
+            min_duration_ms: ms,
+            ..RecordingConfig::default()
+        }
+    }
+
+    #[test]
+    fn precedence_explicit_then_preset_then_last_then_default() {
+        let last = temp_path("last.json");
+        save_config(&last, &with_min_duration(222)).unwrap();
+
+        // 1. An explicit config beats everything
+        let config = resolve_from(Some(with_min_duration(111)), Some(MUSIC.into()), Some(&last)).unwrap();
+        assert_eq!(config.min_duration_ms, 111);
+        assert!(!config.passthrough);
+
+        // 2./3. A preset (from the argument or the session) beats the last config
+        let config = resolve_from(None, Some(MUSIC.into()), Some(&last)).unwrap();
+        assert!(config.passthrough);
+        assert_eq!(config.min_duration_ms, RecordingConfig::default().min_duration_ms);
+
+        // 4. The persisted config beats the defaults
+        let config = resolve_from(None, None, Some(&last)).unwrap();
+        assert_eq!(config.min_duration_ms, 222);
+
+        // 5. Nothing persisted (or unreadable) falls back to the defaults
+        std::fs::write(&last, b"not json").unwrap();
+        for path in [Some(last.as_path()), Some(temp_path("missing.json").as_path()), None] {
+            let config = resolve_from(None, None, path).unwrap();
+            assert_eq!(
+                serde_json::to_value(&config).unwrap(),
+                serde_json::to_value(RecordingConfig::default()).unwrap()
+            );
+        }
+        let _ = std::fs::remove_file(&last);
+    }
+
+    #[test]
+    fn an_encrypted_last_recording_is_not_resumed_in_plaintext() {
+        let last = temp_path("encrypted.json");
+        let encrypted = RecordingConfig {
+            encryption_key: Some(super::super::crypto::EncryptionKey::from_hex(&"ab".repeat(32)).unwrap()),
+            ..with_min_duration(222)
+        };
+        save_config(&last, &encrypted).unwrap();
+        let stored = std::fs::read_to_string(&last).unwrap();
+        assert!(!stored.contains(&"ab".repeat(32)), "key written out: {}", stored);
+
+        let err = resolve_from(None, None, Some(&last)).unwrap_err();
+        assert!(err.contains("encryption_key"), "{}", err);
+        // Supplying the config (with its key) or a preset still works
+        assert!(resolve_from(Some(encrypted), None, Some(&last)).is_ok());
+        assert!(resolve_from(None, Some(VOICE_NOTE.into()), Some(&last)).is_ok());
+
+        // The next plaintext recording clears the marker
+        save_config(&last, &with_min_duration(333)).unwrap();
+        assert_eq!(resolve_from(None, None, Some(&last)).unwrap().min_duration_ms, 333);
+        let _ = std::fs::remove_file(&last);
+    }
+
+    #[test]
+    fn unknown_preset_is_an_error() {
+        assert!(resolve_from(None, Some("no such preset".into()), None).is_err());
+    }
+}
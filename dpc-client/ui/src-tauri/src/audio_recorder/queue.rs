@@ -0,0 +1,145 @@
+// Bounded frame queue between the processing thread and the encoder
+//
+// When the encoder falls behind (slow disk), the oldest queued frame is
+// dropped to make room and the caller counts it as an xrun. A recording
+// that loses a few frames is preferable to one that grows until OOM.
+// A `lossless` queue makes the sender wait for room instead, for callers
+// that need every frame and can absorb the backlog upstream.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvTimeoutError, SendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    ready: Condvar,
+    space: Condvar, // Signalled when a lossless queue has room again
+    capacity: usize,
+    lossless: bool,
+}
+
+struct Inner<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+pub struct FrameSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct FrameReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Create a queue holding at most `capacity` items (minimum 1)
+pub fn bounded<T>(capacity: usize) -> (FrameSender<T>, FrameReceiver<T>) {
+    with_mode(capacity, false)
+}
+
+/// Like `bounded`, but `send` blocks while full and never drops
+pub fn lossless<T>(capacity: usize) -> (FrameSender<T>, FrameReceiver<T>) {
+    with_mode(capacity, true)
+}
+
+fn with_mode<T>(capacity: usize, lossless: bool) -> (FrameSender<T>, FrameReceiver<T>) {
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            items: VecDeque::with_capacity(capacity.max(1)),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        ready: Condvar::new(),
+        space: Condvar::new(),
+        capacity: capacity.max(1),
+        lossless,
+    });
+    (
+        FrameSender { shared: shared.clone() },
+        FrameReceiver { shared },
+    )
+}
+
+impl<T> FrameSender<T> {
+    /// Queue an item, evicting the oldest one if the queue is full (or
+    /// waiting for room, if lossless). Returns the number of items dropped
+    /// (0 or 1), or an error once the receiver has gone away.
+    pub fn send(&self, item: T) -> Result<usize, SendError<T>> {
+        let mut inner = self.shared.inner.lock().unwrap();
+        if self.shared.lossless {
+            inner = self
+                .shared
+                .space
+                .wait_while(inner, |i| i.items.len() >= self.shared.capacity && i.receiver_alive)
+                .unwrap();
+        }
+        if !inner.receiver_alive {
+            return Err(SendError(item));
+        }
+        let mut dropped = 0;
+        if inner.items.len() >= self.shared.capacity {
+            inner.items.pop_front();
+            dropped = 1;
+        }
+        inner.items.push_back(item);
+        drop(inner);
+        self.shared.ready.notify_one();
+        Ok(dropped)
+    }
+
+    /// Items waiting for the receiver; a steadily growing count means the
+    /// encoder can't keep up
+    pub fn queued(&self) -> usize {
+        self.shared.inner.lock().unwrap().items.len()
+    }
+}
+
+impl<T> Clone for FrameSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.inner.lock().unwrap().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for FrameSender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.senders -= 1;
+        if inner.senders == 0 {
+            drop(inner);
+            self.shared.ready.notify_all();
+        }
+    }
+}
+
+impl<T> FrameReceiver<T> {
+    /// Same contract as `mpsc::Receiver::recv_timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let inner = self.shared.inner.lock().unwrap();
+        let (mut inner, _) = self
+            .shared
+            .ready
+            .wait_timeout_while(inner, timeout, |i| i.items.is_empty() && i.senders > 0)
+            .unwrap();
+        match inner.items.pop_front() {
+            Some(item) => {
+                drop(inner);
+                self.shared.space.notify_one();
+                Ok(item)
+            }
+            None if inner.senders == 0 => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+}
+
+impl<T> Drop for FrameReceiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.receiver_alive = false;
+        inner.items.clear();
+        drop(inner);
+        self.shared.space.notify_all();
+    }
+}
@@ -0,0 +1,149 @@
+// Debug copy of the device stream before any processing (`capture_raw`)
+//
+// `voice_123.wav` gets `voice_123.raw.wav`: every buffer the device
+// delivers, byte for byte, at its own rate, channel count and sample format,
+// ahead of resampling, downmixing, muting and pausing. WAV has no tag for
+// signed 8-bit or unsigned 16/32/64-bit samples; those keep their bytes under
+// an integer PCM header of the same width, and the ICMT comment always names
+// the real format.
+//
+// The device callback copies into recycled buffers from a fixed pool
+// (`pool`) and never allocates; when the writer falls that far behind the
+// callback's bytes are dropped and counted, and the recording's
+// `raw_capture_overruns` says how many.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use cpal::SampleFormat;
+
+use super::pool::{self, PoolReceiver, PoolSender};
+use super::wav::{WavMetadata, WavWriter};
+
+// About half a second of 1024-frame callbacks; a longer callback grows its
+// buffer once and keeps the capacity
+const RAW_BUFFERS: usize = 24;
+const RAW_BUFFER_FRAMES: usize = 1024;
+
+/// `dir/voice_123.wav` -> `dir/voice_123.raw.wav`
+pub fn raw_path(wav_path: &Path) -> PathBuf {
+    wav_path.with_extension("raw.wav")
+}
+
+/// The writer thread for one recording's raw copy. Each stream opened for
+/// the recording (a reopen after an interruption opens another) fills its
+/// own pool, and the writer moves on to the next pool once the previous
+/// stream is gone.
+pub struct RawCapture {
+    pools: mpsc::Sender<PoolReceiver<u8>>,
+    buffer_bytes: usize,
+    overruns: Arc<AtomicU64>,
+}
+
+impl RawCapture {
+    /// Start the thread writing to `path`, counting dropped callbacks into
+    /// `overruns`. The file is created with the first buffer, so a stream
+    /// that never opens leaves nothing behind, and finished once this and
+    /// every pool's sender are dropped.
+    pub fn spawn(path: &Path, config: &cpal::SupportedStreamConfig, overruns: Arc<AtomicU64>) -> Self {
+        let format = config.sample_format();
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels();
+        // 8-bit WAV is unsigned, wider is signed
+        let exact = !matches!(format, SampleFormat::I8 | SampleFormat::U16 | SampleFormat::U32 | SampleFormat::U64);
+        let metadata = WavMetadata {
+            comment: Some(match exact {
+                true => format!("Raw device capture: {}", format),
+                false => format!("Raw device capture: {} (bytes unchanged; the header can't describe it)", format),
+            }),
+            ..Default::default()
+        };
+        let wav_format = (if format.is_float() { 3 } else { 1 }, format.sample_size() as u16 * 8);
+
+        let path = path.to_string_lossy().to_string();
+        let (pools, pools_rx) = mpsc::channel::<PoolReceiver<u8>>();
+        let writer_overruns = overruns.clone();
+        thread::spawn(move || {
+            let mut writer: Option<WavWriter> = None;
+            for pool in pools_rx {
+                while let Some(bytes) = pool.recv() {
+                    let result = match &mut writer {
+                        Some(writer) => writer.write_bytes(&bytes),
+                        None => WavWriter::native(&path, sample_rate, channels, wav_format, Some(&metadata))
+                            .and_then(|w| writer.insert(w).write_bytes(&bytes)),
+                    };
+                    pool.recycle(bytes);
+                    if let Err(e) = result {
+                        eprintln!("Failed to write raw capture {}: {}", path, e);
+                        return;
+                    }
+                }
+            }
+            let dropped = writer_overruns.load(Ordering::Relaxed);
+            if dropped > 0 {
+                eprintln!("Raw capture {} is missing {} device buffers; its writer fell behind", path, dropped);
+            }
+            if let Some(Err(e)) = writer.map(WavWriter::finish) {
+                eprintln!("Failed to finalize raw capture {}: {}", path, e);
+            }
+        });
+        Self {
+            pools,
+            buffer_bytes: RAW_BUFFER_FRAMES * channels as usize * format.sample_size(),
+            overruns,
+        }
+    }
+
+    /// A fresh pool for the next stream's callback to fill
+    pub fn pool(&self) -> PoolSender<u8> {
+        let (tx, rx) = pool::buffer_pool_counting(RAW_BUFFERS, self.buffer_bytes, self.overruns.clone());
+        let _ = self.pools.send(rx);
+        tx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    use super::super::convert::ToI16;
+    use super::super::wav::WavReader;
+
+    #[test]
+    fn a_reopened_stream_is_written_after_the_first() {
+        let path = std::env::temp_dir().join(format!("dpc-raw-capture-test-{}-reopen.raw.wav", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = cpal::SupportedStreamConfig::new(
+            1,
+            cpal::SampleRate(48000),
+            cpal::SupportedBufferSize::Unknown,
+            SampleFormat::I16,
+        );
+        let overruns = Arc::new(AtomicU64::new(0));
+        let capture = RawCapture::spawn(&path, &config, overruns.clone());
+        let first = capture.pool();
+        let second = capture.pool();
+        // The new stream starts before the dead one is dropped
+        assert!(second.send_with(|b| [3i16, 4].iter().for_each(|&s| s.extend_le_bytes(b))));
+        assert!(first.send_with(|b| [1i16, 2].iter().for_each(|&s| s.extend_le_bytes(b))));
+        drop((first, second, capture));
+
+        // The writer thread finishes the file once every sender is gone
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let samples = loop {
+            let read = WavReader::open(&path.to_string_lossy()).and_then(|mut r| r.read_samples());
+            match read {
+                Ok(samples) if samples.len() == 4 => break samples,
+                _ if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                other => panic!("raw capture never finished: {:?}", other),
+            }
+        };
+        assert_eq!(samples, [1, 2, 3, 4]);
+        assert_eq!(overruns.load(Ordering::Relaxed), 0);
+        let _ = std::fs::remove_file(&path);
+    }
+}
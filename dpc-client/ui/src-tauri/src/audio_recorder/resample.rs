@@ -0,0 +1,449 @@
+// Streaming resampler for the recording path
+//
+// Works in both directions (e.g. 44.1 kHz -> 48 kHz and 16 kHz -> 48 kHz).
+// `ResamplerState` carries the input still needed and the fractional read
+// position between calls, so splitting the input into arbitrary chunks gives
+// the same output as resampling it in one go.
+//
+// `Fast` interpolates linearly: a few operations per sample, with some
+// high-frequency droop and imaging on fractional ratios. `High` weighs
+// `SINC_HALF_TAPS` samples either side with a Blackman-windowed sinc, at
+// roughly 30x the work. Both read output sample n at the same input time,
+// so a take can `downgrade` from one to the other mid-stream: the sinc
+// output fades into linear over `DOWNGRADE_FADE_FRAMES` and linear then
+// carries on from the same read position, with no jump in time or level.
+
+use std::f64::consts::PI;
+
+/// Group delay of `resample_mono`, in output frames
+pub const LINEAR_DELAY_FRAMES: u32 = 0;
+
+/// Input samples either side of the read position that `High` weighs (at
+/// the input rate; downsampling widens the kernel by the ratio). The output
+/// waits for that many samples of look-ahead; its timing is unchanged.
+pub const SINC_HALF_TAPS: usize = 16;
+
+// Output samples over which a downgrade crossfades from sinc to linear
+const DOWNGRADE_FADE_FRAMES: usize = 480;
+
+/// How rates are converted on the recording path
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResamplerQuality {
+    #[default]
+    Fast,
+    High,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ResamplerState {
+    // Read position of the next output sample, in units of 1/`to` input
+    // samples so it stays exact across calls. Relative to `last` (index 0)
+    // when linear, to `sinc.history[0]` otherwise.
+    phase: u64,
+    last: Option<i16>,
+    sinc: Option<SincState>, // Set for `High`, until a downgrade has faded out
+}
+
+#[derive(Debug, Default, Clone)]
+struct SincState {
+    history: Vec<i16>,   // Input from the oldest sample the next output reads
+    started: bool,       // `history` has its lead-in of silence
+    fade: Option<usize>, // Outputs into a downgrade's crossfade
+}
+
+impl ResamplerState {
+    pub fn new(quality: ResamplerQuality) -> Self {
+        let sinc = (quality == ResamplerQuality::High).then(SincState::default);
+        Self { sinc, ..Self::default() }
+    }
+
+    /// `High` until a downgrade starts
+    pub fn quality(&self) -> ResamplerQuality {
+        match &self.sinc {
+            Some(sinc) if sinc.fade.is_none() => ResamplerQuality::High,
+            _ => ResamplerQuality::Fast,
+        }
+    }
+
+    /// Forget the previous input, so the next chunk is treated as the
+    /// start of a new stream rather than interpolated from stale audio.
+    /// The quality stays as it is (a downgrade is not undone).
+    pub fn reset(&mut self) {
+        *self = Self::new(self.quality());
+    }
+
+    /// Switch `High` to `Fast` from the next output on, fading between
+    /// them. Returns false if there was nothing to downgrade.
+    pub fn downgrade(&mut self) -> bool {
+        match &mut self.sinc {
+            Some(sinc) if sinc.fade.is_none() => {
+                sinc.fade = Some(0);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How long `quality` holds output back for look-ahead, in output frames
+pub fn lookahead_frames(quality: ResamplerQuality, from: u32, to: u32) -> u64 {
+    if quality == ResamplerQuality::Fast || from == to || from == 0 || to == 0 {
+        return 0;
+    }
+    let (_, half) = sinc_width(from, to);
+    (half as f64 * to as f64 / from as f64).ceil() as u64
+}
+
+// The sinc's cutoff (a fraction of the input's Nyquist) and half-width in
+// input samples. Downsampling lowers the cutoff to the output's Nyquist and
+// widens the kernel to keep the same quality.
+fn sinc_width(from: u32, to: u32) -> (f64, usize) {
+    let cutoff = (to as f64 / from as f64).min(1.0);
+    (cutoff, (SINC_HALF_TAPS as f64 / cutoff).ceil() as usize)
+}
+
+/// A rate conversion as reported to the UI. Integer ratios (96k -> 48k)
+/// line up input and output samples exactly; fractional ones (44.1k -> 48k)
+/// interpolate every sample and are where linear resampling is audible.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ResampleInfo {
+    pub from_rate: u32,
+    pub to_rate: u32,
+    pub ratio: f64, // Output samples per input sample
+    pub integer_ratio: bool,
+    // Output frames by which the converted audio lags the input. The first
+    // output sample is taken exactly at input sample 0, so linear
+    // interpolation adds none and there is no warm-up to trim
+    pub delay_frames: u32,
+}
+
+impl ResampleInfo {
+    /// `None` when no conversion happens
+    pub fn between(from: u32, to: u32) -> Option<Self> {
+        if from == to || from == 0 || to == 0 {
+            return None;
+        }
+        Some(Self {
+            from_rate: from,
+            to_rate: to,
+            ratio: to as f64 / from as f64,
+            integer_ratio: from.is_multiple_of(to) || to.is_multiple_of(from),
+            delay_frames: LINEAR_DELAY_FRAMES,
+        })
+    }
+}
+
+/// Resample one chunk of a mono stream from `from` Hz to `to` Hz
+pub fn resample_mono(input: &[i16], from: u32, to: u32, state: &mut ResamplerState) -> Vec<i16> {
+    let mut output = Vec::new();
+    resample_mono_into(input, from, to, state, &mut output);
+    output
+}
+
+/// `resample_mono`, appending to `output` so a caller can reuse one buffer
+pub fn resample_mono_into(input: &[i16], from: u32, to: u32, state: &mut ResamplerState, output: &mut Vec<i16>) {
+    if from == to || from == 0 || to == 0 {
+        output.extend_from_slice(input);
+        return;
+    }
+    if input.is_empty() {
+        return;
+    }
+    if state.sinc.is_some() {
+        resample_sinc(input, from, to, state, output);
+        return;
+    }
+
+    let (from, to) = (from as u64, to as u64);
+    let offset = state.last.is_some() as usize;
+    let len = (input.len() + offset) as u64;
+    let at = |i: usize| -> f64 {
+        match state.last {
+            Some(last) if i == 0 => last as f64,
+            _ => input[i - offset] as f64,
+        }
+    };
+
+    output.reserve((input.len() as u64 * to / from) as usize + 1);
+    let mut phase = state.phase;
+    // Interpolate between idx and idx + 1, so stop one short of the end and
+    // finish this gap on the next call
+    while phase / to + 1 < len {
+        let idx = (phase / to) as usize;
+        let frac = (phase % to) as f64 / to as f64;
+        let sample = at(idx) + (at(idx + 1) - at(idx)) * frac;
+        output.push(sample.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16);
+        phase += from;
+    }
+
+    // Re-anchor so the newest input sample becomes index 0 next time
+    state.phase = phase - (len - 1) * to;
+    state.last = input.last().copied();
+}
+
+// `resample_mono_into` for a `High` state
+fn resample_sinc(input: &[i16], from: u32, to: u32, state: &mut ResamplerState, output: &mut Vec<i16>) {
+    let Some(sinc) = state.sinc.as_mut() else { return };
+    let (cutoff, half) = sinc_width(from, to);
+    let rates = (from, to);
+    let (from, to) = (from as u64, to as u64);
+    if !sinc.started {
+        // Silence before the stream, so output 0 is centred on input 0
+        sinc.history.resize(half, 0);
+        state.phase = half as u64 * to;
+        sinc.started = true;
+    }
+    sinc.history.extend_from_slice(input);
+
+    // Each output reads `half` samples either side of its position, so it
+    // waits until the look-ahead has arrived
+    let mut idx = (state.phase / to) as usize;
+    while idx + half < sinc.history.len() {
+        let frac = (state.phase % to) as f64 / to as f64;
+        let taps = idx + 1 - half..=idx + half;
+        let mut sample: f64 = sinc.history[taps.clone()]
+            .iter()
+            .zip(taps)
+            .map(|(&x, k)| x as f64 * kernel(idx as f64 + frac - k as f64, cutoff, half))
+            .sum();
+        if let Some(faded) = sinc.fade.as_mut() {
+            let (a, b) = (sinc.history[idx] as f64, sinc.history[idx + 1] as f64);
+            let weight = *faded as f64 / DOWNGRADE_FADE_FRAMES as f64;
+            sample += (a + (b - a) * frac - sample) * weight;
+            *faded += 1;
+        }
+        output.push(sample.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16);
+        state.phase += from;
+        idx = (state.phase / to) as usize;
+
+        if sinc.fade.is_some_and(|faded| faded >= DOWNGRADE_FADE_FRAMES) {
+            // Faded out: linear carries on from the same read position, over
+            // the input the sinc was still holding for its look-ahead
+            let pending = sinc.history.split_off(idx + 1);
+            state.last = Some(sinc.history[idx]);
+            state.phase -= idx as u64 * to;
+            state.sinc = None;
+            resample_mono_into(&pending, rates.0, rates.1, state, output);
+            return;
+        }
+    }
+
+    // Keep only what the next output reads
+    let consumed = (idx + 1).saturating_sub(half);
+    sinc.history.drain(..consumed);
+    state.phase -= consumed as u64 * to;
+}
+
+// Blackman-windowed sinc lowpass at `cutoff` (a fraction of the input's
+// Nyquist), `x` input samples from the read position
+fn kernel(x: f64, cutoff: f64, half: usize) -> f64 {
+    let w = x / half as f64;
+    if w.abs() >= 1.0 {
+        return 0.0;
+    }
+    let window = 0.42 + 0.5 * (PI * w).cos() + 0.08 * (2.0 * PI * w).cos();
+    let t = PI * cutoff * x;
+    let sinc = if t.abs() < 1e-9 { 1.0 } else { t.sin() / t };
+    cutoff * sinc * window
+}
+
+/// Resample an interleaved multi-channel chunk, one state per channel
+pub fn resample_interleaved(
+    input: &[i16],
+    channels: usize,
+    from: u32,
+    to: u32,
+    states: &mut [ResamplerState],
+) -> Vec<i16> {
+    if channels <= 1 {
+        return resample_mono(input, from, to, &mut states[0]);
+    }
+    if from == to {
+        return input.to_vec();
+    }
+
+    let resampled: Vec<Vec<i16>> = states
+        .iter_mut()
+        .take(channels)
+        .enumerate()
+        .map(|(ch, state)| {
+            let channel: Vec<i16> = input.iter().skip(ch).step_by(channels).copied().collect();
+            resample_mono(&channel, from, to, state)
+        })
+        .collect();
+
+    let frames = resampled.iter().map(Vec::len).min().unwrap_or(0);
+    let mut output = Vec::with_capacity(frames * channels);
+    for i in 0..frames {
+        output.extend(resampled.iter().map(|channel| channel[i]));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Up, down, integer and odd ratios
+    const RATE_PAIRS: [(u32, u32); 6] =
+        [(16000, 48000), (48000, 16000), (44100, 48000), (48000, 22050), (8000, 32000), (96000, 44100)];
+
+    fn sine(rate: u32, hz: f64, frames: usize) -> Vec<i16> {
+        (0..frames)
+            .map(|i| ((i as f64 * std::f64::consts::TAU * hz / rate as f64).sin() * 16000.0).round() as i16)
+            .collect()
+    }
+
+    #[test]
+    fn chunked_matches_one_shot() {
+        for (from, to) in RATE_PAIRS {
+            let input = sine(from, 440.0, from as usize / 10);
+            let whole = resample_mono(&input, from, to, &mut ResamplerState::default());
+
+            let mut state = ResamplerState::default();
+            let mut chunked = Vec::new();
+            // Uneven chunk sizes, including single samples
+            for chunk in input.chunks(1).take(3).chain(input[3..].chunks(317)) {
+                chunked.extend(resample_mono(chunk, from, to, &mut state));
+            }
+            assert_eq!(chunked, whole, "{} -> {}", from, to);
+        }
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        for (from, to) in RATE_PAIRS {
+            let frames = from as usize / 4;
+            let output = resample_mono(&vec![0; frames], from, to, &mut ResamplerState::default());
+            // The gap after the last input sample waits for the next chunk,
+            // so up to one input sample's worth of output is still pending
+            let expected = frames as f64 * to as f64 / from as f64;
+            let pending = (to as f64 / from as f64).ceil();
+            let len = output.len() as f64;
+            assert!(len <= expected + 1.0 && len >= expected - pending, "{} -> {}: {} samples", from, to, len);
+        }
+    }
+
+    #[test]
+    fn frequency_is_preserved() {
+        let rising_zero_crossings = |samples: &[i16]| samples.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        for (from, to) in RATE_PAIRS {
+            // One second of 1 kHz (below every Nyquist in the list)
+            let output = resample_mono(&sine(from, 1000.0, from as usize), from, to, &mut ResamplerState::default());
+            let crossings = rising_zero_crossings(&output);
+            assert!((999..=1001).contains(&crossings), "{} -> {}: {} cycles", from, to, crossings);
+        }
+    }
+
+    #[test]
+    fn transient_is_not_delayed() {
+        // An impulse at input frame 960 must peak at output frame 960 * to / from,
+        // which is what `LINEAR_DELAY_FRAMES` = 0 promises
+        for (from, to) in RATE_PAIRS {
+            let mut input = vec![0i16; 2000];
+            input[960] = 20000;
+            let output = resample_mono(&input, from, to, &mut ResamplerState::default());
+            let peak = (0..output.len()).max_by_key(|&i| output[i]).unwrap();
+            let expected = (960.0 * to as f64 / from as f64).round() as usize;
+            assert_eq!(peak, expected + LINEAR_DELAY_FRAMES as usize, "{} -> {}", from, to);
+        }
+        // A transient at t=0 stays at the very first output sample
+        let output = resample_mono(&[20000, 0, 0, 0], 16000, 48000, &mut ResamplerState::default());
+        assert_eq!(output[0], 20000);
+        assert_eq!(ResampleInfo::between(44100, 48000).unwrap().delay_frames, 0);
+    }
+
+    #[test]
+    fn reset_keeps_one_take_out_of_the_next() {
+        for (from, to) in RATE_PAIRS {
+            // An odd length leaves the first take's read position between samples
+            let first = sine(from, 440.0, from as usize / 10 + 1);
+            let second = sine(from, 1000.0, from as usize / 10);
+            let fresh = resample_mono(&second, from, to, &mut ResamplerState::default());
+
+            // One state carried across both takes, reset in between
+            let mut state = ResamplerState::default();
+            resample_mono(&first, from, to, &mut state);
+            state.reset();
+            let reused = resample_mono(&second, from, to, &mut state);
+            assert_eq!(reused, fresh, "{} -> {}", from, to);
+
+            // Without the reset the opening samples interpolate from the
+            // first take's last sample, so the check above means something
+            let mut stale = ResamplerState::default();
+            resample_mono(&first, from, to, &mut stale);
+            let contaminated = resample_mono(&second, from, to, &mut stale);
+            assert_ne!(contaminated[..4], fresh[..4], "{} -> {}", from, to);
+        }
+    }
+
+    fn resample_high(input: &[i16], from: u32, to: u32) -> Vec<i16> {
+        resample_mono(input, from, to, &mut ResamplerState::new(ResamplerQuality::High))
+    }
+
+    #[test]
+    fn high_quality_chunked_matches_one_shot_and_keeps_timing() {
+        for (from, to) in RATE_PAIRS {
+            let input = sine(from, 440.0, from as usize / 10);
+            let whole = resample_high(&input, from, to);
+            let mut state = ResamplerState::new(ResamplerQuality::High);
+            let mut chunked = Vec::new();
+            for chunk in input.chunks(1).take(3).chain(input[3..].chunks(317)) {
+                chunked.extend(resample_mono(chunk, from, to, &mut state));
+            }
+            assert_eq!(chunked, whole, "{} -> {}", from, to);
+
+            // Same read positions as linear: the look-ahead delays the
+            // output's arrival, not where it sits in time
+            let mut impulse = vec![0i16; 2000];
+            impulse[960] = 20000;
+            let output = resample_high(&impulse, from, to);
+            let peak = (0..output.len()).max_by_key(|&i| output[i]).unwrap();
+            assert_eq!(peak, (960.0 * to as f64 / from as f64).round() as usize, "{} -> {}", from, to);
+        }
+    }
+
+    #[test]
+    fn high_quality_keeps_the_top_of_the_band() {
+        let rms = |samples: &[i16]| (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt();
+        // 6 kHz is near the 8 kHz Nyquist of a 16 kHz device
+        let input = sine(16000, 6000.0, 16000);
+        let reference = rms(&input[1000..15000]);
+        let fast = resample_mono(&input, 16000, 48000, &mut ResamplerState::default());
+        let high = resample_high(&input, 16000, 48000);
+        let (fast, high) = (rms(&fast[3000..45000]) / reference, rms(&high[3000..45000]) / reference);
+        assert!(fast < 0.9, "linear kept {:.3}", fast);
+        assert!((0.98..1.02).contains(&high), "sinc kept {:.3}", high);
+    }
+
+    #[test]
+    fn downgrade_splices_onto_the_same_timeline() {
+        let (from, to) = (44100, 48000);
+        let input = sine(from, 1000.0, from as usize);
+        let mut state = ResamplerState::new(ResamplerQuality::High);
+        let mut output = Vec::new();
+        for (i, chunk) in input.chunks(441).enumerate() {
+            if i == 50 {
+                assert!(state.downgrade());
+                assert!(!state.downgrade(), "already downgrading");
+            }
+            resample_mono_into(chunk, from, to, &mut state, &mut output);
+        }
+        assert_eq!(state.quality(), ResamplerQuality::Fast);
+        state.reset();
+        assert_eq!(state.quality(), ResamplerQuality::Fast, "reset undid the downgrade");
+
+        // Every sample, before, across and after the crossfade, is the tone
+        // at its own time: nothing skipped, repeated or stepped
+        let worst = output
+            .iter()
+            .enumerate()
+            .map(|(n, &s)| (s as f64 - 16000.0 * (n as f64 * std::f64::consts::TAU * 1000.0 / to as f64).sin()).abs())
+            .fold(0.0, f64::max);
+        assert!(worst < 100.0, "off by up to {}", worst);
+        // And linear picked up exactly where the sinc had read to
+        let fast = resample_mono(&input, from, to, &mut ResamplerState::default());
+        assert_eq!(output.len(), fast.len());
+    }
+}
@@ -0,0 +1,112 @@
+// End-to-end diagnostic for "recording doesn't work" reports
+//
+// Runs each stage of a recording in miniature and reports every one, so a
+// failure points at the device, the stream or the disk. Stages that need an
+// earlier one (the stream needs a device) are reported as skipped rather
+// than hidden; the file stages always run, on silence if nothing was captured.
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::path::Path;
+use std::time::Duration;
+
+use super::stream;
+use super::wav::{WavReader, WavWriter};
+
+const CAPTURE_MS: u64 = 300;
+const TEST_FILE: &str = "dpc_self_test.wav";
+
+#[derive(Debug, serde::Serialize)]
+pub struct StageResult {
+    pub stage: &'static str, // device_present, stream_opens, samples_flow, file_writes, file_reads_back
+    pub passed: bool,
+    pub message: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool, // Every stage passed
+    pub stages: Vec<StageResult>,
+}
+
+fn stage(stage: &'static str, result: Result<String, String>) -> StageResult {
+    match result {
+        Ok(message) => StageResult { stage, passed: true, message },
+        Err(message) => StageResult { stage, passed: false, message },
+    }
+}
+
+/// Check the default input and `output_dir` the way a recording uses them.
+/// The test file is removed afterwards.
+pub fn run_audio_self_test(output_dir: String) -> Result<SelfTestReport, String> {
+    if super::get_global_state().lock().unwrap().is_recording {
+        return Err("Cannot run the audio self-test while recording".to_string());
+    }
+    let mut stages = Vec::new();
+
+    let host = cpal::default_host();
+    let device = host.default_input_device();
+    let count = host.input_devices().map(|d| d.count()).unwrap_or(0);
+    stages.push(stage("device_present", match &device {
+        Some(d) => Ok(format!(
+            "Default input: {} ({} input device(s))",
+            d.name().unwrap_or_else(|_| "Unknown device".to_string()),
+            count
+        )),
+        None => Err(format!("No default input device ({} input device(s) listed)", count)),
+    }));
+
+    let mut captured = None;
+    match device {
+        Some(device) => match stream::capture_mono(device, Duration::from_millis(CAPTURE_MS)) {
+            Ok((samples, sample_rate)) => {
+                stages.push(stage("stream_opens", Ok(format!("Opened at {} Hz", sample_rate))));
+                stages.push(stage("samples_flow", if samples.is_empty() {
+                    Err(format!("Stream opened but delivered no audio in {} ms", CAPTURE_MS))
+                } else {
+                    Ok(format!("{} samples in {} ms", samples.len(), CAPTURE_MS))
+                }));
+                captured = Some((samples, sample_rate));
+            }
+            Err(e) => {
+                stages.push(stage("stream_opens", Err(e)));
+                stages.push(stage("samples_flow", Err("Skipped: the stream did not open".to_string())));
+            }
+        },
+        None => {
+            stages.push(stage("stream_opens", Err("Skipped: no input device".to_string())));
+            stages.push(stage("samples_flow", Err("Skipped: no input device".to_string())));
+        }
+    }
+
+    // Without audio from the device, 100 ms of silence still exercises the disk
+    let (samples, sample_rate) = captured
+        .filter(|(samples, _)| !samples.is_empty())
+        .unwrap_or_else(|| (vec![0; 4800], 48000));
+    let path = Path::new(&output_dir).join(TEST_FILE).to_string_lossy().to_string();
+
+    let written = WavWriter::new(&path, sample_rate, 1).and_then(|mut writer| {
+        writer.write_samples(&samples)?;
+        writer.finish()
+    });
+    let wrote = written.is_ok();
+    stages.push(stage("file_writes", match written {
+        Ok(()) => Ok(format!("Wrote {}", path)),
+        Err(e) => Err(format!("Failed to write {}: {}", path, e)),
+    }));
+
+    stages.push(stage("file_reads_back", if !wrote {
+        Err("Skipped: the file was not written".to_string())
+    } else {
+        match WavReader::open(&path).and_then(|mut reader| reader.read_samples()) {
+            Ok(read) if read == samples => Ok(format!("Read back {} samples intact", read.len())),
+            Ok(read) => Err(format!("Read back {} samples, differing from the {} written", read.len(), samples.len())),
+            Err(e) => Err(format!("Failed to read {}: {}", path, e)),
+        }
+    }));
+    let _ = std::fs::remove_file(&path);
+
+    Ok(SelfTestReport {
+        passed: stages.iter().all(|s| s.passed),
+        stages,
+    })
+}
@@ -0,0 +1,99 @@
+// Sent/unsent state of finished recordings
+//
+// The network layer calls `mark_sent` once a message carrying a recording is
+// confirmed delivered. The state is an empty `.sent` marker next to the file
+// (`voice_123.wav` -> `voice_123.wav.sent`, so a WAV and an AIFF of the same
+// take each have their own), and the recording itself never moves: its
+// path, peaks sidecar and any references to it stay valid. A recording
+// without a marker is unsent: still in progress, failed or never sent.
+// `list_recordings` shows only sent ones unless asked for the unsent (to
+// retry or recover them) or for everything.
+
+use std::path::{Path, PathBuf};
+
+/// Which recordings `list_recordings` returns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SendFilter {
+    All,
+    #[default]
+    Sent,
+    Unsent,
+}
+
+impl SendFilter {
+    pub fn matches(self, sent: bool) -> bool {
+        match self {
+            SendFilter::All => true,
+            SendFilter::Sent => sent,
+            SendFilter::Unsent => !sent,
+        }
+    }
+}
+
+/// `dir/voice_123.wav` -> `dir/voice_123.wav.sent`
+pub fn marker_path(recording: &Path) -> PathBuf {
+    let mut marker = recording.as_os_str().to_owned();
+    marker.push(".sent");
+    PathBuf::from(marker)
+}
+
+pub fn is_sent(recording: &Path) -> bool {
+    marker_path(recording).exists()
+}
+
+/// Record that `path` was delivered
+pub fn mark_sent(path: String) -> Result<(), String> {
+    let recording = Path::new(&path);
+    if !recording.is_file() {
+        return Err(format!("Recording not found: {}", path));
+    }
+    let marker = marker_path(recording);
+    std::fs::write(&marker, b"")
+        .map_err(|e| format!("Failed to write {}: {}", marker.display(), e))
+}
+
+/// Return `path` to the unsent list, e.g. when a delivery is retracted
+pub fn mark_unsent(path: String) -> Result<(), String> {
+    let marker = marker_path(Path::new(&path));
+    match std::fs::remove_file(&marker) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to remove {}: {}", marker.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tools;
+
+    #[test]
+    fn each_container_has_its_own_marker_and_unsent_takes_stay_hidden() {
+        let dir = std::env::temp_dir().join(format!("dpc-sent-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (wav, aif) = (dir.join("voice_1.wav"), dir.join("voice_1.aif"));
+        for path in [&wav, &aif] {
+            std::fs::write(path, b"audio").unwrap();
+        }
+
+        mark_sent(wav.to_string_lossy().to_string()).unwrap();
+        assert_eq!(marker_path(&wav), dir.join("voice_1.wav.sent"));
+        assert!(is_sent(&wav));
+        assert!(!is_sent(&aif), "the AIFF shares the WAV's marker");
+
+        let listed = |filter| -> Vec<String> {
+            let entries = tools::list_recordings(dir.to_string_lossy().to_string(), false, filter).unwrap();
+            entries.into_iter().map(|e| e.path).collect()
+        };
+        let (wav, aif) = (wav.to_string_lossy().to_string(), aif.to_string_lossy().to_string());
+        assert_eq!(listed(SendFilter::default()), [wav.as_str()]);
+        assert_eq!(listed(SendFilter::Unsent), [aif.as_str()]);
+        assert_eq!(listed(SendFilter::All), [aif.as_str(), wav.as_str()]);
+
+        mark_unsent(wav).unwrap();
+        assert!(listed(SendFilter::default()).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
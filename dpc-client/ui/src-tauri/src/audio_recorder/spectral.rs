@@ -0,0 +1,209 @@
+// Spectral noise subtraction for steady noise (fans, hum)
+//
+// `capture_noise_profile` records ambient noise and averages its magnitude
+// spectrum; recordings with `spectral_subtract` then remove that profile
+// frame by frame with a 50%-overlap, sqrt-Hann STFT and overlap-add.
+//
+// Cost: one forward and one inverse 512-point FFT per 256 samples per
+// channel, roughly 2-3% of one core at 48 kHz mono. Latency: 512 samples
+// (~10.7 ms at 48 kHz) are held back before output starts, and up to that
+// much of the recording's tail is not flushed on stop.
+
+use cpal::traits::HostTrait;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::convert;
+use super::dsp;
+use super::stream;
+
+pub const FFT_SIZE: usize = 512;
+const HOP: usize = FFT_SIZE / 2;
+const BINS: usize = FFT_SIZE / 2 + 1;
+const OVER_SUBTRACTION: f32 = 1.5; // Removes a bit more than the average noise
+const SPECTRAL_FLOOR: f32 = 0.05;  // Keep 5% of each bin to avoid "musical noise"
+const MIN_PROFILE_MS: u64 = 100;
+const MAX_PROFILE_MS: u64 = 10_000;
+
+/// Average magnitude spectrum of the captured noise
+#[derive(Debug, Clone)]
+pub struct NoiseProfile {
+    pub sample_rate: u32,
+    magnitudes: Vec<f32>, // BINS entries, for FFT_SIZE at `sample_rate`
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct NoiseProfileInfo {
+    pub sample_rate: u32,
+    pub duration_ms: u64,
+    pub noise_level_dbfs: f32,
+}
+
+fn window() -> Vec<f32> {
+    // Periodic sqrt-Hann: analysis x synthesis sums to 1 at 50% overlap
+    (0..FFT_SIZE)
+        .map(|i| (0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos()).sqrt())
+        .collect()
+}
+
+impl NoiseProfile {
+    /// Build a profile from mono samples; `None` if shorter than one FFT frame
+    pub fn from_samples(samples: &[i16], sample_rate: u32) -> Option<Self> {
+        if samples.len() < FFT_SIZE {
+            return None;
+        }
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let window = window();
+        let mut magnitudes = vec![0f32; BINS];
+        let mut frames = 0;
+        let mut buffer = vec![Complex::default(); FFT_SIZE];
+        for start in (0..=samples.len() - FFT_SIZE).step_by(HOP) {
+            for (i, slot) in buffer.iter_mut().enumerate() {
+                *slot = Complex::new(samples[start + i] as f32 / 32768.0 * window[i], 0.0);
+            }
+            fft.process(&mut buffer);
+            for (m, c) in magnitudes.iter_mut().zip(&buffer) {
+                *m += c.norm();
+            }
+            frames += 1;
+        }
+        magnitudes.iter_mut().for_each(|m| *m /= frames as f32);
+        Some(Self { sample_rate, magnitudes })
+    }
+
+    // The profile's bins mapped by frequency onto FFT_SIZE bins at `sample_rate`
+    fn magnitudes_at(&self, sample_rate: u32) -> Vec<f32> {
+        if sample_rate == self.sample_rate {
+            return self.magnitudes.clone();
+        }
+        let scale = sample_rate as f32 / self.sample_rate as f32;
+        (0..BINS)
+            .map(|k| {
+                let pos = (k as f32 * scale).min((BINS - 1) as f32);
+                let i = pos.floor() as usize;
+                let frac = pos - i as f32;
+                let next = self.magnitudes[(i + 1).min(BINS - 1)];
+                self.magnitudes[i] * (1.0 - frac) + next * frac
+            })
+            .collect()
+    }
+}
+
+/// Streaming subtractor for one channel
+pub struct SpectralSubtractor {
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    noise: Vec<f32>,
+    pending: Vec<f32>, // Input not yet consumed by a full frame
+    overlap: Vec<f32>, // Synthesis accumulator, FFT_SIZE long
+    buffer: Vec<Complex<f32>>,
+}
+
+impl SpectralSubtractor {
+    pub fn new(profile: &NoiseProfile, sample_rate: u32) -> Self {
+        let mut planner = FftPlanner::<f32>::new();
+        Self {
+            forward: planner.plan_fft_forward(FFT_SIZE),
+            inverse: planner.plan_fft_inverse(FFT_SIZE),
+            window: window(),
+            noise: profile.magnitudes_at(sample_rate),
+            // Half a frame of leading silence so the first output is fully overlapped
+            pending: vec![0.0; HOP],
+            overlap: vec![0.0; FFT_SIZE],
+            buffer: vec![Complex::default(); FFT_SIZE],
+        }
+    }
+
+    /// Feed mono samples, returning whatever output is ready (multiples of HOP)
+    pub fn process(&mut self, samples: &[i16]) -> Vec<i16> {
+        self.pending.extend(samples.iter().map(|&s| convert::i16_to_f32(s)));
+        let mut output = Vec::with_capacity(self.pending.len() / HOP * HOP);
+
+        while self.pending.len() >= FFT_SIZE {
+            for (i, slot) in self.buffer.iter_mut().enumerate() {
+                *slot = Complex::new(self.pending[i] * self.window[i], 0.0);
+            }
+            self.forward.process(&mut self.buffer);
+
+            // Subtract the noise magnitude per bin, keeping the phase
+            for k in 0..BINS {
+                let c = self.buffer[k];
+                let magnitude = c.norm();
+                if magnitude > 0.0 {
+                    let cleaned = (magnitude - OVER_SUBTRACTION * self.noise[k]).max(SPECTRAL_FLOOR * magnitude);
+                    self.buffer[k] = c * (cleaned / magnitude);
+                }
+                // Mirror onto the negative frequencies so the output stays real
+                if k > 0 && k < FFT_SIZE - k {
+                    self.buffer[FFT_SIZE - k] = self.buffer[k].conj();
+                }
+            }
+            self.inverse.process(&mut self.buffer);
+
+            for i in 0..FFT_SIZE {
+                self.overlap[i] += self.buffer[i].re / FFT_SIZE as f32 * self.window[i];
+            }
+            output.extend(
+                self.overlap[..HOP]
+                    .iter()
+                    .map(|&s| convert::f32_to_i16(s)),
+            );
+            self.overlap.copy_within(HOP.., 0);
+            self.overlap[FFT_SIZE - HOP..].fill(0.0);
+            self.pending.drain(..HOP);
+        }
+        output
+    }
+}
+
+/// Run one subtractor per channel over interleaved audio
+pub fn process_interleaved(subtractors: &mut [SpectralSubtractor], samples: &[i16]) -> Vec<i16> {
+    if subtractors.len() == 1 {
+        return subtractors[0].process(samples);
+    }
+    let channels = subtractors.len();
+    let outputs: Vec<Vec<i16>> = subtractors
+        .iter_mut()
+        .enumerate()
+        .map(|(ch, sub)| {
+            let channel: Vec<i16> = samples.iter().skip(ch).step_by(channels).copied().collect();
+            sub.process(&channel)
+        })
+        .collect();
+    // Every channel saw the same number of samples, so outputs line up
+    (0..outputs[0].len())
+        .flat_map(|i| outputs.iter().map(move |out| out[i]))
+        .collect()
+}
+
+/// Record `duration_ms` of ambient noise from the default input and keep its
+/// spectrum for recordings with `spectral_subtract`
+pub fn capture_noise_profile(duration_ms: u64) -> Result<NoiseProfileInfo, String> {
+    if !(MIN_PROFILE_MS..=MAX_PROFILE_MS).contains(&duration_ms) {
+        return Err(format!(
+            "Noise profile duration must be {}-{} ms",
+            MIN_PROFILE_MS, MAX_PROFILE_MS
+        ));
+    }
+    if super::get_global_state().lock().unwrap().is_recording {
+        return Err("Cannot capture a noise profile while recording".to_string());
+    }
+
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or("No audio input device found")?;
+    let (samples, sample_rate) = stream::capture_mono(device, Duration::from_millis(duration_ms))?;
+
+    let profile = NoiseProfile::from_samples(&samples, sample_rate)
+        .ok_or("Microphone produced too little audio for a noise profile")?;
+    let info = NoiseProfileInfo {
+        sample_rate,
+        duration_ms: samples.len() as u64 * 1000 / sample_rate as u64,
+        noise_level_dbfs: dsp::linear_to_dbfs(dsp::frame_rms(&samples)),
+    };
+    super::get_global_state().lock().unwrap().noise_profile = Some(Arc::new(profile));
+    Ok(info)
+}
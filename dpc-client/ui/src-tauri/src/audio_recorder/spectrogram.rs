@@ -0,0 +1,106 @@
+// Spectrogram images of recordings, for debugging noise and clipping
+//
+// The image maps time left to right and frequency bottom (0 Hz) to top
+// (Nyquist), linearly. Each column is one Hann-windowed FFT of the mono
+// mix; the FFT is the smallest power of two with at least `height` bins,
+// so taller images get finer frequency resolution and wider ones finer
+// time resolution. Frames overlap when there are more columns than FFT
+// lengths in the file. Levels run from -90 dBFS (black) to 0 dBFS (white)
+// through a blue-red-yellow heat scale.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::io::BufWriter;
+
+use super::dsp;
+use super::tools;
+
+const MAX_DIMENSION: u32 = 4096;
+const MIN_FFT_SIZE: usize = 64;
+
+// Heat scale stops, evenly spaced from -90 to 0 dBFS
+const HEAT_STOPS: [[f32; 3]; 5] = [
+    [0.0, 0.0, 0.0],
+    [0.0, 0.0, 160.0],
+    [200.0, 0.0, 60.0],
+    [255.0, 200.0, 0.0],
+    [255.0, 255.0, 255.0],
+];
+
+#[derive(Debug, serde::Serialize)]
+pub struct SpectrogramInfo {
+    pub output_path: String,
+    pub fft_size: usize,
+    pub hz_per_row: f32,
+    pub ms_per_column: f32,
+}
+
+/// Render `input` as a `width` x `height` PNG at `output_png`
+pub fn render_spectrogram(input: String, output_png: String, width: u32, height: u32) -> Result<SpectrogramInfo, String> {
+    if !(1..=MAX_DIMENSION).contains(&width) || !(1..=MAX_DIMENSION).contains(&height) {
+        return Err(format!("Image size must be 1-{} pixels per side, got {}x{}", MAX_DIMENSION, width, height));
+    }
+    let mut reader = tools::open_wav(&input)?;
+    let spec = reader.spec();
+    let samples = reader.read_samples_i16()
+        .map_err(|e| format!("Failed to read {}: {}", input, e))?;
+    let mono: Vec<i16> = samples.chunks_exact(spec.channels as usize).map(dsp::downmix).collect();
+
+    let fft_size = (2 * height as usize).next_power_of_two().max(MIN_FFT_SIZE);
+    let bins = fft_size / 2;
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(fft_size);
+    let window: Vec<f32> = (0..fft_size)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / fft_size as f32).cos())
+        .collect();
+    // A full-scale sine peaks at fft_size / 4 after the Hann window
+    let full_scale = fft_size as f32 / 4.0 * 32768.0;
+
+    // Column centres spread over the file; anything past either end is
+    // zero, which also pads files shorter than one FFT
+    let step = mono.len() as f32 / width as f32;
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); fft_size];
+    for col in 0..width as usize {
+        let centre = ((col as f32 + 0.5) * step) as i64;
+        let start = centre - fft_size as i64 / 2;
+        for (i, slot) in buffer.iter_mut().enumerate() {
+            let sample = usize::try_from(start + i as i64).ok().and_then(|pos| mono.get(pos)).copied().unwrap_or(0);
+            *slot = Complex::new(sample as f32 * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+
+        for row in 0..height as usize {
+            // Row 0 is the top of the image: the highest frequency
+            let bin = (height as usize - 1 - row) * bins / height as usize;
+            let dbfs = dsp::linear_to_dbfs(buffer[bin].norm() / full_scale);
+            let pixel = (row * width as usize + col) * 3;
+            pixels[pixel..pixel + 3].copy_from_slice(&heat(dbfs));
+        }
+    }
+
+    let file = std::fs::File::create(&output_png)
+        .map_err(|e| format!("Failed to create {}: {}", output_png, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| format!("Failed to write {}: {}", output_png, e))?;
+
+    Ok(SpectrogramInfo {
+        output_path: output_png,
+        fft_size,
+        hz_per_row: spec.sample_rate as f32 / 2.0 / height as f32,
+        ms_per_column: step * 1000.0 / spec.sample_rate as f32,
+    })
+}
+
+// dBFS to a heat-scale colour
+fn heat(dbfs: f32) -> [u8; 3] {
+    let t = (1.0 - dbfs / dsp::MIN_DBFS).clamp(0.0, 1.0) * (HEAT_STOPS.len() - 1) as f32;
+    let lower = (t as usize).min(HEAT_STOPS.len() - 2);
+    let frac = t - lower as f32;
+    let (a, b) = (HEAT_STOPS[lower], HEAT_STOPS[lower + 1]);
+    [0, 1, 2].map(|c| (a[c] + (b[c] - a[c]) * frac).round() as u8)
+}
@@ -0,0 +1,423 @@
+// Input stream lifecycle
+//
+// Every microphone stream is opened through `open_on_thread` (cpal streams
+// via `open_input_stream`), which owns the stream on a dedicated thread
+// (cpal streams are not `Send` on every platform) and drives the
+// `microphone-active` event: true when the first stream opens, false once
+// the last one is torn down.
+//
+// On Windows, cpal (0.15) opens WASAPI endpoints in shared mode only, so
+// other apps keep their access and Windows may resample.
+// `RecordingConfig::exclusive_mode` streams come from `exclusive` instead.
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, InputCallbackInfo, SizedSample, StreamConfig, StreamError, SupportedStreamConfig};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::convert::{self, ToI16};
+use super::dsp;
+use super::events;
+use super::pool::{self, PoolReceiver, PoolSender};
+
+const MIN_DEVICE_SAMPLE_RATE: u32 = 8000;
+
+static OPEN_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// A live input stream; dropping it stops and closes the device
+pub struct InputStreamHandle {
+    stop_tx: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for InputStreamHandle {
+    fn drop(&mut self) {
+        if let Some(tx) = self.stop_tx.take() {
+            let _ = tx.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reject configs some virtual drivers report (0 channels, 0 Hz) before
+/// they reach code that divides by the channel count or the rate
+pub fn check_input_config(config: &SupportedStreamConfig) -> Result<(), String> {
+    if config.channels() < 1 {
+        return Err("Input device reports no channels".to_string());
+    }
+    if config.sample_rate().0 < MIN_DEVICE_SAMPLE_RATE {
+        return Err(format!(
+            "Input device reports an unusable sample rate: {} Hz",
+            config.sample_rate().0
+        ));
+    }
+    Ok(())
+}
+
+/// Build and start an input stream, returning once it is playing
+pub fn open_input_stream<T, D>(
+    device: Device,
+    config: StreamConfig,
+    data_callback: D,
+) -> Result<InputStreamHandle, String>
+where
+    T: SizedSample,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+{
+    open_input_stream_with_errors(device, config, data_callback, |_| {})
+}
+
+/// `open_input_stream`, also handing every error the device reports to
+/// `on_error` (on cpal's thread, after logging it)
+pub fn open_input_stream_with_errors<T, D, E>(
+    device: Device,
+    config: StreamConfig,
+    data_callback: D,
+    mut on_error: E,
+) -> Result<InputStreamHandle, String>
+where
+    T: SizedSample,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    open_on_thread(move || {
+        let err_callback = move |err: StreamError| {
+            eprintln!("Audio input error: {}", err);
+            on_error(err);
+        };
+        let stream = device
+            .build_input_stream(&config, data_callback, err_callback, None)
+            .map_err(|e| format!("Failed to build input stream: {}", e))?;
+        stream.play().map_err(|e| format!("Failed to play stream: {}", e))?;
+        Ok(stream)
+    })
+}
+
+/// Run `open` on a thread of its own and hold what it returns, which keeps
+/// the stream running, until the handle is dropped. Returns once `open`
+/// has, with its error if it failed.
+pub fn open_on_thread<S, F>(open: F) -> Result<InputStreamHandle, String>
+where
+    F: FnOnce() -> Result<S, String> + Send + 'static,
+{
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(), String>>();
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+    let thread = thread::spawn(move || {
+        let stream = match open() {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+
+        if OPEN_STREAMS.fetch_add(1, Ordering::SeqCst) == 0 {
+            events::emit(events::MICROPHONE_ACTIVE, true);
+        }
+        let _ = ready_tx.send(Ok(()));
+
+        // Hold the stream until the handle asks us to stop (or is dropped)
+        let _ = stop_rx.recv();
+        drop(stream);
+
+        if OPEN_STREAMS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            events::emit(events::MICROPHONE_ACTIVE, false);
+        }
+    });
+
+    match ready_rx.recv() {
+        Ok(Ok(())) => Ok(InputStreamHandle {
+            stop_tx: Some(stop_tx),
+            thread: Some(thread),
+        }),
+        Ok(Err(e)) => {
+            let _ = thread.join();
+            Err(e)
+        }
+        Err(_) => {
+            let _ = thread.join();
+            Err("Audio stream thread exited unexpectedly".to_string())
+        }
+    }
+}
+
+/// Record `duration` of mono audio from `device` at its default config and
+/// close the stream before returning. Returns the samples and their rate.
+pub fn capture_mono(device: Device, duration: Duration) -> Result<(Vec<i16>, u32), String> {
+    let mut capture = MonoCapture::open(device)?;
+    let sample_rate = capture.sample_rate;
+    capture.collect_for(duration);
+    Ok((capture.finish()?, sample_rate))
+}
+
+// Buffers in a capture's pool, each sized for a typical device callback;
+// a bigger callback grows its buffer once and keeps the room when recycled
+const CAPTURE_BUFFERS: usize = 32;
+const CAPTURE_BUFFER_FRAMES: usize = 8192;
+
+/// Mono audio from a device at its default config. The callback downmixes
+/// into pooled buffers (see `pool`) and the owner collects them, so the
+/// device's thread never waits on a lock or allocates per callback.
+pub struct MonoCapture {
+    input: Option<InputStreamHandle>,
+    rx: PoolReceiver<i16>,
+    started: Arc<OnceLock<Instant>>,
+    samples: Vec<i16>,
+    pub sample_rate: u32,
+}
+
+impl MonoCapture {
+    pub fn open(device: Device) -> Result<Self, String> {
+        let config = device
+            .default_input_config()
+            .map_err(|e| format!("Failed to get default input config: {}", e))?;
+        check_input_config(&config)?;
+        let channels = config.channels() as usize;
+
+        let (tx, rx) = pool::buffer_pool(CAPTURE_BUFFERS, CAPTURE_BUFFER_FRAMES);
+        let started = Arc::new(OnceLock::new());
+        let input = convert::with_sample_type!(
+            config.sample_format(),
+            open_mono_capture(device, config.config(), channels, tx, started.clone())
+        )?;
+        Ok(Self { input: Some(input), rx, started, samples: Vec::new(), sample_rate: config.sample_rate().0 })
+    }
+
+    /// When the first callback arrived: the time of `samples[0]`
+    pub fn started(&self) -> Option<Instant> {
+        self.started.get().copied()
+    }
+
+    /// Collect what the device delivers over the next `duration`
+    pub fn collect_for(&mut self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match self.rx.recv_timeout(left) {
+                Ok(buffer) => {
+                    self.samples.extend_from_slice(&buffer);
+                    self.rx.recycle(buffer);
+                }
+                Err(_) => break, // Nothing more in time, or the stream has closed
+            }
+        }
+    }
+
+    /// Close the stream and return everything captured. Fails if audio had
+    /// to be dropped, as the samples would no longer line up with time.
+    pub fn finish(mut self) -> Result<Vec<i16>, String> {
+        drop(self.input.take());
+        while let Some(buffer) = self.rx.recv() {
+            self.samples.extend_from_slice(&buffer);
+        }
+        match self.rx.overruns() {
+            0 => Ok(self.samples),
+            n => Err(format!("Capture fell behind the device; {} buffers were dropped", n)),
+        }
+    }
+}
+
+fn open_mono_capture<T>(
+    device: Device,
+    config: StreamConfig,
+    channels: usize,
+    tx: PoolSender<i16>,
+    started: Arc<OnceLock<Instant>>,
+) -> Result<InputStreamHandle, String>
+where
+    T: ToI16,
+{
+    let mut frame_i16 = Vec::with_capacity(channels);
+    open_input_stream(device, config, move |data: &[T], _: &InputCallbackInfo| {
+        started.get_or_init(Instant::now);
+        tx.send_with(|buffer| downmix_into(data, channels, &mut frame_i16, buffer));
+    })
+}
+
+// Append `data`'s frames to `out` in mono, converting each through `frame_i16`
+// (sized for one frame) rather than a new Vec per frame
+fn downmix_into<T: ToI16>(data: &[T], channels: usize, frame_i16: &mut Vec<i16>, out: &mut Vec<i16>) {
+    for frame in data.chunks_exact(channels) {
+        frame_i16.clear();
+        frame_i16.extend(frame.iter().map(|&s| s.to_i16()));
+        out.push(dsp::downmix(frame_i16));
+    }
+}
+
+// How far the delivered rate may drift from the negotiated one. Callback
+// jitter over a window is ~1%; a profile switch (e.g. Bluetooth A2DP 48 kHz
+// to HFP 16 kHz) is far beyond this.
+const RATE_TOLERANCE: f64 = 0.1;
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+const RATE_WINDOWS_TO_FAIL: u32 = 2; // One stalled window (e.g. a system hiccup) is not enough
+
+/// Compares the frames a device delivers against the wall clock. cpal 0.15
+/// never reports a config change on a running stream, so a device that
+/// silently switches rate is only visible this way.
+pub struct RateMonitor {
+    nominal: u32,
+    window_start: Option<std::time::Instant>, // Unset until the first callback
+    frames: u64,
+    bad_windows: u32,
+}
+
+impl RateMonitor {
+    pub fn new(nominal: u32) -> Self {
+        Self { nominal, window_start: None, frames: 0, bad_windows: 0 }
+    }
+
+    /// Start over, e.g. after a gap in delivery that would read as a slow rate
+    pub fn reset(&mut self) {
+        *self = Self::new(self.nominal);
+    }
+
+    /// Count `frames` just received; returns the measured rate once it has
+    /// been off for enough consecutive windows
+    pub fn push(&mut self, frames: usize) -> Option<u32> {
+        let now = std::time::Instant::now();
+        // Start timing at the first delivery; the stream's startup burst
+        // belongs to the time before it
+        let Some(start) = self.window_start else {
+            self.window_start = Some(now);
+            return None;
+        };
+        self.frames += frames as u64;
+        let elapsed = now.duration_since(start);
+        if elapsed < RATE_WINDOW {
+            return None;
+        }
+
+        let measured = self.frames as f64 / elapsed.as_secs_f64();
+        self.window_start = Some(now);
+        self.frames = 0;
+        if (measured / self.nominal as f64 - 1.0).abs() <= RATE_TOLERANCE {
+            self.bad_windows = 0;
+            return None;
+        }
+        self.bad_windows += 1;
+        (self.bad_windows >= RATE_WINDOWS_TO_FAIL).then_some(measured.round() as u32)
+    }
+}
+
+// Capture waiting for the processing thread beyond this, and still growing
+// over consecutive windows, means it can't keep up; one slow window (a page
+// fault, a busy moment elsewhere) is not enough
+const BACKLOG_LIMIT_MS: u64 = 200;
+const BACKLOG_WINDOW: Duration = Duration::from_secs(1);
+const BACKLOG_WINDOWS_TO_FAIL: u32 = 3;
+
+/// Watches the captured audio queued for the processing thread, sampled
+/// once a window, for a backlog that keeps growing rather than one that
+/// spikes and drains
+pub struct BacklogMonitor {
+    limit_frames: u64,
+    window_start: Option<Instant>,
+    last: u64,
+    growing: u32,
+}
+
+impl BacklogMonitor {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { limit_frames: sample_rate as u64 * BACKLOG_LIMIT_MS / 1000, window_start: None, last: 0, growing: 0 }
+    }
+
+    /// Start over, e.g. on a reopened stream whose queue starts empty
+    pub fn reset(&mut self) {
+        *self = Self { limit_frames: self.limit_frames, window_start: None, last: 0, growing: 0 };
+    }
+
+    /// `queued` frames are waiting now; returns the backlog once it has
+    /// passed the limit and grown for enough windows in a row
+    pub fn push(&mut self, queued: u64) -> Option<u64> {
+        self.push_at(queued, Instant::now())
+    }
+
+    fn push_at(&mut self, queued: u64, now: Instant) -> Option<u64> {
+        let Some(start) = self.window_start else {
+            self.window_start = Some(now);
+            self.last = queued;
+            return None;
+        };
+        if now.duration_since(start) < BACKLOG_WINDOW {
+            return None;
+        }
+        self.window_start = Some(now);
+        self.growing = if queued > self.last { self.growing + 1 } else { 0 };
+        self.last = queued;
+        (self.growing >= BACKLOG_WINDOWS_TO_FAIL && queued > self.limit_frames).then_some(queued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleFormat, SampleRate, SupportedBufferSize};
+
+    fn device_config(channels: u16, sample_rate: u32) -> SupportedStreamConfig {
+        SupportedStreamConfig::new(channels, SampleRate(sample_rate), SupportedBufferSize::Unknown, SampleFormat::F32)
+    }
+
+    #[test]
+    fn bogus_device_configs_are_rejected() {
+        assert_eq!(check_input_config(&device_config(0, 48000)).unwrap_err(), "Input device reports no channels");
+        let err = check_input_config(&device_config(2, 0)).unwrap_err();
+        assert!(err.contains("unusable sample rate: 0 Hz"), "{}", err);
+        assert!(check_input_config(&device_config(1, MIN_DEVICE_SAMPLE_RATE - 1)).is_err());
+        assert!(check_input_config(&device_config(1, MIN_DEVICE_SAMPLE_RATE)).is_ok());
+        assert!(check_input_config(&device_config(2, 48000)).is_ok());
+    }
+
+    #[test]
+    fn callbacks_downmix_without_growing_the_frame_buffer() {
+        let mut frame_i16 = Vec::with_capacity(2);
+        let mut out = Vec::with_capacity(4);
+        downmix_into(&[100i16, 300, -200, -400, 7, 9], 2, &mut frame_i16, &mut out);
+        assert_eq!(out, [200, -300, 8]);
+        assert_eq!(frame_i16.capacity(), 2);
+
+        // A float device goes through the same conversion as a recording
+        let mut out = Vec::new();
+        downmix_into(&[0.5f32, 0.5, -1.0, 0.0], 2, &mut frame_i16, &mut out);
+        let expected = [
+            dsp::downmix(&[0.5f32.to_i16(), 0.5f32.to_i16()]),
+            dsp::downmix(&[(-1.0f32).to_i16(), 0.0f32.to_i16()]),
+        ];
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn only_a_backlog_that_keeps_growing_is_reported() {
+        let start = Instant::now();
+        let second = |n: u64| start + BACKLOG_WINDOW * n as u32;
+        let limit = 48000 * BACKLOG_LIMIT_MS / 1000;
+
+        // A spike that drains again never counts
+        let mut monitor = BacklogMonitor::new(48000);
+        for (n, queued) in [0, limit * 2, limit * 3, limit, limit * 4, limit * 5].into_iter().enumerate() {
+            assert_eq!(monitor.push_at(queued, second(n as u64)), None, "window {}", n);
+        }
+
+        // Growth below the limit doesn't either, however long it lasts
+        let mut monitor = BacklogMonitor::new(48000);
+        for n in 0..10 {
+            assert_eq!(monitor.push_at(n * limit / 10, second(n)), None);
+        }
+
+        // Growing past the limit for three windows running does
+        let mut monitor = BacklogMonitor::new(48000);
+        monitor.push_at(0, second(0));
+        // Pushes inside a window are not samples of their own
+        assert_eq!(monitor.push_at(limit * 9, second(0) + BACKLOG_WINDOW / 2), None);
+        assert_eq!(monitor.push_at(limit, second(1)), None);
+        assert_eq!(monitor.push_at(limit * 2, second(2)), None);
+        assert_eq!(monitor.push_at(limit * 3, second(3)), Some(limit * 3));
+
+        monitor.reset();
+        assert_eq!(monitor.push_at(limit * 4, second(4)), None);
+    }
+}
@@ -0,0 +1,1304 @@
+// Utilities that operate on finished recordings rather than the live stream
+
+use std::io::{Read, Seek};
+
+use super::convert;
+use super::dsp;
+use super::error::RecorderError;
+use super::events;
+use super::manifest;
+use super::resample::{self, ResamplerState};
+use super::sent::{self, SendFilter};
+use super::wav::{self, AudioContainer, PcmEncoding, WavMetadata, WavReader, WavSpec, WavWriter};
+use super::{supported_formats, RecordingFormat, FRAME_SIZE_MS, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+
+#[derive(Debug, serde::Serialize)]
+pub struct RecordingEntry {
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified_ms: u64, // Unix time
+    pub sent: bool,       // Marked by `mark_sent`
+}
+
+/// Recordings (`.wav`/`.aif`) in `dir`, sorted by path. `recursive` also
+/// searches subfolders, e.g. the per-day folders from `subdir_by_date`.
+/// `filter` keeps the sent recordings (the default), the unsent or all.
+pub fn list_recordings(dir: String, recursive: bool, filter: SendFilter) -> Result<Vec<RecordingEntry>, String> {
+    let mut entries = Vec::new();
+    collect_recordings(std::path::Path::new(&dir), recursive, &mut entries)?;
+    entries.retain(|e| filter.matches(e.sent));
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn collect_recordings(dir: &std::path::Path, recursive: bool, out: &mut Vec<RecordingEntry>) -> Result<(), String> {
+    let read_dir = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if recursive {
+                collect_recordings(&path, recursive, out)?;
+            }
+            continue;
+        }
+        let is_recording = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("wav") || e.eq_ignore_ascii_case("aif"));
+        if !is_recording {
+            continue;
+        }
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        out.push(RecordingEntry {
+            path: path.to_string_lossy().to_string(),
+            size_bytes: metadata.len(),
+            modified_ms,
+            sent: sent::is_sent(&path),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DownmixResult {
+    pub output_path: String,
+    pub source_channels: u16,
+    pub note: Option<String>,
+}
+
+/// Write a mono copy of a WAV file, averaging channels like live capture does
+pub fn downmix_to_mono(input: String, output: String) -> Result<DownmixResult, String> {
+    let target = OutputSpec { channels: Some(1), bits_per_sample: Some(16), ..Default::default() };
+    let result = transcode(input, output, target)?;
+    Ok(DownmixResult {
+        output_path: result.output_path,
+        source_channels: result.source.channels,
+        note: (result.source.channels == 1).then(|| "Input was already mono; copied unchanged".to_string()),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConcatResult {
+    pub output_path: String,
+    pub duration_ms: u64,
+}
+
+/// Join WAV files end to end; all inputs must share rate, channels and depth
+pub fn concat_wavs(paths: Vec<String>, output: String) -> Result<ConcatResult, String> {
+    let first = paths.first().ok_or("No input files given")?;
+    let spec = open_wav(first)?.spec();
+
+    // Check every header before writing anything
+    for path in &paths[1..] {
+        let other = open_wav(path)?.spec();
+        if other != spec {
+            return Err(format!(
+                "Format mismatch: {} is {}, but {} is {}",
+                first, describe(spec), path, describe(other)
+            ));
+        }
+    }
+
+    let mut writer = WavWriter::new(&output, spec.sample_rate, spec.channels)
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    let mut total_samples = 0u64;
+    for path in &paths {
+        let (_, _, _, samples) = read_wav(path)?;
+        writer.write_samples(&samples)
+            .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+        total_samples += samples.len() as u64;
+    }
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+
+    Ok(ConcatResult {
+        output_path: output,
+        duration_ms: total_samples / spec.channels as u64 * 1000 / spec.sample_rate as u64,
+    })
+}
+
+/// Open a WAV/AIFF, checking the header describes audio before anything is read
+pub(super) fn open_wav(path: &str) -> Result<WavReader, String> {
+    let reader = WavReader::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    checked(reader, path)
+}
+
+/// Reject a header with no channels or no sample rate
+pub(super) fn checked<R: Read + Seek>(reader: WavReader<R>, path: &str) -> Result<WavReader<R>, String> {
+    let spec = reader.spec();
+    if spec.channels == 0 || spec.sample_rate == 0 {
+        return Err(format!("Invalid WAV format in {}", path));
+    }
+    Ok(reader)
+}
+
+/// A whole 16-bit file, with what it takes to write it back the same way
+pub(super) fn read_wav(path: &str) -> Result<(WavSpec, AudioContainer, Option<WavMetadata>, Vec<i16>), String> {
+    let mut reader = open_wav(path)?;
+    let samples = reader.read_samples()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok((reader.spec(), reader.container(), reader.metadata().cloned(), samples))
+}
+
+/// Frames per level-meter frame, so offline silence decisions match the
+/// live meter's
+fn meter_frames(sample_rate: u32) -> usize {
+    (sample_rate as usize * FRAME_SIZE_MS as usize / 1000).max(1)
+}
+
+fn describe(spec: WavSpec) -> String {
+    format!("{} Hz, {} ch, {}-bit", spec.sample_rate, spec.channels, spec.bits_per_sample)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct WavValidation {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub data_size: u64,
+    pub duration_ms: u64,
+    /// Problems a player will likely tolerate; hard failures are errors
+    pub warnings: Vec<String>,
+}
+
+/// Check that a file is a well-formed WAV we can send, before uploading it
+pub fn validate_wav(path: String) -> Result<WavValidation, RecorderError> {
+    let invalid = |msg: String| RecorderError::InvalidWav(msg);
+    let layout = wav::scan_layout(&path).map_err(|e| invalid(e.to_string()))?;
+    let mut warnings = Vec::new();
+
+    let spec = layout.fmt.ok_or_else(|| invalid("Missing or truncated fmt chunk".to_string()))?;
+    if spec.format_tag != 1 || spec.bits_per_sample != 16 {
+        return Err(invalid(format!(
+            "Unsupported encoding (format {}, {}-bit); expected 16-bit PCM",
+            spec.format_tag, spec.bits_per_sample
+        )));
+    }
+    if spec.channels == 0 || spec.sample_rate == 0 {
+        return Err(invalid(format!(
+            "Invalid format: {} channels at {} Hz", spec.channels, spec.sample_rate
+        )));
+    }
+    let block_align = spec.channels as u32 * 2;
+    if layout.block_align as u32 != block_align || layout.byte_rate != spec.sample_rate * block_align {
+        warnings.push("Byte rate / block align fields are inconsistent with the format".to_string());
+    }
+
+    let data = layout
+        .chunks
+        .iter()
+        .find(|c| &c.id == b"data")
+        .ok_or_else(|| invalid("No data chunk".to_string()))?;
+    let available = layout.file_len - data.offset;
+    if data.size == 0 && layout.riff_size == 0 && available > 0 {
+        return Err(invalid("Header sizes are zero; the recording was never finalized".to_string()));
+    }
+
+    // Any chunk other than data running past the end means real corruption
+    if let Some(chunk) = layout.chunks.iter().find(|c| {
+        &c.id != b"data" && c.offset + c.size > layout.file_len
+    }) {
+        return Err(invalid(format!(
+            "Chunk '{}' claims {} bytes but the file ends first",
+            String::from_utf8_lossy(&chunk.id),
+            chunk.size
+        )));
+    }
+
+    let data_size = if data.size > available {
+        warnings.push(format!(
+            "Data size in header ({} bytes) is larger than the file ({} bytes available); file is truncated",
+            data.size, available
+        ));
+        available
+    } else {
+        data.size
+    };
+    if data_size % block_align as u64 != 0 {
+        warnings.push("Data size is not a whole number of sample frames".to_string());
+    }
+    if layout.riff_size + 8 != layout.file_len {
+        warnings.push(format!(
+            "RIFF size in header ({} bytes) does not match the file length ({} bytes)",
+            layout.riff_size + 8,
+            layout.file_len
+        ));
+    }
+
+    Ok(WavValidation {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        bits_per_sample: spec.bits_per_sample,
+        data_size,
+        duration_ms: data_size / block_align as u64 * 1000 / spec.sample_rate as u64,
+        warnings,
+    })
+}
+
+/// Apply volume automation: `points` are (time_seconds, gain_db), interpolated
+/// linearly in dB. Gain holds at the first/last point outside their range.
+pub fn apply_gain_envelope(input: String, output: String, points: Vec<(f64, f32)>) -> Result<(), String> {
+    if points.is_empty() {
+        return Err("Gain envelope needs at least one point".to_string());
+    }
+    if points.iter().any(|&(t, db)| !t.is_finite() || !db.is_finite()) {
+        return Err("Gain envelope points must be finite numbers".to_string());
+    }
+    if points.windows(2).any(|w| w[1].0 < w[0].0) {
+        return Err("Gain envelope points must be sorted by time".to_string());
+    }
+
+    let (spec, _, _, mut samples) = read_wav(&input)?;
+
+    let mut next = 0; // First point at or after the current time
+    for (i, frame) in samples.chunks_exact_mut(spec.channels as usize).enumerate() {
+        let t = i as f64 / spec.sample_rate as f64;
+        while next < points.len() && points[next].0 < t {
+            next += 1;
+        }
+        let db = match (next.checked_sub(1).map(|p| points[p]), points.get(next)) {
+            (Some((t0, db0)), Some(&(t1, db1))) if t1 > t0 => {
+                db0 + (db1 - db0) * ((t - t0) / (t1 - t0)) as f32
+            }
+            (_, Some(&(_, db))) | (Some((_, db)), None) => db,
+            (None, None) => 0.0,
+        };
+        let gain = dsp::db_to_gain(db);
+        for sample in frame.iter_mut() {
+            *sample = dsp::apply_gain(*sample, gain);
+        }
+    }
+
+    let mut writer = WavWriter::new(&output, spec.sample_rate, spec.channels)
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    writer.write_samples(&samples)
+        .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))
+}
+
+/// Finalize-time tail edits: cut trailing frames quieter than
+/// `trim_threshold` (linear RMS), then fade out the last `fade_out_ms` before
+/// the new end. Trimming first means the fade shapes audio that is kept,
+/// and the last sample always lands on zero. Returns the frames kept.
+/// A file larger than `max_buffer_bytes` is worked on in place, a piece at
+/// a time, rather than loaded whole.
+pub fn trim_and_fade(
+    path: &str,
+    trim_threshold: Option<f32>,
+    fade_out_ms: u64,
+    max_buffer_bytes: Option<u64>,
+) -> Result<u64, String> {
+    let mut reader = open_wav(path)?;
+    if let Some(budget) = max_buffer_bytes.filter(|&budget| reader.data_size() > budget) {
+        return trim_and_fade_in_place(reader, path, trim_threshold, fade_out_ms, budget);
+    }
+    let spec = reader.spec();
+    let container = reader.container();
+    let metadata = reader.metadata().cloned();
+    let mut samples = reader.read_samples()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    drop(reader);
+    let channels = spec.channels as usize;
+
+    if let Some(threshold) = trim_threshold {
+        let frame = meter_frames(spec.sample_rate) * channels;
+        let mut end = samples.len() / channels * channels;
+        while end > 0 {
+            let start = end.saturating_sub(frame);
+            if dsp::frame_rms(&samples[start..end]) >= threshold {
+                break;
+            }
+            end = start;
+        }
+        samples.truncate(end);
+    }
+
+    let frames = samples.len() / channels;
+    let fade_frames = ((fade_out_ms * spec.sample_rate as u64 / 1000) as usize).min(frames);
+    for i in 0..fade_frames {
+        let gain = 1.0 - (i + 1) as f32 / fade_frames as f32;
+        let frame = frames - fade_frames + i;
+        for sample in &mut samples[frame * channels..(frame + 1) * channels] {
+            *sample = dsp::apply_gain(*sample, gain);
+        }
+    }
+
+    let mut writer = WavWriter::with_metadata(path, spec.sample_rate, spec.channels, container, metadata.as_ref())
+        .map_err(|e| format!("Failed to rewrite {}: {}", path, e))?;
+    writer.write_samples(&samples)
+        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", path, e))?;
+    Ok(frames as u64)
+}
+
+// `trim_and_fade` holding at most `budget` bytes of samples (or the fade,
+// if that is longer): the trim scans back from the end in budget-sized
+// pieces, aligned to the same meter frames, and the fade rewrites only the tail
+fn trim_and_fade_in_place(
+    mut reader: WavReader,
+    path: &str,
+    trim_threshold: Option<f32>,
+    fade_out_ms: u64,
+    budget: u64,
+) -> Result<u64, String> {
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", path, e);
+    let mut end = reader.frames();
+
+    if let Some(threshold) = trim_threshold {
+        let frame = meter_frames(spec.sample_rate) as u64;
+        let piece = (budget / (channels as u64 * 2) / frame).max(1) * frame;
+        'scan: while end > 0 {
+            let start = end.saturating_sub(piece);
+            let samples = reader.read_frames_at(start, end - start).map_err(read_error)?;
+            let mut local_end = (end - start) as usize;
+            while local_end > 0 {
+                let local_start = local_end.saturating_sub(frame as usize);
+                if dsp::frame_rms(&samples[local_start * channels..local_end * channels]) >= threshold {
+                    end = start + local_end as u64;
+                    break 'scan;
+                }
+                local_end = local_start;
+            }
+            end = start;
+        }
+    }
+
+    let fade_frames = (fade_out_ms * spec.sample_rate as u64 / 1000).min(end);
+    let mut tail = reader.read_frames_at(end - fade_frames, fade_frames).map_err(read_error)?;
+    drop(reader);
+    for i in 0..fade_frames as usize {
+        let gain = 1.0 - (i + 1) as f32 / fade_frames as f32;
+        for sample in &mut tail[i * channels..(i + 1) * channels] {
+            *sample = dsp::apply_gain(*sample, gain);
+        }
+    }
+    wav::rewrite_tail(path, end, end - fade_frames, &tail)
+        .map_err(|e| format!("Failed to rewrite {}: {}", path, e))?;
+    Ok(end)
+}
+
+// Rates tried, best first, when shrinking a file to fit a size budget
+const FIT_SAMPLE_RATES: [u32; 9] = [48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000];
+
+#[derive(Debug, serde::Serialize)]
+pub struct FitResult {
+    pub output_path: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub size_bytes: u64,
+    pub reduced: bool, // False when the input already fit and was copied
+}
+
+/// Write a copy of a WAV that is at most `max_bytes`, lowering the sample
+/// rate (and going mono if that is not enough). Errors if even 8 kHz mono
+/// would not fit. Only WAV output is supported; there is no Opus encoder here.
+pub fn fit_to_size(input: String, output: String, max_bytes: u64) -> Result<FitResult, String> {
+    if !output.to_ascii_lowercase().ends_with(".wav") {
+        return Err("fit_to_size can only write WAV; Opus encoding is not available".to_string());
+    }
+    let (spec, _, _, samples) = read_wav(&input)?;
+
+    let frames = (samples.len() / spec.channels as usize) as u64;
+    let size_at = |rate: u32, channels: u16| {
+        AudioContainer::Wav.header_bytes() + (frames * rate as u64).div_ceil(spec.sample_rate as u64) * channels as u64 * 2
+    };
+
+    // Keep the channel layout if some rate fits; otherwise fall back to mono
+    let rates: Vec<u32> = std::iter::once(spec.sample_rate)
+        .chain(FIT_SAMPLE_RATES.iter().copied().filter(|&r| r < spec.sample_rate))
+        .collect();
+    let layouts: Vec<u16> = if spec.channels > 1 { vec![spec.channels, 1] } else { vec![1] };
+    let (rate, channels) = layouts
+        .iter()
+        .flat_map(|&channels| rates.iter().map(move |&rate| (rate, channels)))
+        .find(|&(rate, channels)| size_at(rate, channels) <= max_bytes)
+        .ok_or_else(|| format!(
+            "Cannot fit {} into {} bytes even at 8 kHz mono ({} bytes needed)",
+            input, max_bytes, size_at(8000, 1)
+        ))?;
+
+    let mut audio = if channels != spec.channels {
+        samples.chunks_exact(spec.channels as usize).map(dsp::downmix).collect()
+    } else {
+        samples
+    };
+    if rate != spec.sample_rate {
+        let mut states = vec![ResamplerState::default(); channels as usize];
+        audio = resample::resample_interleaved(&audio, channels as usize, spec.sample_rate, rate, &mut states);
+    }
+    // The resampler can round up by a frame; never overshoot the budget
+    let max_samples = ((max_bytes - AudioContainer::Wav.header_bytes()) / 2 / channels as u64 * channels as u64) as usize;
+    audio.truncate(max_samples);
+
+    let mut writer = WavWriter::new(&output, rate, channels)
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    writer.write_samples(&audio)
+        .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+
+    let size_bytes = std::fs::metadata(&output).map(|m| m.len()).unwrap_or(0);
+    Ok(FitResult {
+        output_path: output,
+        sample_rate: rate,
+        channels,
+        bits_per_sample: 16,
+        size_bytes,
+        reduced: rate != spec.sample_rate || channels != spec.channels,
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SegmentResult {
+    pub output_path: String,
+    pub start_ms: u64, // Range actually copied, after clamping to the file
+    pub end_ms: u64,
+}
+
+/// Copy the frames between `start_seconds` and `end_seconds` into a new file
+/// with the source's rate, channels and container (no resampling)
+pub fn extract_segment(
+    input: String,
+    output: String,
+    start_seconds: f64,
+    end_seconds: f64,
+) -> Result<SegmentResult, String> {
+    if !start_seconds.is_finite() || !end_seconds.is_finite() {
+        return Err("Segment bounds must be finite numbers".to_string());
+    }
+    if start_seconds >= end_seconds {
+        return Err(format!("Segment start ({}s) must be before its end ({}s)", start_seconds, end_seconds));
+    }
+
+    let (spec, container, metadata, samples) = read_wav(&input)?;
+
+    let channels = spec.channels as usize;
+    let frames = samples.len() / channels;
+    let to_frame = |seconds: f64| ((seconds.max(0.0) * spec.sample_rate as f64).round() as usize).min(frames);
+    let (start, end) = (to_frame(start_seconds), to_frame(end_seconds));
+    if start >= end {
+        return Err(format!("Segment {}s-{}s is outside {}", start_seconds, end_seconds, input));
+    }
+
+    let mut writer = WavWriter::with_metadata(&output, spec.sample_rate, spec.channels, container, metadata.as_ref())
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    writer.write_samples(&samples[start * channels..end * channels])
+        .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+
+    let to_ms = |frame: usize| frame as u64 * 1000 / spec.sample_rate as u64;
+    Ok(SegmentResult {
+        output_path: output,
+        start_ms: to_ms(start),
+        end_ms: to_ms(end),
+    })
+}
+
+// Gains smaller than this are treated as "already at target"
+const NORMALIZE_TOLERANCE_DB: f32 = 0.05;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NormalizeResult {
+    pub output_path: String,
+    pub peak_dbfs: f32,       // Before normalizing
+    pub applied_gain_db: f32, // 0.0 when the file was left untouched
+}
+
+/// Scale `input` so its peak sits at `target_dbfs`, writing `output` (which
+/// may be the same path). A file already at the target, or silent, is not
+/// rewritten; it is only copied when `output` is a different path.
+pub fn normalize_wav(input: String, output: String, target_dbfs: f32) -> Result<NormalizeResult, String> {
+    if !target_dbfs.is_finite() || target_dbfs > 0.0 || target_dbfs <= dsp::MIN_DBFS {
+        return Err(format!("Normalize target must be between {} and 0 dBFS", dsp::MIN_DBFS));
+    }
+
+    let (spec, container, metadata, mut samples) = read_wav(&input)?;
+
+    let peak = samples.iter().map(|&s| (s as f32).abs()).fold(0.0, f32::max) / 32768.0;
+    let peak_dbfs = dsp::linear_to_dbfs(peak);
+    let gain_db = target_dbfs - peak_dbfs;
+    if peak == 0.0 || gain_db.abs() < NORMALIZE_TOLERANCE_DB {
+        if output != input {
+            std::fs::copy(&input, &output)
+                .map_err(|e| format!("Failed to copy {} to {}: {}", input, output, e))?;
+        }
+        return Ok(NormalizeResult { output_path: output, peak_dbfs, applied_gain_db: 0.0 });
+    }
+
+    let gain = dsp::db_to_gain(gain_db);
+    for sample in samples.iter_mut() {
+        *sample = dsp::apply_gain(*sample, gain);
+    }
+    let mut writer = WavWriter::with_metadata(&output, spec.sample_rate, spec.channels, container, metadata.as_ref())
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    writer.write_samples(&samples)
+        .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+
+    Ok(NormalizeResult { output_path: output, peak_dbfs, applied_gain_db: gain_db })
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BatchProgress {
+    pub index: usize, // 1-based, of `total`
+    pub total: usize,
+    pub path: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BatchNormalizeEntry {
+    pub path: String,
+    pub result: Option<NormalizeResult>,
+    pub error: Option<String>, // A failed file does not stop the batch
+}
+
+/// Peak-normalize every recording in `dir` (not recursive), one file at a
+/// time. With `output_dir` the originals are kept and results go there
+/// under the same names; otherwise files are rewritten in place.
+pub fn normalize_directory(
+    dir: String,
+    target_dbfs: f32,
+    output_dir: Option<String>,
+) -> Result<Vec<BatchNormalizeEntry>, String> {
+    let recordings = list_recordings(dir, false, SendFilter::All)?;
+    if let Some(out) = &output_dir {
+        std::fs::create_dir_all(out)
+            .map_err(|e| format!("Failed to create {}: {}", out, e))?;
+    }
+
+    let total = recordings.len();
+    let mut entries = Vec::with_capacity(total);
+    for (i, recording) in recordings.into_iter().enumerate() {
+        events::emit(events::BATCH_PROGRESS, BatchProgress {
+            index: i + 1,
+            total,
+            path: recording.path.clone(),
+        });
+        let output = match (&output_dir, std::path::Path::new(&recording.path).file_name()) {
+            (Some(out), Some(name)) => std::path::Path::new(out).join(name).to_string_lossy().to_string(),
+            _ => recording.path.clone(),
+        };
+        let (result, error) = match normalize_wav(recording.path.clone(), output, target_dbfs) {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        entries.push(BatchNormalizeEntry { path: recording.path, result, error });
+    }
+    Ok(entries)
+}
+
+/// Split a long take into one file per stretch of speech. Cuts happen at
+/// silences (below `threshold`, the recorder's default when `None`) lasting
+/// at least `min_silence_ms`; shorter pauses stay inside a segment, and the
+/// long silences themselves are dropped. Segments shorter than
+/// `min_segment_ms` are discarded. Files are named `<input stem>_001.wav`
+/// and so on in `output_dir`; returns their paths in order.
+pub fn auto_split_on_silence(
+    input: String,
+    output_dir: String,
+    min_silence_ms: u64,
+    min_segment_ms: u64,
+    threshold: Option<dsp::SilenceThreshold>,
+) -> Result<Vec<String>, String> {
+    if min_silence_ms == 0 {
+        return Err("min_silence_ms must be greater than 0".to_string());
+    }
+    let threshold = threshold.unwrap_or_default();
+
+    let (spec, container, metadata, samples) = read_wav(&input)?;
+
+    let channels = spec.channels as usize;
+    let frame_ms = FRAME_SIZE_MS as u64;
+    let frame = meter_frames(spec.sample_rate) * channels;
+    let silent: Vec<bool> = samples.chunks(frame).map(|f| threshold.is_silent(dsp::frame_rms(f))).collect();
+    let min_silence_frames = min_silence_ms.div_ceil(frame_ms) as usize;
+    let min_segment_frames = min_segment_ms.div_ceil(frame_ms) as usize;
+
+    // Frame ranges between silences long enough to cut at
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < silent.len() {
+        if !silent[i] {
+            i += 1;
+            continue;
+        }
+        let run_start = i;
+        while i < silent.len() && silent[i] {
+            i += 1;
+        }
+        if i - run_start >= min_silence_frames {
+            segments.push(start..run_start);
+            start = i;
+        }
+    }
+    segments.push(start..silent.len());
+
+    std::fs::create_dir_all(&output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir, e))?;
+    let stem = std::path::Path::new(&input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "segment".to_string());
+
+    let mut paths = Vec::new();
+    for range in segments.into_iter().filter(|r| r.len() >= min_segment_frames.max(1)) {
+        let path = std::path::Path::new(&output_dir)
+            .join(format!("{}_{:03}.{}", stem, paths.len() + 1, container.extension()))
+            .to_string_lossy()
+            .to_string();
+        let end = (range.end * frame).min(samples.len());
+        let mut writer = WavWriter::with_metadata(&path, spec.sample_rate, spec.channels, container, metadata.as_ref())
+            .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        writer.write_samples(&samples[range.start * frame..end])
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        writer.finish()
+            .map_err(|e| format!("Failed to finalize {}: {}", path, e))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EnsureFormatResult {
+    pub output_path: String,
+    pub source: WavFormatInfo,
+    pub resampled: bool,
+    pub remixed: bool,     // Channels downmixed or duplicated
+    pub requantized: bool, // Converted to 16-bit
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct WavFormatInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+/// Bring an incoming WAV/AIFF to `target_rate` Hz, `target_channels` (1 or
+/// 2) and `target_bits` (16), converting only what differs, in the same
+/// container. A file already in that format is copied byte for byte.
+pub fn ensure_format(
+    input: String,
+    output: String,
+    target_rate: u32,
+    target_channels: u16,
+    target_bits: u16,
+) -> Result<EnsureFormatResult, String> {
+    if target_bits != 16 {
+        return Err(format!("Unsupported target bit depth: {} (only 16-bit is written)", target_bits));
+    }
+    if !(1..=2).contains(&target_channels) {
+        return Err(format!("Unsupported target channel count: {}", target_channels));
+    }
+
+    let reader = open_wav(&input)?;
+    let (spec, container) = (reader.spec(), reader.container());
+    drop(reader);
+    let unchanged = spec.format_tag == 1
+        && spec.bits_per_sample == 16
+        && spec.channels == target_channels
+        && spec.sample_rate == target_rate;
+    if unchanged {
+        if output != input {
+            std::fs::copy(&input, &output)
+                .map_err(|e| format!("Failed to copy {} to {}: {}", input, output, e))?;
+        }
+        let source = WavFormatInfo {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            bits_per_sample: spec.bits_per_sample,
+        };
+        return Ok(EnsureFormatResult { output_path: output, source, resampled: false, remixed: false, requantized: false });
+    }
+
+    let target = OutputSpec {
+        container,
+        sample_rate: Some(target_rate),
+        channels: Some(target_channels),
+        bits_per_sample: Some(16),
+        ..Default::default()
+    };
+    let result = transcode(input, output, target)?;
+    Ok(EnsureFormatResult {
+        output_path: result.output_path,
+        source: result.source,
+        resampled: result.resampled,
+        remixed: result.remixed,
+        requantized: result.requantized,
+    })
+}
+
+/// Same mapping as live capture: average to mono, or take the leading
+/// channels and repeat the last one
+fn remix(audio: &[i16], from: u16, to: u16) -> Vec<i16> {
+    let from = from as usize;
+    match to {
+        1 => audio.chunks_exact(from).map(dsp::downmix).collect(),
+        _ => audio
+            .chunks_exact(from)
+            .flat_map(|frame| (0..to as usize).map(move |ch| frame[ch.min(from - 1)]))
+            .collect(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PrependResult {
+    pub output_path: String,
+    pub duration_ms: u64,      // Intro plus recording
+    pub intro_ms: u64,         // Length of the intro as written
+    pub intro_converted: bool, // Intro was remixed or resampled to match
+}
+
+/// Write `intro` followed by `recording` to `output`. The intro is remixed
+/// and resampled to the recording's rate and channels; the recording itself
+/// is copied as is and must be 16-bit PCM.
+pub fn prepend_audio(intro: String, recording: String, output: String) -> Result<PrependResult, String> {
+    let mut reader = open_wav(&recording)?;
+    let spec = reader.spec();
+    if spec.format_tag != 1 || spec.bits_per_sample != 16 {
+        return Err(format!("Unsupported recording format in {}: {} (expected 16-bit PCM)", recording, describe(spec)));
+    }
+    if spec.channels > 2 {
+        return Err(format!("Unsupported channel count in {}: {}", recording, spec.channels));
+    }
+    let container = reader.container();
+    let samples = reader.read_samples()
+        .map_err(|e| format!("Failed to read {}: {}", recording, e))?;
+    drop(reader);
+
+    let mut intro_reader = open_wav(&intro)?;
+    let intro_spec = intro_reader.spec();
+    let mut intro_audio = intro_reader.read_samples_i16()
+        .map_err(|e| format!("Failed to read {}: {}", intro, e))?;
+    drop(intro_reader);
+
+    let intro_converted = intro_spec.channels != spec.channels || intro_spec.sample_rate != spec.sample_rate;
+    if intro_spec.channels != spec.channels {
+        intro_audio = remix(&intro_audio, intro_spec.channels, spec.channels);
+    }
+    if intro_spec.sample_rate != spec.sample_rate {
+        let mut states = vec![ResamplerState::default(); spec.channels as usize];
+        intro_audio = resample::resample_interleaved(
+            &intro_audio, spec.channels as usize, intro_spec.sample_rate, spec.sample_rate, &mut states,
+        );
+    }
+    // A partial frame at the join would shift every channel of the recording
+    let channels = spec.channels as usize;
+    if intro_audio.len() % channels != 0 || samples.len() % channels != 0 {
+        return Err(format!(
+            "Format mismatch after conversion: {} does not align with {} ({})",
+            intro, recording, describe(spec)
+        ));
+    }
+
+    let mut writer = WavWriter::with_container(&output, spec.sample_rate, spec.channels, container)
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    for part in [&intro_audio, &samples] {
+        writer.write_samples(part)
+            .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    }
+    writer.finish()
+        .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+
+    let to_ms = |len: usize| (len / channels) as u64 * 1000 / spec.sample_rate as u64;
+    Ok(PrependResult {
+        output_path: output,
+        duration_ms: to_ms(intro_audio.len() + samples.len()),
+        intro_ms: to_ms(intro_audio.len()),
+        intro_converted,
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RequantizeResult {
+    pub output_path: String,
+    pub source_bits: u16,
+    pub target_bits: u16,
+    pub dithered: bool, // False when the conversion was an exact widening
+}
+
+/// Re-encode `input` at `target_bits` (16 or 24-bit PCM, or 32-bit float)
+/// without changing its rate or channels. Going to an integer depth that
+/// can't hold every source value adds TPDF dither. Widening (16 -> 24, or
+/// up to 24-bit -> float) is exact; 32-bit integer and 64-bit float sources
+/// are rounded to 32-bit float's 24-bit precision, without dither.
+pub fn requantize_wav(input: String, output: String, target_bits: u16) -> Result<RequantizeResult, String> {
+    let target = PcmEncoding::from_bits(target_bits)
+        .ok_or_else(|| format!("Unsupported target depth: {}-bit (use 16, 24 or 32 for float)", target_bits))?;
+    let spec = open_wav(&input)?.spec();
+    if spec.format_tag == target.format_tag() && spec.bits_per_sample == target_bits {
+        return Err(format!("{} is already {}", input, describe(spec)));
+    }
+
+    let result = transcode(input, output, OutputSpec { bits_per_sample: Some(target_bits), ..Default::default() })?;
+    Ok(RequantizeResult {
+        output_path: result.output_path,
+        source_bits: spec.bits_per_sample,
+        target_bits,
+        dithered: result.dithered,
+    })
+}
+
+/// TPDF dither one step of a `bits`-deep integer grid wide, added before
+/// rounding onto it
+fn add_dither(samples: &mut [f64], bits: u16) {
+    let step = 1.0 / (1u64 << (bits - 1)) as f64;
+    let mut dither = dsp::TpdfDither::default();
+    for sample in samples {
+        *sample += dither.next_steps() * step;
+    }
+}
+
+// Rates an Opus stream can be encoded at
+const OPUS_RATES: [u32; 5] = [8_000, 12_000, 16_000, 24_000, 48_000];
+
+/// Target of `transcode`; fields left unset keep the input's value
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct OutputSpec {
+    pub format: RecordingFormat,
+    /// Framing for WAV-format output: RIFF `.wav` or AIFF `.aif`
+    pub container: AudioContainer,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,        // 1 or 2
+    pub bits_per_sample: Option<u16>, // 16 or 24-bit PCM, or 32 for float
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TranscodeResult {
+    pub output_path: String,
+    pub source: WavFormatInfo,
+    pub target: WavFormatInfo,
+    pub container: AudioContainer,
+    pub resampled: bool,
+    pub remixed: bool,     // Channels downmixed or duplicated
+    pub requantized: bool, // Bit depth or sample type changed
+    pub dithered: bool,    // TPDF dither added before rounding to a shallower depth
+    pub duration_ms: u64,
+}
+
+/// Convert `input` to `target`, doing only what differs: remix, then
+/// resample, then requantize. The resampler works on 16-bit samples, so a
+/// resampled file keeps 16-bit precision at any output depth. This build
+/// only reads and writes WAV/AIFF; the other formats are checked against
+/// their own limits and then refused for lack of an encoder.
+pub fn transcode(input: String, output: String, target: OutputSpec) -> Result<TranscodeResult, String> {
+    let mut reader = open_wav(&input)?;
+    let spec = reader.spec();
+    let rate = target.sample_rate.unwrap_or(spec.sample_rate);
+    let channels = target.channels.unwrap_or(spec.channels);
+    let bits = target.bits_per_sample.unwrap_or(spec.bits_per_sample);
+
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&rate) {
+        return Err(format!("Unsupported target sample rate: {} Hz", rate));
+    }
+    if !(1..=2).contains(&channels) {
+        return Err(format!("Unsupported target channel count: {}", channels));
+    }
+    if target.format == RecordingFormat::Opus && !OPUS_RATES.contains(&rate) {
+        return Err(format!("Opus can't be encoded at {} Hz (use 8, 12, 16, 24 or 48 kHz)", rate));
+    }
+    if !supported_formats().contains(&target.format) {
+        return Err(format!("{:?} output is not supported in this build", target.format));
+    }
+    let encoding = PcmEncoding::from_bits(bits)
+        .ok_or_else(|| format!("Unsupported target depth: {}-bit (use 16, 24 or 32 for float)", bits))?;
+    if target.container == AudioContainer::Aiff && encoding != PcmEncoding::Int16 {
+        return Err(format!("AIFF output is 16-bit only (asked for {}-bit)", bits));
+    }
+
+    // Only 16-bit WAV can outgrow RIFF's 4 GB, as RF64
+    let frames = (reader.frames() as u128 * rate as u128 / spec.sample_rate as u128) as u64;
+    let data_bytes = frames * channels as u64 * bits as u64 / 8;
+    let header_bytes = target.container.header_bytes();
+    if (target.container == AudioContainer::Aiff || encoding != PcmEncoding::Int16)
+        && data_bytes + header_bytes > u32::MAX as u64
+    {
+        return Err(format!(
+            "{} would be {} bytes, over the 4 GB limit for {}-bit {:?} (only 16-bit WAV can be larger)",
+            output, data_bytes + header_bytes, bits, target.container
+        ));
+    }
+
+    let source = WavFormatInfo {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        bits_per_sample: spec.bits_per_sample,
+    };
+    let remixed = channels != spec.channels;
+    let resampled = rate != spec.sample_rate;
+    let requantized = spec.format_tag != encoding.format_tag() || spec.bits_per_sample != bits;
+    let metadata = reader.metadata().cloned();
+    let mut samples = reader.read_samples_f64()
+        .map_err(|e| format!("Failed to read {}: {}", input, e))?;
+    drop(reader);
+
+    // Precision the samples carry into the final rounding
+    let (mut float, mut precision) = (spec.format_tag == 3, spec.bits_per_sample);
+    let mut dithered = false;
+    if resampled {
+        if float || precision > 16 {
+            add_dither(&mut samples, 16);
+            dithered = true;
+        }
+        let audio: Vec<i16> = samples.iter().map(|&s| convert::f64_to_i16(s)).collect();
+        let audio = if remixed { remix(&audio, spec.channels, channels) } else { audio };
+        let mut states = vec![ResamplerState::default(); channels as usize];
+        let audio = resample::resample_interleaved(&audio, channels as usize, spec.sample_rate, rate, &mut states);
+        samples = audio.iter().map(|&s| convert::i16_to_f32(s) as f64).collect();
+        (float, precision) = (false, 16);
+    } else if remixed {
+        samples = remix_f64(&samples, spec.channels, channels);
+    }
+    if encoding != PcmEncoding::Float32 && (float || precision > bits) {
+        add_dither(&mut samples, bits);
+        dithered = true;
+    }
+
+    if encoding == PcmEncoding::Int16 {
+        // AIFF has no INFO chunk; the output goes untagged
+        let metadata = metadata.as_ref().filter(|_| target.container == AudioContainer::Wav);
+        let mut writer = WavWriter::with_metadata(&output, rate, channels, target.container, metadata)
+            .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+        let audio: Vec<i16> = samples.iter().map(|&s| convert::f64_to_i16(s)).collect();
+        writer.write_samples(&audio)
+            .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+        writer.finish()
+            .map_err(|e| format!("Failed to finalize {}: {}", output, e))?;
+    } else {
+        wav::write_wav_f64(&output, rate, channels, encoding, &samples)
+            .map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    }
+
+    Ok(TranscodeResult {
+        output_path: output,
+        source,
+        target: WavFormatInfo { sample_rate: rate, channels, bits_per_sample: bits },
+        container: target.container,
+        resampled,
+        remixed,
+        requantized,
+        dithered,
+        duration_ms: (samples.len() / channels as usize) as u64 * 1000 / rate as u64,
+    })
+}
+
+// `remix` without rounding to 16 bits
+fn remix_f64(audio: &[f64], from: u16, to: u16) -> Vec<f64> {
+    let from = from as usize;
+    match to {
+        1 => audio.chunks_exact(from).map(|frame| frame.iter().sum::<f64>() / from as f64).collect(),
+        _ => audio
+            .chunks_exact(from)
+            .flat_map(|frame| (0..to as usize).map(move |ch| frame[ch.min(from - 1)]))
+            .collect(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DiffReport {
+    pub sample_rate: u32,        // Rate both were compared at (`a`'s)
+    pub a_samples: usize,        // Mono samples at `sample_rate`
+    pub b_samples: usize,
+    pub same_length: bool,
+    pub compared_samples: usize, // The overlap; the rest of the longer file is ignored
+    pub rms_a_dbfs: f32,
+    pub rms_b_dbfs: f32,
+    pub rms_difference_db: f32,  // b - a
+    pub peak_a_dbfs: f32,
+    pub peak_b_dbfs: f32,
+    pub peak_difference_db: f32, // b - a
+    pub residual_dbfs: f32,      // RMS of a - b: how much the processing changed
+    pub correlation: f32,        // Pearson, -1.0 to 1.0; 0.0 if either is silent
+}
+
+/// Measure how `b` differs from `a`, e.g. the same take with and without
+/// noise subtraction. Both are mixed to mono and `b` is resampled to `a`'s
+/// rate; only the overlapping start of the two is compared.
+pub fn compare_recordings(a: String, b: String) -> Result<DiffReport, String> {
+    let (mono_a, rate) = read_mono(&a)?;
+    let (mut mono_b, rate_b) = read_mono(&b)?;
+    if rate_b != rate {
+        mono_b = resample::resample_mono(&mono_b, rate_b, rate, &mut ResamplerState::default());
+    }
+
+    let overlap = mono_a.len().min(mono_b.len());
+    let (a_part, b_part) = (&mono_a[..overlap], &mono_b[..overlap]);
+    let residual: Vec<i16> = a_part.iter().zip(b_part).map(|(&x, &y)| x.saturating_sub(y)).collect();
+    let (rms_a, rms_b) = (dsp::linear_to_dbfs(dsp::frame_rms(a_part)), dsp::linear_to_dbfs(dsp::frame_rms(b_part)));
+    let (peak_a, peak_b) = (dsp::linear_to_dbfs(dsp::frame_peak(a_part)), dsp::linear_to_dbfs(dsp::frame_peak(b_part)));
+
+    Ok(DiffReport {
+        sample_rate: rate,
+        a_samples: mono_a.len(),
+        b_samples: mono_b.len(),
+        same_length: mono_a.len() == mono_b.len(),
+        compared_samples: overlap,
+        rms_a_dbfs: rms_a,
+        rms_b_dbfs: rms_b,
+        rms_difference_db: rms_b - rms_a,
+        peak_a_dbfs: peak_a,
+        peak_b_dbfs: peak_b,
+        peak_difference_db: peak_b - peak_a,
+        residual_dbfs: dsp::linear_to_dbfs(dsp::frame_rms(&residual)),
+        correlation: correlation(a_part, b_part),
+    })
+}
+
+// A file's mono mix and its rate
+fn read_mono(path: &str) -> Result<(Vec<i16>, u32), String> {
+    let mut reader = open_wav(path)?;
+    let spec = reader.spec();
+    let samples = reader.read_samples_i16()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok((samples.chunks_exact(spec.channels as usize).map(dsp::downmix).collect(), spec.sample_rate))
+}
+
+fn correlation(a: &[i16], b: &[i16]) -> f32 {
+    let n = a.len() as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    let mean_a = a.iter().map(|&x| x as f64).sum::<f64>() / n;
+    let mean_b = b.iter().map(|&x| x as f64).sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        let (dx, dy) = (x as f64 - mean_a, y as f64 - mean_b);
+        cov += dx * dy;
+        var_a += dx * dx;
+        var_b += dy * dy;
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    (cov / (var_a * var_b).sqrt()) as f32
+}
+
+/// A recording's metadata as Vorbis comments (`FIELD=value`) for the
+/// transcode to Opus or FLAC, which this build leaves to the backend: the
+/// `metadata` tags, `DURATION` in seconds and, when a manifest sits next to
+/// the file, `SESSION_ID`
+pub fn vorbis_comments(path: String) -> Result<Vec<String>, String> {
+    let reader = open_wav(&path)?;
+    let mut comments = reader.metadata().map(|m| m.vorbis_comments()).unwrap_or_default();
+    comments.push(format!("DURATION={:.3}", reader.duration_ms() as f64 / 1000.0));
+
+    let manifest_path = manifest::manifest_path(std::path::Path::new(&path));
+    let session_id = std::fs::read(&manifest_path)
+        .ok()
+        .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok())
+        .and_then(|manifest| manifest["session_id"].as_str().map(str::to_string));
+    if let Some(session_id) = session_id {
+        comments.push(format!("SESSION_ID={}", session_id));
+    }
+    Ok(comments)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SampleRateFix {
+    pub previous_rate: u32,
+    pub sample_rate: u32,
+    pub duration_ms: u64, // At the corrected rate
+}
+
+/// Relabel a WAV whose header rate doesn't match how it was recorded (it
+/// plays too fast or too slow). Only the `fmt ` rate fields change; the
+/// samples are not resampled.
+pub fn fix_sample_rate(path: String, correct_rate: u32) -> Result<SampleRateFix, String> {
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&correct_rate) {
+        return Err(format!(
+            "Implausible sample rate: {} Hz (expected {}-{} Hz)",
+            correct_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+        ));
+    }
+    let reader = WavReader::open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    if reader.container() != AudioContainer::Wav {
+        return Err(format!("Only WAV headers can be repaired: {}", path));
+    }
+    drop(reader);
+
+    let previous_rate = wav::rewrite_sample_rate(&path, correct_rate)
+        .map_err(|e| format!("Failed to rewrite {}: {}", path, e))?;
+    let duration_ms = WavReader::open(&path)
+        .map_err(|e| format!("Failed to reopen {}: {}", path, e))?
+        .duration_ms();
+    Ok(SampleRateFix { previous_rate, sample_rate: correct_rate, duration_ms })
+}
+
+// Data URLs are base64 (+33%) in one IPC string; past this a file should go
+// through the asset protocol instead
+const MAX_DATA_URL_BYTES: u64 = 10 * 1024 * 1024;
+
+/// The whole file as a `data:` URL for an `<audio>` element, with the MIME
+/// type picked from the extension. Files over 10 MB are refused.
+pub fn recording_data_url(path: String) -> Result<String, String> {
+    use base64::Engine;
+
+    let extension = std::path::Path::new(&path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let mime = match extension.as_str() {
+        "wav" => "audio/wav",
+        "aif" | "aiff" => "audio/aiff",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "flac" => "audio/flac",
+        "enc" => return Err(format!("{} is encrypted; decrypt it before previewing", path)),
+        _ => return Err(format!("Unsupported audio file type: {}", path)),
+    };
+    let size = std::fs::metadata(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .len();
+    if size > MAX_DATA_URL_BYTES {
+        return Err(format!(
+            "{} is too large for a data URL ({} bytes, limit {})",
+            path, size, MAX_DATA_URL_BYTES
+        ));
+    }
+    let bytes = std::fs::read(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(format!("data:{};base64,{}", mime, base64::engine::general_purpose::STANDARD.encode(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A path in the temp dir unique to this test run
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("dpc-tools-test-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn write_test_wav(path: &str, sample_rate: u32, channels: u16, samples: &[i16]) {
+        let mut writer = WavWriter::new(path, sample_rate, channels).unwrap();
+        writer.write_samples(samples).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn trim_then_fade_ends_smoothly() {
+        // 400ms of tone, then 300ms of silence to trim; both the buffered and
+        // the piecewise path must give the same smooth ending
+        let mut samples = dsp::tone(48000, 2, 440.0, 400, -6.0);
+        let tone_frames = samples.len() as u64 / 2;
+        samples.resize(samples.len() + 48000 * 2 * 3 / 10, 0);
+        let max_step = samples.windows(2).map(|w| (w[1] as i32 - w[0] as i32).abs()).max().unwrap();
+
+        let mut results = Vec::new();
+        for (name, budget) in [("buffered", None), ("in-place", Some(16 * 1024))] {
+            let path = temp_path(&format!("trim-fade-{}.wav", name));
+            write_test_wav(&path, 48000, 2, &samples);
+            let kept = trim_and_fade(&path, Some(0.001), 50, budget).unwrap();
+            let (_, _, _, out) = read_wav(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+
+            // Cut within one 20ms meter frame of where the tone stops
+            assert!(kept <= tone_frames && kept + 960 >= tone_frames, "{}: kept {} frames", name, kept);
+            assert_eq!(out.len() as u64, kept * 2);
+            assert_eq!(&out[out.len() - 2..], &[0, 0], "{}: last frame must be silent", name);
+            let step = out.windows(2).map(|w| (w[1] as i32 - w[0] as i32).abs()).max().unwrap();
+            assert!(step <= max_step, "{}: a step of {} is a click", name, step);
+            results.push(out);
+        }
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn extract_segment_copies_exact_frames() {
+        // Stereo ramp at 1 kHz: frame n is (n, -n), so boundaries are easy to read
+        let input = temp_path("segment-in.wav");
+        let output = temp_path("segment-out.wav");
+        let ramp: Vec<i16> = (0..3000i16).flat_map(|n| [n, -n]).collect();
+        write_test_wav(&input, 1000, 2, &ramp);
+
+        let result = extract_segment(input.clone(), output.clone(), 0.25, 1.5).unwrap();
+        let (spec, _, _, out) = read_wav(&output).unwrap();
+        assert_eq!((result.start_ms, result.end_ms), (250, 1500));
+        assert_eq!((spec.sample_rate, spec.channels), (1000, 2));
+        assert_eq!(out.len(), 1250 * 2);
+        assert_eq!(&out[..2], &[250, -250]);
+        assert_eq!(&out[out.len() - 2..], &[1499, -1499]);
+
+        // Past the end clamps to the file; an empty or inverted range is an error
+        let clamped = extract_segment(input.clone(), output.clone(), 2.5, 10.0).unwrap();
+        assert_eq!((clamped.start_ms, clamped.end_ms), (2500, 3000));
+        assert!(extract_segment(input.clone(), output.clone(), 1.0, 1.0).is_err());
+        assert!(extract_segment(input.clone(), output.clone(), 5.0, 6.0).is_err());
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn vorbis_comments_carry_the_recording_metadata() {
+        let path = temp_path("tagged.wav");
+        let metadata = WavMetadata {
+            artist: Some("alice".to_string()),
+            date: Some("2024-05-01".to_string()),
+            ..WavMetadata::default()
+        };
+        let mut writer = WavWriter::with_metadata(&path, 48000, 1, AudioContainer::Wav, Some(&metadata)).unwrap();
+        writer.write_samples(&[0; 72000]).unwrap();
+        writer.finish().unwrap();
+        let manifest = manifest::manifest_path(std::path::Path::new(&path));
+        std::fs::write(&manifest, br#"{"session_id": "session-1"}"#).unwrap();
+
+        let comments = vorbis_comments(path.clone()).unwrap();
+        assert_eq!(WavMetadata::from_vorbis_comments(&comments), metadata);
+        assert!(comments.contains(&"DURATION=1.500".to_string()), "{:?}", comments);
+        assert!(comments.contains(&"SESSION_ID=session-1".to_string()), "{:?}", comments);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&manifest);
+    }
+
+    #[test]
+    fn format_helpers_convert_through_transcode() {
+        let input = temp_path("helpers-in.wav");
+        let output = temp_path("helpers-out.wav");
+        // Stereo, left and right differing so the downmix shows
+        let stereo: Vec<i16> = (0..4410).flat_map(|_| [1000, 3000]).collect();
+        write_test_wav(&input, 44100, 2, &stereo);
+
+        let mono = downmix_to_mono(input.clone(), output.clone()).unwrap();
+        assert_eq!((mono.source_channels, mono.note), (2, None));
+        let mut reader = WavReader::open(&output).unwrap();
+        assert_eq!((reader.spec().channels, reader.spec().sample_rate), (1, 44100));
+        assert!(reader.read_samples().unwrap().iter().all(|&s| s == 2000));
+
+        let ensured = ensure_format(input.clone(), output.clone(), 48000, 1, 16).unwrap();
+        assert_eq!((ensured.resampled, ensured.remixed, ensured.requantized), (true, true, false));
+        let reader = WavReader::open(&output).unwrap();
+        assert_eq!((reader.spec().channels, reader.spec().sample_rate), (1, 48000));
+        assert!((4799..=4800).contains(&reader.frames()), "{}", reader.frames());
+
+        let requantized = requantize_wav(input.clone(), output.clone(), 24).unwrap();
+        assert_eq!((requantized.source_bits, requantized.dithered), (16, false));
+        let spec = WavReader::open(&output).unwrap().spec();
+        assert_eq!((spec.bits_per_sample, spec.channels, spec.sample_rate), (24, 2, 44100));
+        assert!(requantize_wav(output.clone(), input.clone(), 24).is_err());
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+}
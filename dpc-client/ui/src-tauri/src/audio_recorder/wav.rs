@@ -0,0 +1,1476 @@
+// WAV reading and writing shared by the recorder and the file utilities
+// The recorder only produces 16-bit PCM (`write_wav_f64` adds 24-bit and
+// float for `requantize_wav`); the reader accepts any RIFF/WAVE layout and
+// big-endian AIFF (FORM/COMM/SSND) for interop with macOS tooling
+//
+// RIFF sizes are 32-bit, so a WAV tops out at 4 GB (~6 hours of 48 kHz
+// stereo). Every WAV we write reserves a `JUNK` chunk right after the RIFF
+// header; if the file ends up larger than that, `finish` turns it into an
+// RF64 file (EBU Tech 3306): the JUNK becomes a `ds64` chunk holding the
+// 64-bit sizes and the 32-bit fields are set to 0xFFFFFFFF.
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use super::convert;
+use super::crypto::{EncryptedWriter, EncryptionKey};
+use super::mapped::MappedWriter;
+
+/// Container framing; the sample data is the same 16-bit PCM either way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioContainer {
+    #[default]
+    Wav,  // RIFF, little-endian
+    Aiff, // FORM, big-endian
+}
+
+impl AudioContainer {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioContainer::Wav => "wav",
+            AudioContainer::Aiff => "aif",
+        }
+    }
+
+    pub fn header_bytes(self) -> u64 {
+        match self {
+            AudioContainer::Wav => 80, // Including the reserved ds64 space
+            AudioContainer::Aiff => 54,
+        }
+    }
+}
+
+// INFO chunk ids and the Vorbis comment fields they map to
+const VORBIS_FIELDS: [(&[u8; 4], &str); 4] =
+    [(b"INAM", "TITLE"), (b"IART", "ARTIST"), (b"ICMT", "COMMENT"), (b"ICRD", "DATE")];
+
+/// Text tags for the `LIST`/`INFO` chunk. Empty fields are not written.
+/// 16-bit PCM needs no `fact` chunk (the spec only requires it for
+/// compressed formats), so none is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WavMetadata {
+    pub title: Option<String>,   // INAM
+    pub artist: Option<String>,  // IART, e.g. the sender
+    pub comment: Option<String>, // ICMT
+    pub date: Option<String>,    // ICRD, e.g. "2024-05-01"
+}
+
+impl WavMetadata {
+    fn tags(&self) -> impl Iterator<Item = (&'static [u8; 4], &str)> {
+        [
+            (b"INAM", &self.title),
+            (b"IART", &self.artist),
+            (b"ICMT", &self.comment),
+            (b"ICRD", &self.date),
+        ]
+        .into_iter()
+        .filter_map(|(id, value)| value.as_deref().filter(|v| !v.is_empty()).map(|v| (id, v)))
+    }
+
+    fn set(&mut self, id: &[u8; 4], value: String) {
+        let field = match id {
+            b"INAM" => &mut self.title,
+            b"IART" => &mut self.artist,
+            b"ICMT" => &mut self.comment,
+            b"ICRD" => &mut self.date,
+            _ => return,
+        };
+        *field = Some(value);
+    }
+
+    /// The same tags as Vorbis comments (`FIELD=value`), for Ogg/FLAC output
+    pub fn vorbis_comments(&self) -> Vec<String> {
+        self.tags()
+            .filter_map(|(id, value)| {
+                let (_, field) = VORBIS_FIELDS.iter().find(|(tag, _)| *tag == id)?;
+                Some(format!("{}={}", field, value))
+            })
+            .collect()
+    }
+
+    /// Tags back from Vorbis comments; field names are case-insensitive and
+    /// fields without an INFO equivalent (e.g. `DURATION`) are skipped.
+    /// Only the tests decode comments so far.
+    #[cfg(test)]
+    pub fn from_vorbis_comments<S: AsRef<str>>(comments: &[S]) -> Self {
+        let mut metadata = Self::default();
+        for comment in comments {
+            let Some((field, value)) = comment.as_ref().split_once('=') else {
+                continue;
+            };
+            if let Some((id, _)) = VORBIS_FIELDS.iter().find(|(_, name)| name.eq_ignore_ascii_case(field)) {
+                metadata.set(id, value.to_string());
+            }
+        }
+        metadata
+    }
+
+    /// Bytes the `LIST` chunk adds to a WAV file (0 when there are no tags)
+    pub fn chunk_bytes(&self) -> u64 {
+        // Each value is NUL-terminated and padded to an even length
+        let body: u64 = self.tags().map(|(_, v)| 8 + ((v.len() as u64 + 2) & !1)).sum();
+        if body == 0 { 0 } else { 12 + body }
+    }
+}
+
+// Where the writer's bytes go
+enum Sink {
+    File(BufWriter<std::fs::File>),
+    Mapped(MappedWriter),
+    Encrypted(EncryptedWriter),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::File(file) => file.write(buf),
+            Sink::Mapped(file) => file.write(buf),
+            Sink::Encrypted(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::File(file) => file.flush(),
+            Sink::Mapped(file) => file.flush(),
+            Sink::Encrypted(file) => file.flush(),
+        }
+    }
+}
+
+// Body of a `ds64` chunk: RIFF size, data size, sample count (u64 each) and
+// an empty table length (u32)
+const DS64_BODY_BYTES: u32 = 28;
+const DS64_OFFSET: u64 = 12; // Where the reserved JUNK/ds64 chunk starts
+
+// AIFF has no 64-bit variant; FORM size = SSND data + 46
+const MAX_AIFF_DATA_BYTES: u64 = u32::MAX as u64 - 46;
+
+// WAV/AIFF file writer (simple 16-bit PCM format)
+pub struct WavWriter {
+    file: Sink,
+    data_size: u64,
+    container: AudioContainer,
+    block_align: u16, // Bytes per frame
+    header_len: u64,  // Bytes before the sample data; grows with metadata
+}
+
+impl WavWriter {
+    pub fn new(path: &str, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        Self::with_container(path, sample_rate, channels, AudioContainer::Wav)
+    }
+
+    pub fn with_container(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        container: AudioContainer,
+    ) -> io::Result<Self> {
+        Self::with_metadata(path, sample_rate, channels, container, None)
+    }
+
+    /// Like `with_container`, adding a `LIST`/`INFO` chunk ahead of the
+    /// data. Tags are WAV-only; AIFF has no INFO equivalent and rejects them.
+    pub fn with_metadata(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        container: AudioContainer,
+        metadata: Option<&WavMetadata>,
+    ) -> io::Result<Self> {
+        let metadata = metadata.filter(|m| m.chunk_bytes() > 0);
+        if metadata.is_some() && container == AudioContainer::Aiff {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "AIFF files cannot carry metadata tags"));
+        }
+        frame_fields(sample_rate, channels, 2)?;
+        let file = std::fs::File::create(path)?;
+        let file = Sink::File(BufWriter::with_capacity(64 * 1024, file));
+        Self::start(file, sample_rate, channels, container, metadata, (1, 16))
+    }
+
+    /// Like `with_metadata`, writing through a memory map of a file
+    /// preallocated to `capacity` bytes (see `mapped`)
+    pub fn mapped(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        container: AudioContainer,
+        metadata: Option<&WavMetadata>,
+        capacity: u64,
+    ) -> io::Result<Self> {
+        let metadata = metadata.filter(|m| m.chunk_bytes() > 0);
+        if metadata.is_some() && container == AudioContainer::Aiff {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "AIFF files cannot carry metadata tags"));
+        }
+        frame_fields(sample_rate, channels, 2)?;
+        let file = Sink::Mapped(MappedWriter::create(path, capacity)?);
+        Self::start(file, sample_rate, channels, container, metadata, (1, 16))
+    }
+
+    /// A WAV sealed chunk by chunk (see `crypto`); no plaintext reaches disk
+    pub fn encrypted(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        metadata: Option<&WavMetadata>,
+        key: &EncryptionKey,
+    ) -> io::Result<Self> {
+        frame_fields(sample_rate, channels, 2)?;
+        let file = Sink::Encrypted(EncryptedWriter::create(path, key)?);
+        Self::start(file, sample_rate, channels, AudioContainer::Wav, metadata.filter(|m| m.chunk_bytes() > 0), (1, 16))
+    }
+
+    /// A WAV for samples in some other encoding, added as bytes with
+    /// `write_bytes`: `format_tag` 1 (integer PCM) or 3 (IEEE float) and
+    /// `bits` per sample. Nothing is converted, so the bytes must already be
+    /// little-endian in that format.
+    pub fn native(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        format: (u16, u16),
+        metadata: Option<&WavMetadata>,
+    ) -> io::Result<Self> {
+        let (format_tag, bits) = format;
+        if !matches!(format_tag, 1 | 3) || bits == 0 || bits % 8 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported WAV format: tag {}, {}-bit", format_tag, bits),
+            ));
+        }
+        frame_fields(sample_rate, channels, bits / 8)?;
+        let file = std::fs::File::create(path)?;
+        let file = Sink::File(BufWriter::with_capacity(64 * 1024, file));
+        Self::start(file, sample_rate, channels, AudioContainer::Wav, metadata.filter(|m| m.chunk_bytes() > 0), format)
+    }
+
+    // Write the header for `container`; `format` is the WAV format tag and
+    // bits per sample (AIFF is always 16-bit PCM)
+    fn start(
+        mut file: Sink,
+        sample_rate: u32,
+        channels: u16,
+        container: AudioContainer,
+        metadata: Option<&WavMetadata>,
+        format: (u16, u16),
+    ) -> io::Result<Self> {
+        let header_len = container.header_bytes() + metadata.map_or(0, WavMetadata::chunk_bytes);
+        if container == AudioContainer::Aiff {
+            write_aiff_header(&mut file, sample_rate, channels)?;
+            return Ok(Self { file, data_size: 0, container, block_align: channels * 2, header_len });
+        }
+        let (format_tag, bits) = format;
+
+        // Write RIFF header
+        file.write_all(b"RIFF")?;
+        // File size - 8 (will be updated on finish)
+        file.write_u32::<LittleEndian>(0)?;
+        // WAVE format
+        file.write_all(b"WAVE")?;
+
+        // Space for a ds64 chunk, in case the file outgrows 32-bit sizes
+        file.write_all(b"JUNK")?;
+        file.write_u32::<LittleEndian>(DS64_BODY_BYTES)?;
+        file.write_all(&[0; DS64_BODY_BYTES as usize])?;
+
+        // fmt chunk
+        file.write_all(b"fmt ")?;
+        // Chunk size (16 for PCM)
+        file.write_u32::<LittleEndian>(16)?;
+        // Audio format (1 = PCM, 3 = IEEE float)
+        file.write_u16::<LittleEndian>(format_tag)?;
+        // Channels
+        file.write_u16::<LittleEndian>(channels)?;
+        // Sample rate
+        file.write_u32::<LittleEndian>(sample_rate)?;
+        // Byte rate (sample_rate * channels * bits_per_sample / 8) and block
+        // align (channels * bits_per_sample / 8)
+        let (byte_rate, block_align) = frame_fields(sample_rate, channels, bits / 8)?;
+        file.write_u32::<LittleEndian>(byte_rate)?;
+        file.write_u16::<LittleEndian>(block_align)?;
+        // Bits per sample (16 unless `native`)
+        file.write_u16::<LittleEndian>(bits)?;
+
+        if let Some(metadata) = metadata {
+            write_info_chunk(&mut file, metadata)?;
+        }
+
+        // data chunk
+        file.write_all(b"data")?;
+        // Data size (will be updated on finish)
+        file.write_u32::<LittleEndian>(0)?;
+
+        Ok(Self {
+            file,
+            data_size: 0,
+            container,
+            block_align,
+            header_len,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes = samples.len() as u64 * 2; // 2 bytes per sample
+        if self.container == AudioContainer::Aiff && self.data_size + bytes > MAX_AIFF_DATA_BYTES {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "AIFF files are limited to 4 GB"));
+        }
+        for &sample in samples {
+            match self.container {
+                AudioContainer::Wav => self.file.write_i16::<LittleEndian>(sample)?,
+                AudioContainer::Aiff => self.file.write_i16::<BigEndian>(sample)?,
+            }
+        }
+        self.data_size += bytes;
+        Ok(())
+    }
+
+    /// Add samples already encoded in the writer's format, for `native`
+    /// writers. Only whole frames keep the file playable.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.container == AudioContainer::Aiff {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "AIFF files take 16-bit samples only"));
+        }
+        self.file.write_all(bytes)?;
+        self.data_size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Push buffered samples to disk. Plain files also get their header
+    /// sizes brought up to date, like `flush_partial`; encrypted ones keep
+    /// zero sizes until `finish`.
+    pub fn sync(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Sink::File(_) | Sink::Mapped(_) => self.flush_partial(true).map(|_| ()),
+            Sink::Encrypted(file) => file.sync(),
+        }
+    }
+
+    /// Make the file on disk a playable copy of everything written so far:
+    /// flush buffered samples and write the current sizes into the header,
+    /// leaving the writer ready to continue. Returns the file's length.
+    pub fn flush_partial(&mut self, fsync: bool) -> io::Result<u64> {
+        let end = self.header_len + self.data_size;
+        match &mut self.file {
+            Sink::File(file) => {
+                file.flush()?;
+                let file = file.get_mut();
+                write_header_sizes(file, self.container, self.header_len, self.data_size, self.block_align)?;
+                file.seek(SeekFrom::Start(end))?;
+                if fsync {
+                    file.sync_data()?;
+                }
+            }
+            Sink::Mapped(file) => {
+                write_header_sizes(file, self.container, self.header_len, self.data_size, self.block_align)?;
+                file.seek(SeekFrom::Start(end))?;
+                if fsync {
+                    file.sync()?;
+                }
+            }
+            Sink::Encrypted(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Encrypted recordings can't be read before they finish",
+                ));
+            }
+        }
+        Ok(end)
+    }
+
+    /// Drop everything after the first `samples` already written
+    pub fn truncate(&mut self, samples: usize) -> io::Result<()> {
+        let data_size = (samples as u64 * 2).min(self.data_size);
+        let end = self.header_len + data_size;
+        let file = match &mut self.file {
+            Sink::File(file) => {
+                file.flush()?;
+                file.get_mut()
+            }
+            Sink::Mapped(file) => {
+                file.truncate(end)?;
+                self.data_size = data_size;
+                return Ok(());
+            }
+            Sink::Encrypted(file) => {
+                file.truncate(end)?;
+                self.data_size = data_size;
+                return Ok(());
+            }
+        };
+        file.set_len(end)?;
+        file.seek(SeekFrom::Start(end))?;
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        // Encrypted output keeps zero sizes; they're restored on decrypt
+        let file = match self.file {
+            Sink::File(file) => file,
+            Sink::Mapped(mut file) => {
+                write_header_sizes(&mut file, self.container, self.header_len, self.data_size, self.block_align)?;
+                return file.finish();
+            }
+            Sink::Encrypted(file) => return file.finish(),
+        };
+        finish_buffered(file, self.container, (self.header_len, self.data_size, self.block_align))
+    }
+}
+
+// What recovering from a failed flush needs from the file under the buffer
+trait RecoverableFile: Write + Seek {
+    fn file_len(&self) -> io::Result<u64>;
+    fn set_file_len(&mut self, len: u64) -> io::Result<()>;
+    fn sync_file(&self) -> io::Result<()>;
+}
+
+impl RecoverableFile for std::fs::File {
+    fn file_len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_file_len(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)
+    }
+
+    fn sync_file(&self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+// Flush the last samples and fill in the header sizes. `sizes` is the
+// writer's header length, data size and block align.
+fn finish_buffered<F: RecoverableFile>(
+    file: BufWriter<F>,
+    container: AudioContainer,
+    sizes: (u64, u64, u16),
+) -> io::Result<()> {
+    let mut file = match file.into_inner() {
+        Ok(file) => file,
+        Err(e) => {
+            let (error, writer) = e.into_parts();
+            return Err(close_after_failed_flush(writer.into_parts().0, container, sizes, error));
+        }
+    };
+    let (header_len, data_size, block_align) = sizes;
+    write_header_sizes(&mut file, container, header_len, data_size, block_align)?;
+    file.flush()?;
+    file.sync_file()
+}
+
+// `finish` couldn't write the last buffered samples (e.g. disk full). What
+// already reached the disk is still a valid recording once the header
+// matches it, so cut the file to whole frames and size the header to that.
+fn close_after_failed_flush<F: RecoverableFile>(
+    mut file: F,
+    container: AudioContainer,
+    sizes: (u64, u64, u16),
+    error: io::Error,
+) -> io::Error {
+    let (header_len, data_size, block_align) = sizes;
+    let on_disk = file.file_len().unwrap_or(0);
+    let frame = block_align.max(1) as u64;
+    let kept = on_disk.saturating_sub(header_len).min(data_size) / frame * frame;
+    // The header itself sits in the first buffer; without it there's nothing to fix
+    let recovered = if on_disk < header_len {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the header never reached the disk"))
+    } else {
+        file.set_file_len(header_len + kept)
+            .and_then(|_| write_header_sizes(&mut file, container, header_len, kept, block_align))
+            .and_then(|_| file.sync_file())
+    };
+    let outcome = match recovered {
+        Ok(()) => format!("the file was closed with {} of {} bytes of audio", kept, data_size),
+        Err(e) => format!("the file could not be repaired ({})", e),
+    };
+    io::Error::new(error.kind(), format!("Failed to write buffered audio ({}); {}", error, outcome))
+}
+
+// Byte rate and block align for the `fmt ` chunk, or an error when the
+// format is too large for their 32/16-bit fields (checked before any file
+// is created, so a bad format never leaves a corrupt header behind)
+fn frame_fields(sample_rate: u32, channels: u16, bytes_per_sample: u16) -> io::Result<(u32, u16)> {
+    channels
+        .checked_mul(bytes_per_sample)
+        .and_then(|block_align| Some((sample_rate.checked_mul(block_align as u32)?, block_align)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} Hz with {} channels doesn't fit a WAV header", sample_rate, channels),
+            )
+        })
+}
+
+// Write the sizes for `data_size` bytes of samples into either header
+fn write_header_sizes<W: Write + Seek>(
+    file: &mut W,
+    container: AudioContainer,
+    header_len: u64,
+    data_size: u64,
+    block_align: u16,
+) -> io::Result<()> {
+    if container == AudioContainer::Aiff {
+        // FORM size, COMM frame count and SSND size; bounded by `write_samples`
+        let data_size = data_size as u32;
+        file.seek(SeekFrom::Start(4))?;
+        file.write_u32::<BigEndian>(data_size + 46)?;
+        file.seek(SeekFrom::Start(22))?;
+        file.write_u32::<BigEndian>(data_size / (block_align as u32).max(1))?;
+        file.seek(SeekFrom::Start(42))?;
+        return file.write_u32::<BigEndian>(data_size + 8);
+    }
+
+    // Data chunk size sits just before the samples (after "data" marker)
+    write_wav_sizes(file, header_len - 4, data_size, block_align, true)
+}
+
+/// Fill in the RIFF and data chunk sizes of a WAV, as RF64 when they don't
+/// fit 32 bits. `data_size_at` is the offset of the data chunk's size field.
+/// `ds64_reserved` says the chunk at offset 12 is this writer's JUNK
+/// reserve (or the ds64 it became), which is required for RF64 and turned
+/// back into JUNK when a trimmed file fits a plain RIFF header again.
+pub(super) fn write_wav_sizes<W: Write + Seek>(
+    file: &mut W,
+    data_size_at: u64,
+    data_size: u64,
+    block_align: u16,
+    ds64_reserved: bool,
+) -> io::Result<()> {
+    // File size excludes the "RIFF" id and itself
+    let riff_size = data_size_at + 4 + data_size - 8;
+    if riff_size <= u32::MAX as u64 {
+        if ds64_reserved {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(b"RIFF")?;
+            file.seek(SeekFrom::Start(DS64_OFFSET))?;
+            file.write_all(b"JUNK")?;
+            file.write_u32::<LittleEndian>(DS64_BODY_BYTES)?;
+            file.write_all(&[0; DS64_BODY_BYTES as usize])?;
+        }
+        file.seek(SeekFrom::Start(data_size_at))?;
+        file.write_u32::<LittleEndian>(data_size as u32)?;
+        file.seek(SeekFrom::Start(4))?;
+        return file.write_u32::<LittleEndian>(riff_size as u32);
+    }
+    if !ds64_reserved {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Audio too large for a RIFF header and the file has no room for a ds64 chunk",
+        ));
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(b"RF64")?;
+    file.write_u32::<LittleEndian>(u32::MAX)?;
+    file.seek(SeekFrom::Start(DS64_OFFSET))?;
+    file.write_all(b"ds64")?;
+    file.write_u32::<LittleEndian>(DS64_BODY_BYTES)?;
+    file.write_u64::<LittleEndian>(riff_size)?;
+    file.write_u64::<LittleEndian>(data_size)?;
+    file.write_u64::<LittleEndian>(data_size / block_align.max(1) as u64)?;
+    file.write_u32::<LittleEndian>(0)?; // No table entries
+    file.seek(SeekFrom::Start(data_size_at))?;
+    file.write_u32::<LittleEndian>(u32::MAX)
+}
+
+fn write_info_chunk<W: Write>(file: &mut W, metadata: &WavMetadata) -> io::Result<()> {
+    file.write_all(b"LIST")?;
+    file.write_u32::<LittleEndian>((metadata.chunk_bytes() - 8) as u32)?;
+    file.write_all(b"INFO")?;
+    for (id, value) in metadata.tags() {
+        let len = value.len() as u32 + 1; // Including the NUL
+        file.write_all(id)?;
+        file.write_u32::<LittleEndian>(len)?;
+        file.write_all(value.as_bytes())?;
+        file.write_all(if len % 2 == 1 { &[0, 0] } else { &[0] })?;
+    }
+    Ok(())
+}
+
+// Tags from the body of a `LIST` chunk; `None` for non-INFO lists
+fn parse_info_chunk(body: &[u8]) -> Option<WavMetadata> {
+    if body.get(..4)? != b"INFO" {
+        return None;
+    }
+    let mut metadata = WavMetadata::default();
+    let mut pos = 4;
+    while pos + 8 <= body.len() {
+        let id: [u8; 4] = body[pos..pos + 4].try_into().unwrap();
+        let size = u32::from_le_bytes(body[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let start = pos + 8;
+        let end = start.saturating_add(size).min(body.len());
+        let value = String::from_utf8_lossy(&body[start..end]).trim_end_matches('\0').to_string();
+        metadata.set(&id, value);
+        pos = start.saturating_add(size).saturating_add(size % 2);
+    }
+    Some(metadata)
+}
+
+fn write_aiff_header<W: Write>(file: &mut W, sample_rate: u32, channels: u16) -> io::Result<()> {
+    file.write_all(b"FORM")?;
+    file.write_u32::<BigEndian>(0)?; // Updated on finish
+    file.write_all(b"AIFF")?;
+
+    file.write_all(b"COMM")?;
+    file.write_u32::<BigEndian>(18)?;
+    file.write_u16::<BigEndian>(channels)?;
+    file.write_u32::<BigEndian>(0)?; // Sample frames, updated on finish
+    file.write_u16::<BigEndian>(16)?;
+    file.write_all(&rate_to_extended(sample_rate))?;
+
+    file.write_all(b"SSND")?;
+    file.write_u32::<BigEndian>(0)?; // Updated on finish
+    file.write_u32::<BigEndian>(0)?; // Offset
+    file.write_u32::<BigEndian>(0)?; // Block size
+    Ok(())
+}
+
+// Bytes a chunk of `size` takes up, including its pad byte (widened first:
+// a hostile u32::MAX size must not wrap to 0)
+fn padded(size: u32) -> i64 {
+    ((size as u64 + 1) & !1) as i64
+}
+
+// AIFF stores the sample rate as an 80-bit IEEE extended float
+fn rate_to_extended(rate: u32) -> [u8; 10] {
+    let mut out = [0u8; 10];
+    if rate == 0 {
+        return out;
+    }
+    let msb = 31 - rate.leading_zeros();
+    let exponent = 16383 + msb as u16;
+    let mantissa = (rate as u64) << (63 - msb);
+    out[..2].copy_from_slice(&exponent.to_be_bytes());
+    out[2..].copy_from_slice(&mantissa.to_be_bytes());
+    out
+}
+
+fn extended_to_rate(bytes: &[u8; 10]) -> u32 {
+    let exponent = u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff;
+    let mantissa = u64::from_be_bytes(bytes[2..].try_into().unwrap());
+    let shift = exponent as i32 - 16383;
+    if !(0..32).contains(&shift) {
+        return 0;
+    }
+    (mantissa >> (63 - shift)) as u32
+}
+
+/// Sample encodings `write_wav_f64` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmEncoding {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl PcmEncoding {
+    /// 16 and 24 are integer PCM, 32 is IEEE float
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        match bits {
+            16 => Some(PcmEncoding::Int16),
+            24 => Some(PcmEncoding::Int24),
+            32 => Some(PcmEncoding::Float32),
+            _ => None,
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        match self {
+            PcmEncoding::Int16 => 16,
+            PcmEncoding::Int24 => 24,
+            PcmEncoding::Float32 => 32,
+        }
+    }
+
+    pub fn format_tag(self) -> u16 {
+        match self {
+            PcmEncoding::Float32 => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// Write a whole WAV at once from -1.0..1.0 samples. Integer encodings
+/// round to the nearest step and clamp, so callers that want dither add it
+/// first. Float files get the `fact` chunk the spec requires for them.
+pub fn write_wav_f64(
+    path: &str,
+    sample_rate: u32,
+    channels: u16,
+    encoding: PcmEncoding,
+    samples: &[f64],
+) -> io::Result<()> {
+    let width = encoding.bits() as u64 / 8;
+    let (fmt_size, fact_bytes) = match encoding {
+        PcmEncoding::Float32 => (18u32, 12u64), // fmt + cbSize, then fact
+        _ => (16, 0),
+    };
+    let (byte_rate, block_align) = frame_fields(sample_rate, channels, width as u16)?;
+    let data_size = samples.len() as u64 * width;
+    let riff_size = 4 + (8 + fmt_size as u64) + fact_bytes + 8 + data_size + data_size % 2;
+    if riff_size > u32::MAX as u64 {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, "WAV files are limited to 4 GB"));
+    }
+
+    let mut file = BufWriter::with_capacity(64 * 1024, std::fs::File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_u32::<LittleEndian>(riff_size as u32)?;
+    file.write_all(b"WAVE")?;
+    file.write_all(b"fmt ")?;
+    file.write_u32::<LittleEndian>(fmt_size)?;
+    file.write_u16::<LittleEndian>(encoding.format_tag())?;
+    file.write_u16::<LittleEndian>(channels)?;
+    file.write_u32::<LittleEndian>(sample_rate)?;
+    file.write_u32::<LittleEndian>(byte_rate)?;
+    file.write_u16::<LittleEndian>(block_align)?;
+    file.write_u16::<LittleEndian>(encoding.bits())?;
+    if fact_bytes > 0 {
+        file.write_u16::<LittleEndian>(0)?; // No extension bytes
+        file.write_all(b"fact")?;
+        file.write_u32::<LittleEndian>(4)?;
+        file.write_u32::<LittleEndian>((samples.len() / channels.max(1) as usize) as u32)?;
+    }
+    file.write_all(b"data")?;
+    file.write_u32::<LittleEndian>(data_size as u32)?;
+
+    for &sample in samples {
+        let sample = if sample.is_nan() { 0.0 } else { sample };
+        match encoding {
+            PcmEncoding::Int16 => file.write_i16::<LittleEndian>(convert::f64_to_i16(sample))?,
+            PcmEncoding::Int24 => {
+                let value = (sample * 8_388_608.0).round().clamp(-8_388_608.0, 8_388_607.0) as i32;
+                file.write_i24::<LittleEndian>(value)?;
+            }
+            PcmEncoding::Float32 => file.write_f32::<LittleEndian>(sample.clamp(-1.0, 1.0) as f32)?,
+        }
+    }
+    if data_size % 2 == 1 {
+        file.write_u8(0)?;
+    }
+    file.flush()?;
+    file.get_ref().sync_data()
+}
+
+/// Format fields from a WAV `fmt ` chunk (or AIFF `COMM`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub format_tag: u16,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+// WAV file reader: walks the RIFF chunks to find `fmt ` and `data`
+// (or the AIFF chunks to find `COMM` and `SSND`)
+pub struct WavReader<R = std::fs::File> {
+    file: BufReader<R>,
+    spec: WavSpec,
+    data_size: u64,
+    data_offset: u64, // Where the samples start
+    container: AudioContainer,
+    metadata: Option<WavMetadata>,
+}
+
+impl WavReader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+        Self::from_reader(file, file_len)
+    }
+}
+
+impl WavReader<io::Cursor<Vec<u8>>> {
+    /// Parse a WAV/AIFF held in memory, e.g. a decrypted recording
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        let len = bytes.len() as u64;
+        Self::from_reader(io::Cursor::new(bytes), len)
+    }
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    /// `file_len` bounds the data chunk of unfinalized files
+    pub fn from_reader(reader: R, file_len: u64) -> io::Result<Self> {
+        let mut file = BufReader::new(reader);
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic == b"FORM" {
+            return Self::open_aiff(file, file_len);
+        }
+        if &magic != b"RIFF" && &magic != b"RF64" {
+            return Err(invalid_data("Missing RIFF header"));
+        }
+        file.read_u32::<LittleEndian>()?;
+        file.read_exact(&mut magic)?;
+        if &magic != b"WAVE" {
+            return Err(invalid_data("Not a WAVE file"));
+        }
+
+        let mut spec = None;
+        let mut metadata = None;
+        let mut ds64_data_size = None;
+        loop {
+            let mut id = [0u8; 4];
+            file.read_exact(&mut id)
+                .map_err(|_| invalid_data("No data chunk found"))?;
+            let size = file.read_u32::<LittleEndian>()?;
+
+            match &id {
+                b"fmt " => {
+                    if size < 16 {
+                        return Err(invalid_data("fmt chunk is too small"));
+                    }
+                    let format_tag = file.read_u16::<LittleEndian>()?;
+                    let channels = file.read_u16::<LittleEndian>()?;
+                    let sample_rate = file.read_u32::<LittleEndian>()?;
+                    file.read_u32::<LittleEndian>()?; // Byte rate
+                    file.read_u16::<LittleEndian>()?; // Block align
+                    let bits_per_sample = file.read_u16::<LittleEndian>()?;
+                    // Skip any extension bytes (chunks are padded to even sizes)
+                    file.seek_relative(padded(size) - 16)?;
+                    spec = Some(WavSpec { format_tag, sample_rate, channels, bits_per_sample });
+                }
+                b"ds64" if size >= 16 => {
+                    file.read_u64::<LittleEndian>()?; // RIFF size
+                    ds64_data_size = Some(file.read_u64::<LittleEndian>()?);
+                    file.seek_relative(padded(size) - 16)?;
+                }
+                b"data" => {
+                    let spec = spec.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+                    let size = match ds64_data_size {
+                        Some(ds64) if size == u32::MAX => ds64,
+                        _ => size as u64,
+                    };
+                    // A recording that was never finalized has a zero or oversized
+                    // length; trust the bytes that are actually on disk instead
+                    let data_offset = file.stream_position()?;
+                    let remaining = file_len.saturating_sub(data_offset);
+                    let data_size = if size == 0 || size > remaining { remaining } else { size };
+                    return Ok(Self { file, spec, data_size, data_offset, container: AudioContainer::Wav, metadata });
+                }
+                b"LIST" => {
+                    let mut body = Vec::new();
+                    (&mut file).take(size as u64).read_to_end(&mut body)?;
+                    if size % 2 == 1 {
+                        file.seek_relative(1)?;
+                    }
+                    metadata = parse_info_chunk(&body).or(metadata);
+                }
+                _ => {
+                    file.seek_relative(padded(size))?;
+                }
+            }
+        }
+    }
+
+    fn open_aiff(mut file: BufReader<R>, file_len: u64) -> io::Result<Self> {
+        file.read_u32::<BigEndian>()?;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != b"AIFF" {
+            return Err(invalid_data("Not an AIFF file"));
+        }
+
+        let mut spec = None;
+        loop {
+            let mut id = [0u8; 4];
+            file.read_exact(&mut id)
+                .map_err(|_| invalid_data("No SSND chunk found"))?;
+            let size = file.read_u32::<BigEndian>()?;
+
+            match &id {
+                b"COMM" => {
+                    if size < 18 {
+                        return Err(invalid_data("COMM chunk is too small"));
+                    }
+                    let channels = file.read_u16::<BigEndian>()?;
+                    file.read_u32::<BigEndian>()?; // Sample frames
+                    let bits_per_sample = file.read_u16::<BigEndian>()?;
+                    let mut rate = [0u8; 10];
+                    file.read_exact(&mut rate)?;
+                    file.seek_relative(padded(size) - 18)?;
+                    // Plain AIFF is always integer PCM
+                    spec = Some(WavSpec {
+                        format_tag: 1,
+                        sample_rate: extended_to_rate(&rate),
+                        channels,
+                        bits_per_sample,
+                    });
+                }
+                b"SSND" => {
+                    let spec = spec.ok_or_else(|| invalid_data("SSND chunk before COMM chunk"))?;
+                    let offset = file.read_u32::<BigEndian>()?;
+                    file.read_u32::<BigEndian>()?; // Block size
+                    file.seek_relative(offset as i64)?;
+                    let claimed = size.saturating_sub(8 + offset);
+                    let data_offset = file.stream_position()?;
+                    let remaining = file_len.saturating_sub(data_offset);
+                    let data_size = if claimed == 0 || claimed as u64 > remaining {
+                        remaining.min(u32::MAX as u64)
+                    } else {
+                        claimed as u64
+                    };
+                    let container = AudioContainer::Aiff;
+                    return Ok(Self { file, spec, data_size, data_offset, container, metadata: None });
+                }
+                _ => {
+                    file.seek_relative(padded(size))?;
+                }
+            }
+        }
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    pub fn container(&self) -> AudioContainer {
+        self.container
+    }
+
+    /// Tags from a `LIST`/`INFO` chunk ahead of the data, if any
+    pub fn metadata(&self) -> Option<&WavMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Length of the audio, from the data chunk size
+    pub fn duration_ms(&self) -> u64 {
+        let frame_bytes = self.spec.channels as u64 * (self.spec.bits_per_sample as u64 / 8);
+        if frame_bytes == 0 || self.spec.sample_rate == 0 {
+            return 0;
+        }
+        self.data_size / frame_bytes * 1000 / self.spec.sample_rate as u64
+    }
+
+    /// Bytes in the data chunk
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    /// Whole frames in the data chunk
+    pub fn frames(&self) -> u64 {
+        let frame_bytes = self.spec.channels as u64 * (self.spec.bits_per_sample as u64 / 8);
+        self.data_size / frame_bytes.max(1)
+    }
+
+    /// Up to `frames` 16-bit frames starting at `first_frame`, for working
+    /// through a long file a piece at a time; fewer at the end of the data
+    pub fn read_frames_at(&mut self, first_frame: u64, frames: u64) -> io::Result<Vec<i16>> {
+        self.check_16_bit()?;
+        let channels = self.spec.channels as u64;
+        let frames = frames.min(self.frames().saturating_sub(first_frame));
+        self.file.seek(SeekFrom::Start(self.data_offset + first_frame * channels * 2))?;
+        let mut samples = vec![0i16; (frames * channels) as usize];
+        match self.container {
+            AudioContainer::Wav => self.file.read_i16_into::<LittleEndian>(&mut samples)?,
+            AudioContainer::Aiff => self.file.read_i16_into::<BigEndian>(&mut samples)?,
+        }
+        Ok(samples)
+    }
+
+    fn check_16_bit(&self) -> io::Result<()> {
+        if self.spec.format_tag != 1 || self.spec.bits_per_sample != 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported WAV encoding (format {}, {}-bit)",
+                    self.spec.format_tag, self.spec.bits_per_sample),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read the whole data chunk as interleaved 16-bit samples
+    pub fn read_samples(&mut self) -> io::Result<Vec<i16>> {
+        self.check_16_bit()?;
+        let mut samples = vec![0i16; self.data_size as usize / 2];
+        match self.container {
+            AudioContainer::Wav => self.file.read_i16_into::<LittleEndian>(&mut samples)?,
+            AudioContainer::Aiff => self.file.read_i16_into::<BigEndian>(&mut samples)?,
+        }
+        Ok(samples)
+    }
+
+    /// Read the data chunk as 16-bit samples whatever its depth: 8/24/32-bit
+    /// integer PCM is rescaled and 32/64-bit float is clamped and scaled
+    pub fn read_samples_i16(&mut self) -> io::Result<Vec<i16>> {
+        let spec = self.spec;
+        if spec.format_tag == 1 && spec.bits_per_sample == 16 {
+            return self.read_samples();
+        }
+        let (bytes, width) = self.read_raw_le()?;
+        let big_endian = self.container == AudioContainer::Aiff;
+        let samples = bytes.chunks_exact(width).map(|le| {
+            match (spec.format_tag, width) {
+                // WAV 8-bit is unsigned; AIFF 8-bit is signed
+                (1, 1) if big_endian => convert::i8_to_i16(le[0] as i8),
+                (1, 1) => convert::u8_to_i16(le[0]),
+                (1, 3) => convert::i24_to_i16(i32::from_le_bytes([0, le[0], le[1], le[2]]) >> 8),
+                (1, _) => convert::i32_to_i16(i32::from_le_bytes([le[0], le[1], le[2], le[3]])),
+                (_, 4) => convert::f32_to_i16(f32::from_le_bytes([le[0], le[1], le[2], le[3]])),
+                _ => convert::f64_to_i16(f64::from_le_bytes(le.try_into().unwrap())),
+            }
+        });
+        Ok(samples.collect())
+    }
+
+    /// Read the data chunk at full precision as -1.0..1.0 (integers on the
+    /// same symmetric scale as `convert`; floats as stored, unclamped)
+    pub fn read_samples_f64(&mut self) -> io::Result<Vec<f64>> {
+        let spec = self.spec;
+        let (bytes, width) = self.read_raw_le()?;
+        let big_endian = self.container == AudioContainer::Aiff;
+        let samples = bytes.chunks_exact(width).map(|le| {
+            match (spec.format_tag, width) {
+                (1, 1) if big_endian => le[0] as i8 as f64 / 128.0,
+                (1, 1) => (le[0] as f64 - 128.0) / 128.0,
+                (1, 2) => i16::from_le_bytes([le[0], le[1]]) as f64 / 32_768.0,
+                (1, 3) => (i32::from_le_bytes([0, le[0], le[1], le[2]]) >> 8) as f64 / 8_388_608.0,
+                (1, _) => i32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64 / 2_147_483_648.0,
+                (_, 4) => f32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+                _ => f64::from_le_bytes(le.try_into().unwrap()),
+            }
+        });
+        Ok(samples.collect())
+    }
+
+    // The data chunk with every sample in little-endian order, and the
+    // sample width; errors on encodings the readers can't decode
+    fn read_raw_le(&mut self) -> io::Result<(Vec<u8>, usize)> {
+        let spec = self.spec;
+        let width = spec.bits_per_sample as usize / 8;
+        let supported = match spec.format_tag {
+            1 => matches!(spec.bits_per_sample, 8 | 16 | 24 | 32),
+            3 => matches!(spec.bits_per_sample, 32 | 64),
+            _ => false,
+        };
+        if !supported {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported WAV encoding (format {}, {}-bit)", spec.format_tag, spec.bits_per_sample),
+            ));
+        }
+
+        let mut bytes = vec![0u8; self.data_size as usize / width * width];
+        self.file.read_exact(&mut bytes)?;
+        if self.container == AudioContainer::Aiff {
+            bytes.chunks_exact_mut(width).for_each(|b| b.reverse());
+        }
+        Ok((bytes, width))
+    }
+}
+
+/// Cut a 16-bit recording from `WavWriter` to its first `frames` frames,
+/// first overwriting the samples from `at_frame` on with `tail`. Only those
+/// bytes and the header are touched, so this works on files too large to
+/// load. The data must be the last chunk, as `WavWriter` writes it.
+pub fn rewrite_tail(path: &str, frames: u64, at_frame: u64, tail: &[i16]) -> io::Result<()> {
+    let reader = WavReader::open(path)?;
+    reader.check_16_bit()?;
+    let (container, data_offset) = (reader.container, reader.data_offset);
+    let block_align = reader.spec.channels * 2;
+    drop(reader);
+    // Files from elsewhere may have no JUNK reserve to turn into ds64 or back
+    let ds64_reserved = container == AudioContainer::Wav
+        && scan_layout(path)?.chunks.first().is_some_and(|chunk| {
+            chunk.offset == DS64_OFFSET + 8
+                && (&chunk.id == b"JUNK" || &chunk.id == b"ds64")
+                && chunk.size >= DS64_BODY_BYTES as u64
+        });
+
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let mut bytes = Vec::with_capacity(tail.len() * 2);
+    for &sample in tail {
+        match container {
+            AudioContainer::Wav => bytes.write_i16::<LittleEndian>(sample)?,
+            AudioContainer::Aiff => bytes.write_i16::<BigEndian>(sample)?,
+        }
+    }
+    file.seek(SeekFrom::Start(data_offset + at_frame * block_align as u64))?;
+    file.write_all(&bytes)?;
+    let data_size = frames * block_align as u64;
+    file.set_len(data_offset + data_size)?;
+    match container {
+        AudioContainer::Wav => write_wav_sizes(&mut file, data_offset - 4, data_size, block_align, ds64_reserved)?,
+        AudioContainer::Aiff => write_header_sizes(&mut file, container, data_offset, data_size, block_align)?,
+    }
+    file.sync_data()
+}
+
+/// One chunk as declared in the file, without any clamping
+#[derive(Debug, Clone)]
+pub struct ChunkInfo {
+    pub id: [u8; 4],
+    pub offset: u64, // Start of the chunk body
+    pub size: u64,   // Size claimed by the header (from ds64 for RF64 data)
+}
+
+/// Raw RIFF layout, for validating files rather than reading them
+#[derive(Debug, Clone)]
+pub struct RiffLayout {
+    pub file_len: u64,
+    pub riff_size: u64,
+    pub chunks: Vec<ChunkInfo>,
+    pub fmt: Option<WavSpec>,
+    pub byte_rate: u32,
+    pub block_align: u16,
+}
+
+/// Walk the chunk headers of a RIFF/WAVE (or RF64) file, stopping at the
+/// first chunk that runs past the end of the file
+pub fn scan_layout(path: &str) -> io::Result<RiffLayout> {
+    let file = std::fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)
+        .map_err(|_| invalid_data("File is too short for a RIFF header"))?;
+    if &magic != b"RIFF" && &magic != b"RF64" {
+        return Err(invalid_data("Missing RIFF header"));
+    }
+    let riff_size = file.read_u32::<LittleEndian>()? as u64;
+    file.read_exact(&mut magic)?;
+    if &magic != b"WAVE" {
+        return Err(invalid_data("Not a WAVE file"));
+    }
+
+    let mut layout = RiffLayout {
+        file_len,
+        riff_size,
+        chunks: Vec::new(),
+        fmt: None,
+        byte_rate: 0,
+        block_align: 0,
+    };
+    let mut ds64_data_size = None;
+    let mut pos = 12u64;
+    while pos + 8 <= file_len {
+        let mut id = [0u8; 4];
+        file.read_exact(&mut id)?;
+        let mut size = file.read_u32::<LittleEndian>()? as u64;
+        let offset = pos + 8;
+
+        if &id == b"ds64" && size >= 16 && offset + 16 <= file_len {
+            let riff_size = file.read_u64::<LittleEndian>()?;
+            ds64_data_size = Some(file.read_u64::<LittleEndian>()?);
+            if layout.riff_size == u32::MAX as u64 {
+                layout.riff_size = riff_size;
+            }
+        }
+        if &id == b"data" && size == u32::MAX as u64 {
+            size = ds64_data_size.unwrap_or(size);
+        }
+        layout.chunks.push(ChunkInfo { id, offset, size });
+
+        if &id == b"fmt " && size >= 16 && offset + 16 <= file_len {
+            let format_tag = file.read_u16::<LittleEndian>()?;
+            let channels = file.read_u16::<LittleEndian>()?;
+            let sample_rate = file.read_u32::<LittleEndian>()?;
+            layout.byte_rate = file.read_u32::<LittleEndian>()?;
+            layout.block_align = file.read_u16::<LittleEndian>()?;
+            let bits_per_sample = file.read_u16::<LittleEndian>()?;
+            layout.fmt = Some(WavSpec { format_tag, sample_rate, channels, bits_per_sample });
+        }
+
+        let next = offset + ((size + 1) & !1);
+        if next > file_len {
+            break;
+        }
+        file.seek(SeekFrom::Start(next))?;
+        pos = next;
+    }
+    Ok(layout)
+}
+
+/// Overwrite the sample rate and byte rate in the `fmt ` chunk in place,
+/// leaving every other byte alone. Returns the rate the header claimed.
+pub fn rewrite_sample_rate(path: &str, sample_rate: u32) -> io::Result<u32> {
+    let layout = scan_layout(path)?;
+    let spec = layout.fmt.ok_or_else(|| invalid_data("Missing fmt chunk"))?;
+    let fmt = layout
+        .chunks
+        .iter()
+        .find(|chunk| &chunk.id == b"fmt ")
+        .ok_or_else(|| invalid_data("Missing fmt chunk"))?;
+    let byte_rate = sample_rate
+        .checked_mul(layout.block_align as u32)
+        .ok_or_else(|| invalid_data("Sample rate doesn't fit a WAV header"))?;
+
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    // format tag and channels come first, then the two rate fields
+    file.seek(SeekFrom::Start(fmt.offset + 4))?;
+    file.write_u32::<LittleEndian>(sample_rate)?;
+    file.write_u32::<LittleEndian>(byte_rate)?;
+    file.sync_all()?;
+    Ok(spec.sample_rate)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A path in the temp dir unique to this test run
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("dpc-wav-test-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn aiff_round_trip() {
+        let path = temp_path("round-trip.aif");
+        let samples: Vec<i16> = (0..2000).map(|i| (i * 37 % 65536 - 32768) as i16).collect();
+        let mut writer = WavWriter::with_container(&path, 44100, 2, AudioContainer::Aiff).unwrap();
+        writer.write_samples(&samples).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.container(), AudioContainer::Aiff);
+        assert_eq!(reader.spec(), WavSpec { format_tag: 1, sample_rate: 44100, channels: 2, bits_per_sample: 16 });
+        assert_eq!(reader.read_samples().unwrap(), samples);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn extended_rate_round_trip() {
+        for rate in [1, 8000, 11025, 22050, 44100, 48000, 96000, 192000, 384000, u32::MAX] {
+            assert_eq!(extended_to_rate(&rate_to_extended(rate)), rate);
+        }
+        assert_eq!(rate_to_extended(0), [0; 10]);
+        assert_eq!(extended_to_rate(&[0; 10]), 0);
+        // 44.1 kHz as written by other tools
+        assert_eq!(rate_to_extended(44100), [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn info_chunk_round_trip() {
+        // Odd and even value lengths, so both pad cases are exercised
+        let metadata = WavMetadata {
+            title: Some("Voice note".to_string()),
+            artist: Some("Alice".to_string()),
+            comment: None,
+            date: Some("2024-05-01".to_string()),
+        };
+        let mut chunk = Vec::new();
+        write_info_chunk(&mut chunk, &metadata).unwrap();
+        assert_eq!(chunk.len() as u64, metadata.chunk_bytes());
+        assert_eq!(&chunk[..4], b"LIST");
+        assert_eq!(parse_info_chunk(&chunk[8..]), Some(metadata.clone()));
+        assert_eq!(parse_info_chunk(b"adtl"), None);
+
+        // And through a whole file, ahead of the data
+        let path = temp_path("info.wav");
+        let mut writer = WavWriter::with_metadata(&path, 48000, 1, AudioContainer::Wav, Some(&metadata)).unwrap();
+        writer.write_samples(&[1, -2, 3]).unwrap();
+        writer.finish().unwrap();
+        let mut reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.metadata(), Some(&metadata));
+        assert_eq!(reader.read_samples().unwrap(), vec![1, -2, 3]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn max_chunk_size_is_rejected_cleanly() {
+        // A chunk claiming u32::MAX bytes: the reader must seek past it, not
+        // wrap to 0 and misread the size field as the next chunk id
+        let mut bytes = b"RIFF\0\0\0\0WAVEJUNK".to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"fmt \x10\0\0\0");
+        let len = bytes.len() as u64;
+        assert!(WavReader::from_reader(io::Cursor::new(bytes), len).is_err());
+    }
+
+    #[test]
+    fn oversized_data_becomes_rf64() {
+        // The header of an empty stereo recording, as the writer lays it out
+        let path = temp_path("rf64.wav");
+        WavWriter::new(&path, 48000, 2).unwrap().finish().unwrap();
+        let mut header = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(&header[DS64_OFFSET as usize..DS64_OFFSET as usize + 4], b"JUNK");
+        let data_size_at = header.len() as u64 - 4;
+        assert_eq!(&header[data_size_at as usize - 4..data_size_at as usize], b"data");
+
+        // Just past what a 32-bit RIFF size can describe, in whole frames
+        let data_size = u32::MAX as u64 + 4;
+        let mut cursor = io::Cursor::new(&mut header);
+        write_wav_sizes(&mut cursor, data_size_at, data_size, 4, true).unwrap();
+
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let ds64 = DS64_OFFSET as usize;
+        assert_eq!(&header[0..4], b"RF64");
+        assert_eq!(u32_at(4), u32::MAX);
+        assert_eq!(&header[8..12], b"WAVE");
+        assert_eq!(&header[ds64..ds64 + 4], b"ds64");
+        assert_eq!(u32_at(ds64 + 4), DS64_BODY_BYTES);
+        assert_eq!(u64_at(ds64 + 8), data_size_at + 4 + data_size - 8); // RIFF size
+        assert_eq!(u64_at(ds64 + 16), data_size);
+        assert_eq!(u64_at(ds64 + 24), data_size / 4); // Sample frames
+        assert_eq!(u32_at(ds64 + 32), 0); // Table entries
+        assert_eq!(u32_at(data_size_at as usize), u32::MAX);
+
+        // The reader takes the data size from ds64 (the length stands in for
+        // the audio, which this test doesn't write)
+        let file_len = data_size_at + 4 + data_size;
+        let reader = WavReader::from_reader(io::Cursor::new(header), file_len).unwrap();
+        assert_eq!(reader.data_size(), data_size);
+        assert_eq!(reader.frames(), data_size / 4);
+        assert_eq!(reader.spec(), WavSpec { format_tag: 1, sample_rate: 48000, channels: 2, bits_per_sample: 16 });
+    }
+
+    #[test]
+    fn vorbis_comments_round_trip() {
+        let metadata = WavMetadata {
+            title: Some("Voice note".to_string()),
+            artist: Some("alice".to_string()),
+            comment: Some("a=b, with an equals sign".to_string()),
+            date: Some("2024-05-01".to_string()),
+        };
+        let comments = metadata.vorbis_comments();
+        assert_eq!(
+            comments,
+            ["TITLE=Voice note", "ARTIST=alice", "COMMENT=a=b, with an equals sign", "DATE=2024-05-01"]
+        );
+        assert_eq!(WavMetadata::from_vorbis_comments(&comments), metadata);
+
+        // Case-insensitive names; unknown fields and malformed entries are skipped
+        let decoded = WavMetadata::from_vorbis_comments(&["title=x", "DURATION=1.500", "no separator"]);
+        assert_eq!(decoded, WavMetadata { title: Some("x".to_string()), ..WavMetadata::default() });
+        assert!(WavMetadata::default().vorbis_comments().is_empty());
+    }
+
+    #[test]
+    fn overflowing_formats_are_refused_before_writing() {
+        // 4 GB/s doesn't fit the 32-bit byte rate, 32768 channels the 16-bit block align
+        assert!(frame_fields(u32::MAX / 2 + 1, 1, 2).is_err());
+        assert!(frame_fields(48000, 32768, 2).is_err());
+        assert_eq!(frame_fields(u32::MAX / 2, 1, 2).unwrap(), (u32::MAX - 1, 2));
+        assert_eq!(frame_fields(48000, 2, 2).unwrap(), (192000, 4));
+
+        for (rate, channels) in [(u32::MAX, 2), (48000, 40000)] {
+            let path = temp_path(&format!("overflow-{}-{}.wav", rate, channels));
+            let err = WavWriter::new(&path, rate, channels).err().expect("format should be refused");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+            assert!(!std::path::Path::new(&path).exists(), "a file was created for {} Hz x {}", rate, channels);
+        }
+    }
+
+    #[test]
+    fn trimmed_rf64_becomes_riff_again() {
+        let path = temp_path("trim-rf64.wav");
+        let samples: Vec<i16> = (0..4000).map(|i| (i * 7) as i16).collect();
+        let mut writer = WavWriter::new(&path, 48000, 2).unwrap();
+        writer.write_samples(&samples).unwrap();
+        writer.finish().unwrap();
+        let header_len = WavReader::open(&path).unwrap().data_offset;
+
+        // Mark it as grown past 4 GB, as a long recording would have
+        let mut file = std::fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        write_wav_sizes(&mut file, header_len - 4, u32::MAX as u64 + 4, 4, true).unwrap();
+        drop(file);
+        assert_eq!(&std::fs::read(&path).unwrap()[0..4], b"RF64");
+
+        rewrite_tail(&path, 1000, 900, &[0; 200]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let ds64 = DS64_OFFSET as usize;
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[ds64..ds64 + 4], b"JUNK");
+        assert_eq!(u32::from_le_bytes(bytes[ds64 + 4..ds64 + 8].try_into().unwrap()), DS64_BODY_BYTES);
+        let layout = scan_layout(&path).unwrap();
+        assert_eq!(layout.chunks.iter().find(|c| &c.id == b"data").unwrap().size, 4000);
+
+        let mut expected = samples[..2000].to_vec();
+        expected[1800..].fill(0);
+        assert_eq!(WavReader::open(&path).unwrap().read_samples().unwrap(), expected);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // A disk that fills up after `capacity` bytes
+    struct FullDisk {
+        data: io::Cursor<Vec<u8>>,
+        capacity: u64,
+    }
+
+    impl Write for FullDisk {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let room = self.capacity.saturating_sub(self.data.position()) as usize;
+            if room == 0 {
+                return Err(io::Error::other("No space left on device"));
+            }
+            self.data.write(&buf[..buf.len().min(room)])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FullDisk {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    // By reference, so the test can look at the disk after `finish_buffered`
+    impl RecoverableFile for &mut FullDisk {
+        fn file_len(&self) -> io::Result<u64> {
+            Ok(self.data.get_ref().len() as u64)
+        }
+
+        fn set_file_len(&mut self, len: u64) -> io::Result<()> {
+            self.data.get_mut().resize(len as usize, 0);
+            Ok(())
+        }
+
+        fn sync_file(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_flush_keeps_the_whole_frames_on_disk() {
+        // A real stereo header, then 10000 bytes of audio still in the buffer
+        let path = temp_path("full-disk.wav");
+        WavWriter::new(&path, 48000, 2).unwrap().finish().unwrap();
+        let header = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let header_len = header.len() as u64;
+        let samples: Vec<i16> = (0..5000).map(|i| i as i16 - 2500).collect();
+
+        // The disk fills one byte into the 251st frame
+        let mut disk = FullDisk { data: io::Cursor::new(Vec::new()), capacity: header_len + 1001 };
+        let mut file = BufWriter::with_capacity(64 * 1024, &mut disk);
+        file.write_all(&header).unwrap();
+        for sample in &samples {
+            file.write_i16::<LittleEndian>(*sample).unwrap();
+        }
+        let err = finish_buffered(file, AudioContainer::Wav, (header_len, samples.len() as u64 * 2, 4)).unwrap_err();
+        assert!(err.to_string().contains("closed with 1000 of 10000 bytes of audio"), "{}", err);
+
+        // Header-sized plus the 250 whole frames, with sizes that say so
+        let bytes = disk.data.into_inner();
+        assert_eq!(bytes.len() as u64, header_len + 1000);
+        let mut reader = WavReader::from_bytes(bytes).unwrap();
+        assert_eq!(reader.data_size(), 1000);
+        assert_eq!(reader.read_samples().unwrap(), samples[..500]);
+    }
+
+    #[test]
+    fn full_disk_before_the_header_is_reported() {
+        let mut disk = FullDisk { data: io::Cursor::new(Vec::new()), capacity: 20 };
+        let mut file = BufWriter::new(&mut disk);
+        file.write_all(&[0; 200]).unwrap();
+        let err = finish_buffered(file, AudioContainer::Wav, (80, 120, 4)).unwrap_err();
+        assert!(err.to_string().contains("could not be repaired"), "{}", err);
+    }
+}
@@ -60,10 +60,70 @@ fn main() {
             get_home_directory,
             get_ws_token,
             audio_recorder::tauri_start_recording,
+            audio_recorder::tauri_supported_formats,
+            audio_recorder::tauri_supported_containers,
+            audio_recorder::tauri_list_presets,
+            audio_recorder::tauri_save_preset,
+            audio_recorder::tauri_apply_preset,
             audio_recorder::tauri_stop_recording,
+            audio_recorder::tauri_request_stop_at,
+            audio_recorder::tauri_capture_noise_profile,
             audio_recorder::tauri_get_recording_status,
+            audio_recorder::tauri_get_recorder_snapshot,
+            audio_recorder::tauri_get_latency_estimate,
+            audio_recorder::tauri_get_level_history,
+            audio_recorder::tauri_get_remaining_time,
+            audio_recorder::tauri_flush_recording,
+            audio_recorder::tauri_set_recording_paused,
+            audio_recorder::tauri_set_recording_muted,
+            audio_recorder::tauri_list_recordings,
+            audio_recorder::tauri_mark_sent,
+            audio_recorder::tauri_mark_unsent,
+            audio_recorder::tauri_downmix_to_mono,
+            audio_recorder::tauri_concat_wavs,
+            audio_recorder::tauri_prepend_audio,
+            audio_recorder::tauri_apply_gain_envelope,
+            audio_recorder::tauri_extract_segment,
+            audio_recorder::tauri_auto_split_on_silence,
+            audio_recorder::tauri_ensure_format,
+            audio_recorder::tauri_fix_sample_rate,
+            audio_recorder::tauri_get_recording_data_url,
+            audio_recorder::tauri_normalize_wav,
+            audio_recorder::tauri_requantize_wav,
+            audio_recorder::tauri_transcode,
+            audio_recorder::tauri_compare_recordings,
+            audio_recorder::tauri_vorbis_comments,
+            audio_recorder::tauri_normalize_directory,
+            audio_recorder::tauri_fit_to_size,
+            audio_recorder::tauri_validate_wav,
+            audio_recorder::tauri_generate_waveform,
+            audio_recorder::tauri_fingerprint_wav,
+            audio_recorder::tauri_estimate_size,
+            audio_recorder::tauri_play_audio,
+            audio_recorder::tauri_play_encrypted,
+            audio_recorder::tauri_decrypt_recording,
+            audio_recorder::tauri_stop_playback,
+            audio_recorder::tauri_get_playback_level,
+            audio_recorder::tauri_set_event_target,
+            audio_recorder::tauri_measure_loopback_latency,
+            audio_recorder::tauri_list_input_devices,
+            audio_recorder::tauri_get_input_volume,
+            audio_recorder::tauri_set_input_volume,
+            audio_recorder::tauri_run_audio_self_test,
+            audio_recorder::tauri_test_input_device,
+            audio_recorder::tauri_compare_devices,
+            audio_recorder::tauri_measure_noise_floor,
+            audio_recorder::tauri_set_monitoring,
+            audio_recorder::tauri_set_monitor_pan,
+            audio_recorder::tauri_duck_monitor,
+            audio_recorder::tauri_start_live_pcm,
+            audio_recorder::tauri_stop_live_pcm,
+            audio_recorder::tauri_render_spectrogram,
         ])
+        .on_window_event(audio_recorder::on_window_event)
         .setup(|app| {
+            audio_recorder::init(app.handle().clone());
+
             #[cfg(debug_assertions)]
             {
                 // Now that the `Manager` trait is in scope, this call will work.